@@ -0,0 +1,6 @@
+CREATE TABLE "sensor_status" (
+    "host" TEXT PRIMARY KEY,
+    "last_seen" INTEGER NULL,
+    "last_error" TEXT NULL,
+    "failures" UINT NOT NULL DEFAULT 0
+);
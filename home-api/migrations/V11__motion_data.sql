@@ -0,0 +1,6 @@
+CREATE TABLE "sensor_motion_data" (
+    "host" TEXT NOT NULL,
+    "start" INTEGER NOT NULL,
+    "end" INTEGER NULL,
+    PRIMARY KEY ("host", "start")
+);
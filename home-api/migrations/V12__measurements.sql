@@ -0,0 +1,18 @@
+CREATE TABLE "sensor_measurements" (
+    "host" TEXT NOT NULL,
+    "feature" UINT NOT NULL,
+    "metric" TEXT NOT NULL,
+    "timestamp" UINT NOT NULL,
+    "value" FLOAT NOT NULL,
+    PRIMARY KEY ("host", "feature", "metric", "timestamp")
+);
+
+CREATE INDEX "sensor_measurements_feature_timestamp" ON "sensor_measurements" ("feature", "timestamp");
+
+INSERT INTO "sensor_measurements" ("host", "feature", "metric", "timestamp", "value")
+SELECT "host", 1, 'temperature', "timestamp", "temperature" FROM "sensor_temp_data";
+
+INSERT INTO "sensor_measurements" ("host", "feature", "metric", "timestamp", "value")
+SELECT "host", 1, 'humidity', "timestamp", "humidity" FROM "sensor_temp_data";
+
+DROP TABLE "sensor_temp_data";
@@ -0,0 +1,27 @@
+ALTER TABLE "data_schedule" RENAME TO "data_schedule_old";
+
+CREATE TABLE "data_schedule" (
+    "rowid" INTEGER PRIMARY KEY,
+    "features" UINT NOT NULL,
+    "interval_ms" UINT NOT NULL,
+    "host" TEXT NULL,
+    "area_id" INTEGER NULL
+);
+
+CREATE UNIQUE INDEX "data_schedule_target" ON "data_schedule" ("features", IFNULL("host", ''), IFNULL("area_id", 0));
+
+INSERT INTO "data_schedule" ("features", "interval_ms")
+SELECT "features", "interval_ms" FROM "data_schedule_old";
+
+DROP TABLE "data_schedule_old";
+
+-- rules of removed sensors and areas have nothing left to apply to
+CREATE TRIGGER "data_schedule_sensor_deleted" AFTER DELETE ON "sensors"
+BEGIN
+    DELETE FROM "data_schedule" WHERE "host" = OLD."host";
+END;
+
+CREATE TRIGGER "data_schedule_area_deleted" AFTER DELETE ON "areas"
+BEGIN
+    DELETE FROM "data_schedule" WHERE "area_id" = OLD."rowid";
+END;
@@ -0,0 +1,14 @@
+ALTER TABLE "data_schedule" ADD COLUMN "offset_ms" UINT NOT NULL DEFAULT 0;
+ALTER TABLE "data_schedule" ADD COLUMN "cron" TEXT NULL;
+ALTER TABLE "data_schedule" ADD COLUMN "active_from" UINT NULL;
+ALTER TABLE "data_schedule" ADD COLUMN "active_to" UINT NULL;
+
+-- rules of the same target may differ by their active hours
+DROP INDEX "data_schedule_target";
+CREATE UNIQUE INDEX "data_schedule_target" ON "data_schedule" (
+    "features",
+    IFNULL("host", ''),
+    IFNULL("area_id", 0),
+    IFNULL("active_from", -1),
+    IFNULL("active_to", -1)
+);
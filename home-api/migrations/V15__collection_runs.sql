@@ -0,0 +1,13 @@
+CREATE TABLE "collection_runs" (
+    "rowid" INTEGER PRIMARY KEY,
+    "schedule_id" INTEGER NULL,
+    "features" UINT NOT NULL,
+    "host" TEXT NULL,
+    "started" INTEGER NOT NULL,
+    "duration_ms" INTEGER NOT NULL,
+    "sensors" UINT NOT NULL,
+    "rows" UINT NOT NULL,
+    "errors" TEXT NOT NULL
+);
+
+CREATE INDEX "collection_runs_schedule" ON "collection_runs" ("schedule_id", "started");
@@ -0,0 +1,7 @@
+CREATE TABLE "measurement_gaps" (
+    "host" TEXT NOT NULL,
+    "feature" UINT NOT NULL,
+    "start" UINT NOT NULL,
+    "end" UINT NOT NULL,
+    PRIMARY KEY ("host", "feature", "start")
+);
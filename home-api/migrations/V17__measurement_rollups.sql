@@ -0,0 +1,22 @@
+CREATE TABLE "measurement_rollups" (
+    "host" TEXT NOT NULL,
+    "feature" UINT NOT NULL,
+    "metric" TEXT NOT NULL,
+    "resolution" UINT NOT NULL,
+    "timestamp" UINT NOT NULL,
+    "min" FLOAT NOT NULL,
+    "max" FLOAT NOT NULL,
+    "avg" FLOAT NOT NULL,
+    "count" UINT NOT NULL,
+    PRIMARY KEY ("host", "feature", "metric", "resolution", "timestamp")
+);
+
+CREATE INDEX "measurement_rollups_feature_timestamp" ON "measurement_rollups" ("feature", "resolution", "timestamp");
+
+CREATE TABLE "retention_policy" (
+    "raw_days" UINT NOT NULL,
+    "hourly_days" UINT NOT NULL,
+    "daily_days" UINT NULL
+);
+
+INSERT INTO "retention_policy" ("raw_days", "hourly_days", "daily_days") VALUES (30, 365, NULL);
@@ -0,0 +1,49 @@
+CREATE TABLE "alert_rules" (
+    "rowid" INTEGER PRIMARY KEY,
+    "name" TEXT NOT NULL,
+    "condition" TEXT NOT NULL,
+    "feature" UINT NULL,
+    "metric" TEXT NULL,
+    "threshold" FLOAT NULL,
+    "duration_s" UINT NOT NULL,
+    "hysteresis" FLOAT NOT NULL,
+    "cooldown_s" UINT NOT NULL,
+    "host" TEXT NULL,
+    "area_id" INTEGER NULL
+);
+
+CREATE TABLE "alert_state" (
+    "rule_id" INTEGER NOT NULL,
+    "host" TEXT NOT NULL,
+    "pending_since" INTEGER NOT NULL,
+    PRIMARY KEY ("rule_id", "host")
+);
+
+CREATE TABLE "alerts" (
+    "rowid" INTEGER PRIMARY KEY,
+    "rule_id" INTEGER NOT NULL,
+    "host" TEXT NOT NULL,
+    "started" INTEGER NOT NULL,
+    "resolved" INTEGER NULL,
+    "value" FLOAT NULL,
+    "message" TEXT NOT NULL
+);
+
+CREATE INDEX "alerts_rule_host" ON "alerts" ("rule_id", "host", "started");
+
+CREATE TRIGGER "alert_rules_sensor_deleted" AFTER DELETE ON "sensors"
+BEGIN
+    DELETE FROM "alert_rules" WHERE "host" = OLD."host";
+    DELETE FROM "alert_state" WHERE "host" = OLD."host";
+END;
+
+CREATE TRIGGER "alert_rules_area_deleted" AFTER DELETE ON "areas"
+BEGIN
+    DELETE FROM "alert_rules" WHERE "area_id" = OLD."rowid";
+END;
+
+CREATE TRIGGER "alert_rules_deleted" AFTER DELETE ON "alert_rules"
+BEGIN
+    DELETE FROM "alert_state" WHERE "rule_id" = OLD."rowid";
+    UPDATE "alerts" SET "resolved" = CAST(strftime('%s', 'now') AS INTEGER) WHERE "rule_id" = OLD."rowid" AND "resolved" IS NULL;
+END;
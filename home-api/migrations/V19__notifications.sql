@@ -0,0 +1,44 @@
+CREATE TABLE "notification_channels" (
+    "rowid" INTEGER PRIMARY KEY,
+    "name" TEXT NOT NULL,
+    "kind" TEXT NOT NULL,
+    "url" TEXT NOT NULL,
+    "credentials" TEXT NULL,
+    "sender" TEXT NULL,
+    "template" TEXT NOT NULL
+);
+
+CREATE TABLE "notification_subscriptions" (
+    "user_id" INTEGER NOT NULL,
+    "channel_id" INTEGER NOT NULL,
+    "topics" UINT NOT NULL,
+    "address" TEXT NULL,
+    PRIMARY KEY ("user_id", "channel_id")
+);
+
+CREATE TABLE "notification_deliveries" (
+    "rowid" INTEGER PRIMARY KEY,
+    "channel_id" INTEGER NOT NULL,
+    "recipient" TEXT NULL,
+    "topic" UINT NOT NULL,
+    "title" TEXT NOT NULL,
+    "body" TEXT NOT NULL,
+    "created" INTEGER NOT NULL,
+    "status" UINT NOT NULL,
+    "attempts" UINT NOT NULL,
+    "next_attempt" INTEGER NULL,
+    "error" TEXT NULL
+);
+
+CREATE INDEX "notification_deliveries_due" ON "notification_deliveries" ("status", "next_attempt");
+
+CREATE TRIGGER "notification_channels_deleted" AFTER DELETE ON "notification_channels"
+BEGIN
+    DELETE FROM "notification_subscriptions" WHERE "channel_id" = OLD."rowid";
+    DELETE FROM "notification_deliveries" WHERE "channel_id" = OLD."rowid";
+END;
+
+CREATE TRIGGER "notification_users_deleted" AFTER DELETE ON "users"
+BEGIN
+    DELETE FROM "notification_subscriptions" WHERE "user_id" = OLD."rowid";
+END;
@@ -0,0 +1,15 @@
+CREATE TABLE "sensor_calibration" (
+    "host" TEXT NOT NULL,
+    "feature" UINT NOT NULL,
+    "metric" TEXT NOT NULL,
+    "offset" FLOAT NOT NULL,
+    "scale" FLOAT NOT NULL,
+    PRIMARY KEY ("host", "feature", "metric")
+);
+
+CREATE TRIGGER "sensor_calibration_sensor_deleted" AFTER DELETE ON "sensors"
+BEGIN
+    DELETE FROM "sensor_calibration" WHERE "host" = OLD."host";
+END;
+
+ALTER TABLE "users" ADD COLUMN "temperature_unit" TEXT NOT NULL DEFAULT 'c';
@@ -0,0 +1,15 @@
+ALTER TABLE "sensor_measurements" ADD COLUMN "flag" TEXT NULL;
+
+CREATE TABLE "measurement_validation" (
+    "feature" UINT NOT NULL,
+    "metric" TEXT NOT NULL,
+    "min" FLOAT NULL,
+    "max" FLOAT NULL,
+    "max_rate" FLOAT NULL,
+    "median_window" UINT NOT NULL,
+    "max_deviation" FLOAT NULL,
+    PRIMARY KEY ("feature", "metric")
+);
+
+INSERT INTO "measurement_validation" ("feature", "metric", "min", "max", "max_rate", "median_window", "max_deviation")
+VALUES (1, 'temperature', -40, 80, 0.5, 5, 5), (1, 'humidity', 0, 100, 2, 5, 15);
@@ -0,0 +1,6 @@
+UPDATE "alerts" SET "resolved" = "started"
+WHERE "resolved" IS NULL AND "rowid" NOT IN (
+    SELECT MIN("rowid") FROM "alerts" WHERE "resolved" IS NULL GROUP BY "rule_id", "host"
+);
+
+CREATE UNIQUE INDEX "alerts_active" ON "alerts" ("rule_id", "host") WHERE "resolved" IS NULL;
@@ -0,0 +1,29 @@
+CREATE TABLE "scan_history" (
+    "rowid" INTEGER PRIMARY KEY,
+    "created" UINT NOT NULL,
+    "duration_ms" UINT NOT NULL,
+    "target" TEXT NOT NULL,
+    "found" UINT NOT NULL
+);
+
+CREATE TABLE "scan_devices" (
+    "scan_id" INTEGER NOT NULL,
+    "host" TEXT NOT NULL,
+    "name" TEXT NOT NULL,
+    "features" UINT NOT NULL,
+    FOREIGN KEY ("scan_id") REFERENCES "scan_history" ("rowid") ON DELETE CASCADE
+);
+
+CREATE TABLE "scan_changes" (
+    "scan_id" INTEGER NOT NULL,
+    "kind" UINT NOT NULL,
+    "host" TEXT NOT NULL,
+    "name" TEXT NOT NULL,
+    "previous_host" TEXT NULL,
+    "seen" BOOLEAN NOT NULL DEFAULT FALSE,
+    FOREIGN KEY ("scan_id") REFERENCES "scan_history" ("rowid") ON DELETE CASCADE
+);
+
+CREATE TABLE "scanner_schedule" (
+    "interval_ms" UINT NOT NULL
+);
@@ -0,0 +1,14 @@
+CREATE TABLE "sensor_health" (
+    "host" TEXT NOT NULL,
+    "timestamp" INTEGER NOT NULL,
+    "free_mem" UINT NOT NULL,
+    "uptime" REAL NOT NULL,
+    "paired_keys" UINT NOT NULL,
+    "data_used" UINT NOT NULL,
+    "data_total" UINT NOT NULL,
+    "pair_used" UINT NOT NULL,
+    "pair_total" UINT NOT NULL,
+    "rebooted" BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX "sensor_health_host_timestamp" ON "sensor_health" ("host", "timestamp");
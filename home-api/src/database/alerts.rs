@@ -0,0 +1,208 @@
+use super::{quote, Database, DbConn};
+use crate::models::db::{Alert, AlertCondition, AlertRule, ScheduleTarget};
+
+const HISTORY_SIZE: usize = 1000;
+
+pub trait AlertDatabase {
+    async fn get_alert_rules(&self) -> Result<Vec<AlertRule>, anyhow::Error>;
+    async fn create_alert_rule(&self, rule: AlertRule) -> Result<Option<AlertRule>, anyhow::Error>;
+    async fn delete_alert_rule(&self, id: i64) -> Result<bool, anyhow::Error>;
+    /// Since when the condition of `rule_id` holds for `host` without having fired yet.
+    async fn get_pending_since(
+        &self,
+        rule_id: i64,
+        host: &str,
+    ) -> Result<Option<i64>, anyhow::Error>;
+    async fn set_pending_since(
+        &self,
+        rule_id: i64,
+        host: &str,
+        since: Option<i64>,
+    ) -> Result<(), anyhow::Error>;
+    /// The most recent alert of `rule_id` for `host`, firing or not.
+    async fn get_last_alert(
+        &self,
+        rule_id: i64,
+        host: &str,
+    ) -> Result<Option<Alert>, anyhow::Error>;
+    /// Opens `alert`, `None` if its rule fires for the host already.
+    async fn create_alert(&self, alert: Alert) -> Result<Option<Alert>, anyhow::Error>;
+    /// Resolves the alert `id`, `false` if it was resolved already.
+    async fn resolve_alert(&self, id: i64, at: i64) -> Result<bool, anyhow::Error>;
+    async fn get_active_alerts(&self) -> Result<Vec<Alert>, anyhow::Error>;
+    async fn get_resolved_alerts(&self, limit: usize) -> Result<Vec<Alert>, anyhow::Error>;
+}
+
+impl AlertDatabase for DbConn {
+    async fn get_alert_rules(&self) -> Result<Vec<AlertRule>, anyhow::Error> {
+        self.query::<AlertRule>("SELECT * FROM alert_rules")
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn create_alert_rule(&self, rule: AlertRule) -> Result<Option<AlertRule>, anyhow::Error> {
+        let (host, area_id) = match &rule.target {
+            ScheduleTarget::All => ("NULL".to_string(), "NULL".to_string()),
+            ScheduleTarget::Area(id) => ("NULL".to_string(), id.to_string()),
+            ScheduleTarget::Sensor(host) => (quote(host), "NULL".to_string()),
+        };
+        let (condition, feature, metric, threshold) = match &rule.condition {
+            AlertCondition::Above {
+                feature,
+                metric,
+                threshold,
+            } => (
+                "above",
+                feature.bits().to_string(),
+                quote(metric),
+                threshold.to_string(),
+            ),
+            AlertCondition::Below {
+                feature,
+                metric,
+                threshold,
+            } => (
+                "below",
+                feature.bits().to_string(),
+                quote(metric),
+                threshold.to_string(),
+            ),
+            AlertCondition::Offline => (
+                "offline",
+                "NULL".to_string(),
+                "NULL".to_string(),
+                "NULL".to_string(),
+            ),
+        };
+        self.query_single::<AlertRule>(&format!(
+            "INSERT INTO alert_rules (name, condition, feature, metric, threshold, duration_s, hysteresis, cooldown_s, host, area_id)\n\
+                VALUES ({}, '{}', {}, {}, {}, {}, {}, {}, {}, {})\n\
+                RETURNING *",
+            quote(&rule.name),
+            condition,
+            feature,
+            metric,
+            threshold,
+            rule.duration_s,
+            rule.hysteresis,
+            rule.cooldown_s,
+            host,
+            area_id
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn delete_alert_rule(&self, id: i64) -> Result<bool, anyhow::Error> {
+        Ok(self
+            .execute(&format!("DELETE FROM alert_rules WHERE rowid = {}", id))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?
+            > 0)
+    }
+
+    async fn get_pending_since(
+        &self,
+        rule_id: i64,
+        host: &str,
+    ) -> Result<Option<i64>, anyhow::Error> {
+        self.query_single::<u64>(&format!(
+            "SELECT pending_since FROM alert_state WHERE rule_id = {} AND host = {}",
+            rule_id,
+            quote(host)
+        ))
+        .await
+        .map(|since| since.map(|s| s as i64))
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn set_pending_since(
+        &self,
+        rule_id: i64,
+        host: &str,
+        since: Option<i64>,
+    ) -> Result<(), anyhow::Error> {
+        let query = match since {
+            Some(since) => format!(
+                "INSERT INTO alert_state (rule_id, host, pending_since) VALUES ({}, {}, {})\n\
+                    ON CONFLICT(rule_id, host) DO UPDATE SET pending_since = excluded.pending_since",
+                rule_id,
+                quote(host),
+                since
+            ),
+            None => format!(
+                "DELETE FROM alert_state WHERE rule_id = {} AND host = {}",
+                rule_id,
+                quote(host)
+            ),
+        };
+        self.execute(&query)
+            .await
+            .map(|_| ())
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_last_alert(
+        &self,
+        rule_id: i64,
+        host: &str,
+    ) -> Result<Option<Alert>, anyhow::Error> {
+        self.query_single::<Alert>(&format!(
+            "SELECT * FROM alerts WHERE rule_id = {} AND host = {} ORDER BY started DESC LIMIT 1",
+            rule_id,
+            quote(host)
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn create_alert(&self, alert: Alert) -> Result<Option<Alert>, anyhow::Error> {
+        let alert = self
+            .query_single::<Alert>(&format!(
+                "INSERT INTO alerts (rule_id, host, started, resolved, value, message)\n\
+                    VALUES ({}, {}, {}, NULL, {}, {})\n\
+                    ON CONFLICT DO NOTHING\n\
+                    RETURNING *",
+                alert.rule_id,
+                quote(&alert.host),
+                alert.started,
+                alert.value.map_or("NULL".to_string(), |v| v.to_string()),
+                quote(&alert.message)
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        // keep only the most recent alerts
+        self.execute(&format!(
+            "DELETE FROM alerts WHERE rowid IN (SELECT rowid FROM alerts WHERE resolved IS NOT NULL ORDER BY started DESC LIMIT -1 OFFSET {})",
+            HISTORY_SIZE
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+        Ok(alert)
+    }
+
+    async fn resolve_alert(&self, id: i64, at: i64) -> Result<bool, anyhow::Error> {
+        self.execute(&format!(
+            "UPDATE alerts SET resolved = {} WHERE rowid = {} AND resolved IS NULL",
+            at, id
+        ))
+        .await
+        .map(|changed| changed > 0)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_active_alerts(&self) -> Result<Vec<Alert>, anyhow::Error> {
+        self.query::<Alert>("SELECT * FROM alerts WHERE resolved IS NULL ORDER BY started DESC")
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_resolved_alerts(&self, limit: usize) -> Result<Vec<Alert>, anyhow::Error> {
+        self.query::<Alert>(&format!(
+            "SELECT * FROM alerts WHERE resolved IS NOT NULL ORDER BY resolved DESC LIMIT {}",
+            limit
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+}
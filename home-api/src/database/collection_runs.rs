@@ -0,0 +1,55 @@
+use super::{quote, quote_or_null, Database, DbConn};
+use crate::models::db::CollectionRun;
+
+const HISTORY_SIZE: usize = 1000;
+
+pub trait CollectionRunDatabase {
+    async fn create_collection_run(&self, run: CollectionRun) -> Result<(), anyhow::Error>;
+    async fn get_collection_runs(&self, limit: usize) -> Result<Vec<CollectionRun>, anyhow::Error>;
+    async fn get_last_scheduled_runs(&self) -> Result<Vec<CollectionRun>, anyhow::Error>;
+}
+
+impl CollectionRunDatabase for DbConn {
+    async fn create_collection_run(&self, run: CollectionRun) -> Result<(), anyhow::Error> {
+        self.execute(&format!(
+            "INSERT INTO collection_runs (schedule_id, features, host, started, duration_ms, sensors, rows, errors)\n\
+                VALUES ({}, {}, {}, {}, {}, {}, {}, {})",
+            run.schedule_id.map_or("NULL".to_string(), |id| id.to_string()),
+            run.features.bits(),
+            quote_or_null(run.host.as_deref()),
+            run.started,
+            run.duration_ms,
+            run.sensors,
+            run.rows,
+            quote(&run.errors.join("\n"))
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+        // keep only the most recent runs
+        self.execute(&format!(
+            "DELETE FROM collection_runs WHERE rowid IN (SELECT rowid FROM collection_runs ORDER BY started DESC LIMIT -1 OFFSET {})",
+            HISTORY_SIZE
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+        Ok(())
+    }
+
+    async fn get_collection_runs(&self, limit: usize) -> Result<Vec<CollectionRun>, anyhow::Error> {
+        self.query::<CollectionRun>(&format!(
+            "SELECT * FROM collection_runs ORDER BY started DESC LIMIT {}",
+            limit
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_last_scheduled_runs(&self) -> Result<Vec<CollectionRun>, anyhow::Error> {
+        self.query::<CollectionRun>(
+            "SELECT * FROM collection_runs AS r WHERE schedule_id IS NOT NULL\n\
+                AND started = (SELECT MAX(started) FROM collection_runs WHERE schedule_id = r.schedule_id)",
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+}
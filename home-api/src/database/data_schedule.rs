@@ -1,5 +1,5 @@
-use super::{Database, DbConn};
-use crate::models::db::{DataSchedule, DataScheduleEntry};
+use super::{quote, Database, DbConn};
+use crate::models::db::{DataSchedule, DataScheduleEntry, ScheduleTarget, ScheduleTrigger};
 
 pub trait DataScheduleDatabase {
     async fn create_entry(
@@ -7,7 +7,7 @@ pub trait DataScheduleDatabase {
         entry: DataScheduleEntry,
     ) -> Result<Option<DataScheduleEntry>, anyhow::Error>;
     async fn get_schedule(&self) -> Result<DataSchedule, anyhow::Error>;
-    async fn delete_entry(&self, entry: DataScheduleEntry) -> Result<bool, anyhow::Error>;
+    async fn delete_entry(&self, id: i64) -> Result<bool, anyhow::Error>;
 }
 
 impl DataScheduleDatabase for DbConn {
@@ -15,16 +15,39 @@ impl DataScheduleDatabase for DbConn {
         &self,
         entry: DataScheduleEntry,
     ) -> Result<Option<DataScheduleEntry>, anyhow::Error> {
+        let (host, area_id) = match &entry.target {
+            ScheduleTarget::All => ("NULL".to_string(), "NULL".to_string()),
+            ScheduleTarget::Area(id) => ("NULL".to_string(), id.to_string()),
+            ScheduleTarget::Sensor(host) => (quote(host), "NULL".to_string()),
+        };
+        let (interval_ms, offset_ms, cron) = match &entry.trigger {
+            ScheduleTrigger::Interval {
+                interval_ms,
+                offset_ms,
+            } => (*interval_ms, *offset_ms, "NULL".to_string()),
+            ScheduleTrigger::Cron(expression) => (0, 0, quote(expression)),
+        };
+        let (active_from, active_to) = entry
+            .active_hours
+            .map_or(("NULL".to_string(), "NULL".to_string()), |h| {
+                (h.from.to_string(), h.to.to_string())
+            });
         self.query_single::<DataScheduleEntry>(&format!(
-            "INSERT INTO data_schedule (features, interval_ms)\n\
-                VALUES ('{}', '{}')\n\
-                ON CONFLICT(features)\n\
+            "INSERT INTO data_schedule (features, interval_ms, host, area_id, offset_ms, cron, active_from, active_to)\n\
+                VALUES ('{}', '{}', {}, {}, {}, {}, {}, {})\n\
+                ON CONFLICT(features, IFNULL(host, ''), IFNULL(area_id, 0), IFNULL(active_from, -1), IFNULL(active_to, -1))\n\
                 DO\n\
-                  UPDATE SET interval_ms = excluded.interval_ms\n\
-                  WHERE interval_ms != excluded.interval_ms\n\
+                  UPDATE SET interval_ms = excluded.interval_ms, offset_ms = excluded.offset_ms, cron = excluded.cron\n\
+                  WHERE interval_ms != excluded.interval_ms OR offset_ms != excluded.offset_ms OR cron IS NOT excluded.cron\n\
                   RETURNING *",
             entry.features.bits(),
-            entry.interval_ms
+            interval_ms,
+            host,
+            area_id,
+            offset_ms,
+            cron,
+            active_from,
+            active_to
         ))
         .await
         .map_err(|e| anyhow::anyhow!("{}", e))
@@ -36,13 +59,9 @@ impl DataScheduleDatabase for DbConn {
             .map_err(|e| anyhow::anyhow!("{}", e))
     }
 
-    async fn delete_entry(&self, entry: DataScheduleEntry) -> Result<bool, anyhow::Error> {
+    async fn delete_entry(&self, id: i64) -> Result<bool, anyhow::Error> {
         Ok(self
-            .execute(&format!(
-                "DELETE FROM data_schedule WHERE features = '{}' AND interval_ms = '{}'",
-                entry.features.bits(),
-                entry.interval_ms
-            ))
+            .execute(&format!("DELETE FROM data_schedule WHERE rowid = {}", id))
             .await
             .map_err(|e| anyhow::anyhow!("{}", e))?
             > 0)
@@ -0,0 +1,266 @@
+use super::{measurements::CALIBRATED_MEASUREMENTS, quote_list, Database, DbConn};
+use crate::{
+    features::FeatureInfo,
+    models::db::{ChartBucket, CompactionResult, Resolution, RetentionPolicy},
+};
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+/// Rollups of the last days are always rebuilt, covering readings backfilled from the sensor buffers.
+const REFRESH_SECS: u64 = 2 * DAY_SECS;
+
+pub trait MeasurementRollupDatabase {
+    async fn get_retention_policy(&self) -> Result<RetentionPolicy, anyhow::Error>;
+    async fn set_retention_policy(&self, policy: RetentionPolicy) -> Result<(), anyhow::Error>;
+    /// Readings of `feature` within `[from, to)` aggregated into buckets of `bucket` seconds,
+    /// read from the rows stored at `resolution`, oldest first.
+    /// Flagged readings are only included from raw rows, the rollups always leave them out.
+    async fn get_chart_buckets(
+        &self,
+        feature: &FeatureInfo,
+        host: Vec<String>,
+        resolution: Resolution,
+        bucket: u64,
+        range: (i64, i64),
+        flagged: bool,
+    ) -> Result<Vec<ChartBucket>, anyhow::Error>;
+    /// Number of stored rows at `resolution`.
+    async fn count_measurements(&self, resolution: Resolution) -> Result<u64, anyhow::Error>;
+    /// Rebuilds the hourly and daily rollups of the days from `since` on,
+    /// returning the number of hourly and daily rows written.
+    async fn refresh_rollups(&self, since: u64) -> Result<(usize, usize), anyhow::Error>;
+    /// Adds readings stored from `since` on to the rollups. Buckets past the raw retention of
+    /// `policy` lost the rest of their source, the readings there are merged into them weighted
+    /// by count and dropped, the newer buckets are rebuilt.
+    /// Expects the measurements compacted beforehand, so the old readings are all new ones.
+    async fn import_rollups(
+        &self,
+        since: u64,
+        policy: RetentionPolicy,
+        now: u64,
+    ) -> Result<(usize, usize), anyhow::Error>;
+    /// Rolls raw readings up into hourly and daily buckets and drops everything past `policy`.
+    async fn compact_measurements(
+        &self,
+        policy: RetentionPolicy,
+        now: u64,
+    ) -> Result<CompactionResult, anyhow::Error>;
+}
+
+impl MeasurementRollupDatabase for DbConn {
+    async fn get_retention_policy(&self) -> Result<RetentionPolicy, anyhow::Error> {
+        self.query_single::<RetentionPolicy>(
+            "SELECT raw_days, hourly_days, daily_days FROM retention_policy LIMIT 1",
+        )
+        .await
+        .map(Option::unwrap_or_default)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn set_retention_policy(&self, policy: RetentionPolicy) -> Result<(), anyhow::Error> {
+        self.execute("DELETE FROM retention_policy")
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        self.execute(&format!(
+            "INSERT INTO retention_policy (raw_days, hourly_days, daily_days) VALUES ({}, {}, {})",
+            policy.raw_days,
+            policy.hourly_days,
+            policy
+                .daily_days
+                .map_or("NULL".to_string(), |d| d.to_string())
+        ))
+        .await
+        .map(|_| ())
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_chart_buckets(
+        &self,
+        feature: &FeatureInfo,
+        host: Vec<String>,
+        resolution: Resolution,
+        bucket: u64,
+        (from, to): (i64, i64),
+        flagged: bool,
+    ) -> Result<Vec<ChartBucket>, anyhow::Error> {
+        let mut query = format!(
+            "SELECT host, metric, timestamp / {0} * {0} AS bucket, ",
+            bucket
+        );
+        match resolution.seconds() {
+            None => query.push_str(&format!(
+                "MIN(value), AVG(value), MAX(value) FROM {} WHERE feature = {}{}",
+                CALIBRATED_MEASUREMENTS,
+                feature.feature.bits(),
+                if flagged { "" } else { " AND flag IS NULL" }
+            )),
+            // rollups of different lengths are weighted by their number of readings,
+            // the calibration is linear so it applies to the aggregates as it does to the readings
+            Some(seconds) => query.push_str(&format!(
+                "MIN(min), SUM(avg * count) / SUM(count), MAX(max) FROM \
+                    (SELECT r.host, r.feature, r.metric, r.resolution, r.timestamp, r.count, \
+                        r.min * COALESCE(c.scale, 1) + COALESCE(c.\"offset\", 0) AS min, \
+                        r.avg * COALESCE(c.scale, 1) + COALESCE(c.\"offset\", 0) AS avg, \
+                        r.max * COALESCE(c.scale, 1) + COALESCE(c.\"offset\", 0) AS max \
+                    FROM measurement_rollups AS r LEFT JOIN sensor_calibration AS c \
+                        ON c.host = r.host AND c.feature = r.feature AND c.metric = r.metric) \
+                    WHERE feature = {} AND resolution = {}",
+                feature.feature.bits(),
+                seconds
+            )),
+        }
+        query.push_str(&format!(
+            " AND host IN ({}) AND timestamp >= {} AND timestamp < {} \
+                GROUP BY host, metric, bucket ORDER BY bucket, host, metric",
+            quote_list(&host),
+            from,
+            to
+        ));
+
+        self.query::<ChartBucket>(&query)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn count_measurements(&self, resolution: Resolution) -> Result<u64, anyhow::Error> {
+        let query = match resolution.seconds() {
+            None => "SELECT COUNT(*) FROM sensor_measurements".to_string(),
+            Some(seconds) => format!(
+                "SELECT COUNT(*) FROM measurement_rollups WHERE resolution = {}",
+                seconds
+            ),
+        };
+        self.query_single::<u64>(&query)
+            .await
+            .map(Option::unwrap_or_default)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn refresh_rollups(&self, since: u64) -> Result<(usize, usize), anyhow::Error> {
+        let hour = Resolution::Hourly.seconds().unwrap();
+        let day = Resolution::Daily.seconds().unwrap();
+        let since = since / day * day;
+        let hourly = self
+            .execute(&format!(
+                "INSERT INTO measurement_rollups (host, feature, metric, resolution, timestamp, min, max, avg, count)\n\
+                    SELECT host, feature, metric, {hour}, timestamp / {hour} * {hour} AS bucket, MIN(value), MAX(value), AVG(value), COUNT(*)\n\
+                    FROM sensor_measurements WHERE timestamp >= {since} AND flag IS NULL\n\
+                    GROUP BY host, feature, metric, bucket\n\
+                ON CONFLICT(host, feature, metric, resolution, timestamp) DO UPDATE SET\n\
+                    min = excluded.min, max = excluded.max, avg = excluded.avg, count = excluded.count",
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let daily = self
+            .execute(&format!(
+                "INSERT INTO measurement_rollups (host, feature, metric, resolution, timestamp, min, max, avg, count)\n\
+                    SELECT host, feature, metric, {day}, timestamp / {day} * {day} AS bucket, MIN(min), MAX(max), SUM(avg * count) / SUM(count), SUM(count)\n\
+                    FROM measurement_rollups WHERE resolution = {hour} AND timestamp >= {since}\n\
+                    GROUP BY host, feature, metric, bucket\n\
+                ON CONFLICT(host, feature, metric, resolution, timestamp) DO UPDATE SET\n\
+                    min = excluded.min, max = excluded.max, avg = excluded.avg, count = excluded.count",
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+
+        Ok((hourly, daily))
+    }
+
+    async fn import_rollups(
+        &self,
+        since: u64,
+        policy: RetentionPolicy,
+        now: u64,
+    ) -> Result<(usize, usize), anyhow::Error> {
+        let raw_cutoff = policy.raw_cutoff(now);
+        let mut merged = [0, 0];
+        if since < raw_cutoff {
+            for (rows, resolution) in merged
+                .iter_mut()
+                .zip([Resolution::Hourly, Resolution::Daily])
+            {
+                let seconds = resolution.seconds().unwrap();
+                *rows = self
+                    .execute(&format!(
+                        "INSERT INTO measurement_rollups (host, feature, metric, resolution, timestamp, min, max, avg, count)\n\
+                            SELECT host, feature, metric, {seconds}, timestamp / {seconds} * {seconds} AS bucket, MIN(value), MAX(value), AVG(value), COUNT(*)\n\
+                            FROM sensor_measurements WHERE timestamp < {raw_cutoff} AND flag IS NULL\n\
+                            GROUP BY host, feature, metric, bucket\n\
+                        ON CONFLICT(host, feature, metric, resolution, timestamp) DO UPDATE SET\n\
+                            min = MIN(min, excluded.min), max = MAX(max, excluded.max),\n\
+                            avg = (avg * count + excluded.avg * excluded.count) / (count + excluded.count),\n\
+                            count = count + excluded.count",
+                    ))
+                    .await
+                    .map_err(|e| anyhow::anyhow!("{}", e))?;
+            }
+            self.execute(&format!(
+                "DELETE FROM sensor_measurements WHERE timestamp < {}",
+                raw_cutoff
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        }
+        let (hourly, daily) = self.refresh_rollups(since.max(raw_cutoff)).await?;
+
+        Ok((merged[0] + hourly, merged[1] + daily))
+    }
+
+    async fn compact_measurements(
+        &self,
+        policy: RetentionPolicy,
+        now: u64,
+    ) -> Result<CompactionResult, anyhow::Error> {
+        let hour = Resolution::Hourly.seconds().unwrap();
+        let day = Resolution::Daily.seconds().unwrap();
+        // everything is rolled up on the first run, only the recent buckets afterwards
+        let last_hourly = self
+            .query_single::<u64>(&format!(
+                "SELECT MAX(timestamp) FROM measurement_rollups WHERE resolution = {} HAVING COUNT(*) > 0",
+                hour
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let since = last_hourly.map_or(0, |last| last.min(now.saturating_sub(REFRESH_SECS)));
+        let (hourly, daily) = self.refresh_rollups(since).await?;
+
+        // cut-offs are aligned to the buckets, so no rollup loses a part of its source
+        let raw_cutoff = policy.raw_cutoff(now);
+        let hourly_cutoff = now.saturating_sub(policy.hourly_days as u64 * DAY_SECS) / day * day;
+        let mut deleted = self
+            .execute(&format!(
+                "DELETE FROM sensor_measurements WHERE timestamp < {}",
+                raw_cutoff
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        self.execute(&format!(
+            "DELETE FROM measurement_gaps WHERE start < {}",
+            raw_cutoff
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+        deleted += self
+            .execute(&format!(
+                "DELETE FROM measurement_rollups WHERE resolution = {} AND timestamp < {}",
+                hour, hourly_cutoff
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        if let Some(daily_days) = policy.daily_days {
+            deleted += self
+                .execute(&format!(
+                    "DELETE FROM measurement_rollups WHERE resolution = {} AND timestamp < {}",
+                    day,
+                    now.saturating_sub(daily_days as u64 * DAY_SECS)
+                ))
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+        }
+
+        Ok(CompactionResult {
+            hourly,
+            daily,
+            deleted,
+        })
+    }
+}
@@ -0,0 +1,72 @@
+use super::{quote, Database, DbConn};
+use crate::models::db::{SensorFeatures, ValidationRule};
+
+pub trait MeasurementValidationDatabase {
+    async fn get_validation_rules(&self) -> Result<Vec<ValidationRule>, anyhow::Error>;
+    async fn set_validation_rules(&self, rules: Vec<ValidationRule>) -> Result<(), anyhow::Error>;
+    /// Number of stored raw readings of a metric flagged on collection.
+    async fn count_flagged(
+        &self,
+        feature: SensorFeatures,
+        metric: &str,
+    ) -> Result<u64, anyhow::Error>;
+}
+
+impl MeasurementValidationDatabase for DbConn {
+    async fn get_validation_rules(&self) -> Result<Vec<ValidationRule>, anyhow::Error> {
+        self.query::<ValidationRule>(
+            "SELECT feature, metric, min, max, max_rate, median_window, max_deviation FROM measurement_validation",
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    /// Replaces the validation rules, only rules checking something are kept.
+    async fn set_validation_rules(&self, rules: Vec<ValidationRule>) -> Result<(), anyhow::Error> {
+        self.execute("DELETE FROM measurement_validation")
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let optional = |value: Option<f64>| value.map_or("NULL".to_string(), |v| v.to_string());
+        let values = rules
+            .iter()
+            .filter(|r| !r.is_empty())
+            .map(|r| {
+                format!(
+                    "({}, {}, {}, {}, {}, {}, {})",
+                    r.feature.bits(),
+                    quote(&r.metric),
+                    optional(r.min),
+                    optional(r.max),
+                    optional(r.max_rate),
+                    r.median_window,
+                    optional(r.max_deviation)
+                )
+            })
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            return Ok(());
+        }
+        self.execute(&format!(
+            "INSERT INTO measurement_validation (feature, metric, min, max, max_rate, median_window, max_deviation) VALUES {}",
+            values.join(", ")
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+        Ok(())
+    }
+
+    async fn count_flagged(
+        &self,
+        feature: SensorFeatures,
+        metric: &str,
+    ) -> Result<u64, anyhow::Error> {
+        self.query_single::<u64>(&format!(
+            "SELECT COUNT(*) FROM sensor_measurements WHERE feature = {} AND metric = {} AND flag IS NOT NULL",
+            feature.bits(),
+            quote(metric)
+        ))
+        .await
+        .map(Option::unwrap_or_default)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+}
@@ -0,0 +1,381 @@
+use super::{quote, quote_list, Database, DbConn};
+use crate::{
+    features::FeatureInfo,
+    models::db::{
+        BrowseFilter, BrowsePage, BrowseSort, MeasurementEntry, MeasurementGap, MeasurementRow,
+        ScheduleTarget, SensorFeatures,
+    },
+};
+
+/// Readings as they are shown, corrected by the calibration of their sensor.
+pub(super) const CALIBRATED_MEASUREMENTS: &str = "(SELECT m.host, m.feature, m.metric, m.timestamp, m.flag, \
+    m.value * COALESCE(c.scale, 1) + COALESCE(c.\"offset\", 0) AS value FROM sensor_measurements AS m \
+    LEFT JOIN sensor_calibration AS c ON c.host = m.host AND c.feature = m.feature AND c.metric = m.metric)";
+
+pub trait MeasurementDatabase {
+    async fn get_measurements(
+        &self,
+        feature: &FeatureInfo,
+        host: Option<Vec<impl Into<String>>>,
+        limit: Option<usize>,
+        offset: Option<usize>,
+        after: Option<i64>,
+    ) -> Result<Vec<MeasurementRow>, anyhow::Error>;
+    /// Up to `limit` rows of `feature` within `[from, to)`, oldest first,
+    /// continuing after the `(timestamp, host)` of the last row of the previous page.
+    /// Flagged readings are left out like in the charts.
+    async fn get_measurement_page(
+        &self,
+        feature: &FeatureInfo,
+        host: Option<Vec<String>>,
+        from: Option<i64>,
+        to: Option<i64>,
+        after: Option<(u64, String)>,
+        limit: usize,
+    ) -> Result<Vec<MeasurementRow>, anyhow::Error>;
+    /// Up to `limit` rows of `feature` matching `filter`, in its order for every kind of `page`.
+    async fn browse_measurements(
+        &self,
+        feature: &FeatureInfo,
+        filter: &BrowseFilter,
+        page: &BrowsePage,
+        limit: usize,
+    ) -> Result<Vec<MeasurementRow>, anyhow::Error>;
+    async fn get_last_measurement(
+        &self,
+        host: &str,
+        feature: SensorFeatures,
+    ) -> Result<Option<u64>, anyhow::Error>;
+    /// Up to `limit` stored readings of a metric of a sensor taken before `before`, oldest first.
+    async fn get_recent_entries(
+        &self,
+        host: &str,
+        feature: SensorFeatures,
+        metric: &str,
+        before: u64,
+        limit: usize,
+    ) -> Result<Vec<MeasurementEntry>, anyhow::Error>;
+    async fn create_measurement_batch(
+        &self,
+        entries: Vec<MeasurementEntry>,
+    ) -> Result<usize, anyhow::Error>;
+    async fn get_measurement_gaps(
+        &self,
+        feature: &FeatureInfo,
+        host: Option<&str>,
+    ) -> Result<Vec<MeasurementGap>, anyhow::Error>;
+    async fn mark_gap_unrecoverable(&self, gap: &MeasurementGap) -> Result<(), anyhow::Error>;
+}
+
+impl MeasurementDatabase for DbConn {
+    async fn get_measurements(
+        &self,
+        feature: &FeatureInfo,
+        host: Option<Vec<impl Into<String>>>,
+        limit: Option<usize>,
+        offset: Option<usize>,
+        after: Option<i64>,
+    ) -> Result<Vec<MeasurementRow>, anyhow::Error> {
+        // one column per metric of the feature
+        let mut query = String::from("SELECT host, timestamp");
+        for metric in feature.metrics {
+            query.push_str(&format!(
+                ", MAX(CASE WHEN metric = {} THEN value END)",
+                quote(metric.name)
+            ));
+        }
+        query.push_str(&format!(
+            " FROM {} WHERE feature = {} AND flag IS NULL",
+            CALIBRATED_MEASUREMENTS,
+            feature.feature.bits()
+        ));
+        if let Some(host) = host {
+            query.push_str(&format!(
+                " AND host IN ({})",
+                quote_list(host.into_iter().map(Into::<String>::into))
+            ));
+        }
+        if let Some(after) = after {
+            query.push_str(" AND timestamp > ");
+            query.push_str(&after.to_string());
+        }
+        query.push_str(" GROUP BY host, timestamp ORDER BY timestamp DESC");
+        if let Some(limit) = limit {
+            query.push_str(" LIMIT ");
+            query.push_str(&limit.to_string());
+        }
+        if let Some(offset) = offset {
+            query.push_str(" OFFSET ");
+            query.push_str(&offset.to_string());
+        }
+
+        self.query::<MeasurementRow>(&query)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_measurement_page(
+        &self,
+        feature: &FeatureInfo,
+        host: Option<Vec<String>>,
+        from: Option<i64>,
+        to: Option<i64>,
+        after: Option<(u64, String)>,
+        limit: usize,
+    ) -> Result<Vec<MeasurementRow>, anyhow::Error> {
+        let mut query = String::from("SELECT host, timestamp");
+        for metric in feature.metrics {
+            query.push_str(&format!(
+                ", MAX(CASE WHEN metric = {} THEN value END)",
+                quote(metric.name)
+            ));
+        }
+        query.push_str(&format!(
+            " FROM sensor_measurements WHERE feature = {} AND flag IS NULL",
+            feature.feature.bits()
+        ));
+        if let Some(host) = host {
+            query.push_str(&format!(" AND host IN ({})", quote_list(&host)));
+        }
+        if let Some(from) = from {
+            query.push_str(&format!(" AND timestamp >= {}", from));
+        }
+        if let Some(to) = to {
+            query.push_str(&format!(" AND timestamp < {}", to));
+        }
+        if let Some((timestamp, host)) = after {
+            query.push_str(&format!(
+                " AND (timestamp > {0} OR (timestamp = {0} AND host > {1}))",
+                timestamp,
+                quote(&host)
+            ));
+        }
+        query.push_str(&format!(
+            " GROUP BY host, timestamp ORDER BY timestamp, host LIMIT {}",
+            limit
+        ));
+
+        self.query::<MeasurementRow>(&query)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn browse_measurements(
+        &self,
+        feature: &FeatureInfo,
+        filter: &BrowseFilter,
+        page: &BrowsePage,
+        limit: usize,
+    ) -> Result<Vec<MeasurementRow>, anyhow::Error> {
+        let mut query = String::from("SELECT host, timestamp");
+        for (i, metric) in feature.metrics.iter().enumerate() {
+            query.push_str(&format!(
+                ", MAX(CASE WHEN metric = {0} THEN value END) AS m{1}, \
+                MAX(CASE WHEN metric = {0} THEN flag END) AS flag{1}",
+                quote(metric.name),
+                i
+            ));
+        }
+        query.push_str(&format!(
+            " FROM {} WHERE feature = {}",
+            CALIBRATED_MEASUREMENTS,
+            feature.feature.bits()
+        ));
+        if !filter.flagged {
+            query.push_str(" AND flag IS NULL");
+        }
+        match &filter.target {
+            ScheduleTarget::All => {}
+            ScheduleTarget::Area(id) => query.push_str(&format!(
+                " AND host IN (SELECT host FROM sensors WHERE area_id = {})",
+                id
+            )),
+            ScheduleTarget::Sensor(host) => query.push_str(&format!(" AND host = {}", quote(host))),
+        }
+        if let Some(from) = filter.from {
+            query.push_str(&format!(" AND timestamp >= {}", from));
+        }
+        if let Some(to) = filter.to {
+            query.push_str(&format!(" AND timestamp < {}", to));
+        }
+        query.push_str(" GROUP BY host, timestamp");
+
+        // metric values only exist once the rows are pivoted
+        let mut query = format!("SELECT * FROM ({}) WHERE 1 = 1", query);
+        if let Some((metric, min, max)) = filter.range {
+            if let Some(min) = min {
+                query.push_str(&format!(" AND m{} >= {}", metric, min));
+            }
+            if let Some(max) = max {
+                query.push_str(&format!(" AND m{} <= {}", metric, max));
+            }
+        }
+        let key = match filter.sort {
+            BrowseSort::Time => "timestamp, host".to_string(),
+            BrowseSort::Sensor => "host, timestamp".to_string(),
+            BrowseSort::Metric(metric) => {
+                query.push_str(&format!(" AND m{} IS NOT NULL", metric));
+                format!("m{}, timestamp, host", metric)
+            }
+        };
+        // pages before the cursor are read backwards and turned around
+        let (cursor, descending) = match page {
+            BrowsePage::First => (None, filter.descending),
+            BrowsePage::After(cursor) => (Some(cursor), filter.descending),
+            BrowsePage::Before(cursor) => (Some(cursor), !filter.descending),
+        };
+        if let Some(cursor) = cursor {
+            let host = quote(&cursor.host);
+            let value = match filter.sort {
+                BrowseSort::Time => format!("{}, {}", cursor.timestamp, host),
+                BrowseSort::Sensor => format!("{}, {}", host, cursor.timestamp),
+                BrowseSort::Metric(_) => format!(
+                    "{}, {}, {}",
+                    cursor.value.unwrap_or_default(),
+                    cursor.timestamp,
+                    host
+                ),
+            };
+            query.push_str(&format!(
+                " AND ({}) {} ({})",
+                key,
+                if descending { "<" } else { ">" },
+                value
+            ));
+        }
+        let order = if descending { "DESC" } else { "ASC" };
+        query.push_str(&format!(
+            " ORDER BY {} LIMIT {}",
+            key.split(", ")
+                .map(|column| format!("{} {}", column, order))
+                .collect::<Vec<_>>()
+                .join(", "),
+            limit
+        ));
+
+        let mut rows = self
+            .query::<MeasurementRow>(&query)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        if let BrowsePage::Before(_) = page {
+            rows.reverse();
+        }
+        Ok(rows)
+    }
+
+    async fn get_last_measurement(
+        &self,
+        host: &str,
+        feature: SensorFeatures,
+    ) -> Result<Option<u64>, anyhow::Error> {
+        self.query_single::<u64>(&format!(
+            "SELECT MAX(timestamp) FROM sensor_measurements WHERE host = {} AND feature = {} HAVING COUNT(*) > 0",
+            quote(host),
+            feature.bits()
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_recent_entries(
+        &self,
+        host: &str,
+        feature: SensorFeatures,
+        metric: &str,
+        before: u64,
+        limit: usize,
+    ) -> Result<Vec<MeasurementEntry>, anyhow::Error> {
+        let mut entries = self
+            .query::<MeasurementEntry>(&format!(
+                "SELECT host, feature, metric, timestamp, value, flag FROM sensor_measurements \
+                WHERE host = {} AND feature = {} AND metric = {} AND timestamp < {} \
+                ORDER BY timestamp DESC LIMIT {}",
+                quote(host),
+                feature.bits(),
+                quote(metric),
+                before,
+                limit
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        entries.reverse();
+        Ok(entries)
+    }
+
+    async fn create_measurement_batch(
+        &self,
+        entries: Vec<MeasurementEntry>,
+    ) -> Result<usize, anyhow::Error> {
+        if entries.is_empty() {
+            return Ok(0);
+        }
+        let mut query = String::from(
+            "INSERT INTO sensor_measurements(host, feature, metric, timestamp, value, flag) \nVALUES ",
+        );
+        for entry in entries {
+            query.push_str(&format!(
+                "({}, {}, {}, {}, {}, {}),\n",
+                quote(&entry.host),
+                entry.feature.bits(),
+                quote(&entry.metric),
+                entry.timestamp,
+                entry.value,
+                entry
+                    .flag
+                    .map_or("NULL".to_string(), |flag| quote(&flag.to_string())),
+            ));
+        }
+        query.pop();
+        query.pop();
+        query.push_str(
+            "\nON CONFLICT(host, feature, metric, timestamp) DO UPDATE SET value = excluded.value, flag = excluded.flag;",
+        );
+        self.execute(&query)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_measurement_gaps(
+        &self,
+        feature: &FeatureInfo,
+        host: Option<&str>,
+    ) -> Result<Vec<MeasurementGap>, anyhow::Error> {
+        let Some(threshold) = feature.gap_threshold() else {
+            return Ok(Vec::new());
+        };
+        // pair every reading with the previous one of the same sensor
+        let mut readings = format!(
+            "SELECT DISTINCT host, timestamp FROM sensor_measurements WHERE feature = {}",
+            feature.feature.bits()
+        );
+        if let Some(host) = host {
+            readings.push_str(&format!(" AND host = {}", quote(host)));
+        }
+        self.query::<MeasurementGap>(&format!(
+            "SELECT g.host, {feature}, g.prev, g.next, u.host IS NOT NULL FROM (\n\
+                SELECT host, LAG(timestamp) OVER (PARTITION BY host ORDER BY timestamp) AS prev, timestamp AS next\n\
+                FROM ({readings})\n\
+            ) AS g\n\
+            LEFT JOIN measurement_gaps AS u ON u.host = g.host AND u.feature = {feature} AND u.start = g.prev\n\
+            WHERE g.next - g.prev > {threshold}\n\
+            ORDER BY g.next DESC",
+            feature = feature.feature.bits(),
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn mark_gap_unrecoverable(&self, gap: &MeasurementGap) -> Result<(), anyhow::Error> {
+        self.execute(&format!(
+            "INSERT INTO measurement_gaps (host, feature, start, \"end\") VALUES ({}, {}, {}, {})\n\
+                ON CONFLICT(host, feature, start) DO UPDATE SET \"end\" = excluded.\"end\"",
+            quote(&gap.host),
+            gap.feature.bits(),
+            gap.start,
+            gap.end
+        ))
+        .await
+        .map(|_| ())
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+}
@@ -1,9 +1,19 @@
 use r2d2_sqlite::rusqlite::OptionalExtension;
 
+pub mod alerts;
 pub mod areas;
+pub mod collection_runs;
 pub mod data_schedule;
+pub mod measurement_rollups;
+pub mod measurement_validation;
+pub mod measurements;
+pub mod motion_data;
+pub mod notifications;
+pub mod scan_history;
+pub mod sensor_calibration;
+pub mod sensor_health;
+pub mod sensor_status;
 pub mod sensors;
-pub mod temp_data;
 pub mod user_sessions;
 pub mod users;
 
@@ -23,10 +33,35 @@ pub trait Database {
     ) -> Result<Option<T>, Box<dyn std::error::Error>>;
 }
 
+/// `value` as an SQL string literal, its quotes escaped.
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// `value` as an SQL string literal, `NULL` when missing.
+pub fn quote_or_null(value: Option<&str>) -> String {
+    value.map_or("NULL".to_string(), quote)
+}
+
+/// `values` as a list of SQL string literals, for `IN (...)`.
+pub fn quote_list(values: impl IntoIterator<Item = impl AsRef<str>>) -> String {
+    values
+        .into_iter()
+        .map(|v| quote(v.as_ref()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 pub trait FromRow: Sized {
     fn from_row(row: &r2d2_sqlite::rusqlite::Row) -> r2d2_sqlite::rusqlite::Result<Self>;
 }
 
+impl FromRow for u64 {
+    fn from_row(row: &r2d2_sqlite::rusqlite::Row) -> r2d2_sqlite::rusqlite::Result<Self> {
+        row.get::<_, u64>(0)
+    }
+}
+
 impl Database for DbConn {
     async fn execute(&self, query: &str) -> Result<usize, Box<dyn std::error::Error>> {
         let query = query.to_string();
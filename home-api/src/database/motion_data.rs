@@ -0,0 +1,127 @@
+use super::{quote, quote_list, Database, DbConn};
+use crate::models::db::MotionDataEntry;
+
+pub trait MotionDataDatabase {
+    /// Events of `host` (all sensors if `None`) overlapping `during`, newest first.
+    async fn get_motion_data(
+        &self,
+        host: Option<Vec<impl Into<String>>>,
+        limit: Option<usize>,
+        offset: Option<usize>,
+        during: Option<(i64, i64)>,
+    ) -> Result<Vec<MotionDataEntry>, anyhow::Error>;
+    /// Events still ongoing or that ended after `after`.
+    async fn get_recent_motion_data(
+        &self,
+        after: i64,
+    ) -> Result<Vec<MotionDataEntry>, anyhow::Error>;
+    /// Start of the earliest ongoing event of `host`, otherwise of its latest event.
+    async fn get_motion_sync_point(&self, host: &str) -> Result<Option<i64>, anyhow::Error>;
+    async fn create_motion_data_batch(
+        &self,
+        entries: Vec<MotionDataEntry>,
+    ) -> Result<usize, anyhow::Error>;
+}
+
+impl MotionDataDatabase for DbConn {
+    async fn get_motion_data(
+        &self,
+        host: Option<Vec<impl Into<String>>>,
+        limit: Option<usize>,
+        offset: Option<usize>,
+        during: Option<(i64, i64)>,
+    ) -> Result<Vec<MotionDataEntry>, anyhow::Error> {
+        let mut query = String::from("SELECT * FROM sensor_motion_data");
+        let mut conditions = vec![];
+        if let Some(host) = host {
+            conditions.push(format!(
+                "host IN ({})",
+                quote_list(host.into_iter().map(Into::<String>::into))
+            ));
+        }
+        // events still ongoing or started before `from` reach into the period as well
+        if let Some((from, to)) = during {
+            conditions.push(format!(
+                "(\"end\" IS NULL OR \"end\" > {}) AND start < {}",
+                from, to
+            ));
+        }
+        if !conditions.is_empty() {
+            query.push_str(" WHERE ");
+            query.push_str(&conditions.join(" AND "));
+        }
+        query.push_str(" ORDER BY start DESC");
+        if let Some(limit) = limit {
+            query.push_str(" LIMIT ");
+            query.push_str(&limit.to_string());
+        }
+        if let Some(offset) = offset {
+            query.push_str(" OFFSET ");
+            query.push_str(&offset.to_string());
+        }
+
+        self.query::<MotionDataEntry>(&query)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_recent_motion_data(
+        &self,
+        after: i64,
+    ) -> Result<Vec<MotionDataEntry>, anyhow::Error> {
+        self.query::<MotionDataEntry>(&format!(
+            "SELECT * FROM sensor_motion_data WHERE \"end\" IS NULL OR \"end\" > {}",
+            after
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_motion_sync_point(&self, host: &str) -> Result<Option<i64>, anyhow::Error> {
+        let ongoing = self
+            .query_single::<u64>(&format!(
+                "SELECT MIN(start) FROM sensor_motion_data WHERE host = {} AND \"end\" IS NULL HAVING COUNT(*) > 0",
+                quote(host)
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        if let Some(ongoing) = ongoing {
+            return Ok(Some(ongoing as i64));
+        }
+        self.query_single::<u64>(&format!(
+            "SELECT MAX(start) FROM sensor_motion_data WHERE host = {} HAVING COUNT(*) > 0",
+            quote(host)
+        ))
+        .await
+        .map(|s| s.map(|s| s as i64))
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn create_motion_data_batch(
+        &self,
+        entries: Vec<MotionDataEntry>,
+    ) -> Result<usize, anyhow::Error> {
+        if entries.is_empty() {
+            return Ok(0);
+        }
+        let mut query =
+            String::from("INSERT INTO sensor_motion_data(host, start, \"end\") \nVALUES ");
+        for entry in entries {
+            query.push_str(&format!(
+                "({}, {}, {}),\n",
+                quote(&entry.host),
+                entry.start,
+                entry
+                    .end
+                    .map(|e| e.to_string())
+                    .unwrap_or("NULL".to_string()),
+            ));
+        }
+        query.pop();
+        query.pop();
+        query.push_str("\nON CONFLICT(host, start) DO UPDATE SET \"end\" = excluded.\"end\";");
+        self.execute(&query)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+}
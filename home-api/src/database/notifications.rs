@@ -0,0 +1,194 @@
+use super::{quote, quote_or_null, Database, DbConn};
+use crate::models::db::{
+    DeliveryStatus, NotificationChannel, NotificationDelivery, NotificationSubscription,
+};
+
+const HISTORY_SIZE: usize = 1000;
+
+pub trait NotificationDatabase {
+    async fn get_channels(&self) -> Result<Vec<NotificationChannel>, anyhow::Error>;
+    async fn get_channel(&self, id: i64) -> Result<Option<NotificationChannel>, anyhow::Error>;
+    async fn create_channel(
+        &self,
+        channel: NotificationChannel,
+    ) -> Result<Option<NotificationChannel>, anyhow::Error>;
+    async fn delete_channel(&self, id: i64) -> Result<bool, anyhow::Error>;
+    async fn get_subscriptions(
+        &self,
+        user_id: Option<i64>,
+    ) -> Result<Vec<NotificationSubscription>, anyhow::Error>;
+    /// Replaces the subscription of the user to the channel, removing it when no topics are left.
+    async fn set_subscription(
+        &self,
+        subscription: NotificationSubscription,
+    ) -> Result<(), anyhow::Error>;
+    async fn create_delivery(
+        &self,
+        delivery: NotificationDelivery,
+    ) -> Result<Option<NotificationDelivery>, anyhow::Error>;
+    async fn update_delivery(&self, delivery: &NotificationDelivery) -> Result<(), anyhow::Error>;
+    /// Pending deliveries whose next attempt is due at `now`.
+    async fn get_due_deliveries(
+        &self,
+        now: i64,
+    ) -> Result<Vec<NotificationDelivery>, anyhow::Error>;
+    async fn get_deliveries(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<NotificationDelivery>, anyhow::Error>;
+}
+
+impl NotificationDatabase for DbConn {
+    async fn get_channels(&self) -> Result<Vec<NotificationChannel>, anyhow::Error> {
+        self.query::<NotificationChannel>("SELECT * FROM notification_channels")
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_channel(&self, id: i64) -> Result<Option<NotificationChannel>, anyhow::Error> {
+        self.query_single::<NotificationChannel>(&format!(
+            "SELECT * FROM notification_channels WHERE rowid = {}",
+            id
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn create_channel(
+        &self,
+        channel: NotificationChannel,
+    ) -> Result<Option<NotificationChannel>, anyhow::Error> {
+        self.query_single::<NotificationChannel>(&format!(
+            "INSERT INTO notification_channels (name, kind, url, credentials, sender, template)\n\
+                VALUES ({}, {}, {}, {}, {}, {})\n\
+                RETURNING *",
+            quote(&channel.name),
+            quote(&channel.kind.to_string()),
+            quote(&channel.url),
+            quote_or_null(channel.credentials.as_deref()),
+            quote_or_null(channel.sender.as_deref()),
+            quote(&channel.template)
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn delete_channel(&self, id: i64) -> Result<bool, anyhow::Error> {
+        Ok(self
+            .execute(&format!(
+                "DELETE FROM notification_channels WHERE rowid = {}",
+                id
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?
+            > 0)
+    }
+
+    async fn get_subscriptions(
+        &self,
+        user_id: Option<i64>,
+    ) -> Result<Vec<NotificationSubscription>, anyhow::Error> {
+        let mut query = String::from("SELECT * FROM notification_subscriptions");
+        if let Some(user_id) = user_id {
+            query.push_str(&format!(" WHERE user_id = {}", user_id));
+        }
+        self.query::<NotificationSubscription>(&query)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn set_subscription(
+        &self,
+        subscription: NotificationSubscription,
+    ) -> Result<(), anyhow::Error> {
+        let query = match subscription.topics.is_empty() {
+            true => format!(
+                "DELETE FROM notification_subscriptions WHERE user_id = {} AND channel_id = {}",
+                subscription.user_id, subscription.channel_id
+            ),
+            false => format!(
+                "INSERT INTO notification_subscriptions (user_id, channel_id, topics, address) VALUES ({}, {}, {}, {})\n\
+                    ON CONFLICT(user_id, channel_id) DO UPDATE SET topics = excluded.topics, address = excluded.address",
+                subscription.user_id,
+                subscription.channel_id,
+                subscription.topics.bits(),
+                quote_or_null(subscription.address.as_deref())
+            ),
+        };
+        self.execute(&query)
+            .await
+            .map(|_| ())
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn create_delivery(
+        &self,
+        delivery: NotificationDelivery,
+    ) -> Result<Option<NotificationDelivery>, anyhow::Error> {
+        let delivery = self
+            .query_single::<NotificationDelivery>(&format!(
+                "INSERT INTO notification_deliveries (channel_id, recipient, topic, title, body, created, status, attempts, next_attempt, error)\n\
+                    VALUES ({}, {}, {}, {}, {}, {}, {}, 0, {}, NULL)\n\
+                    RETURNING *",
+                delivery.channel_id,
+                quote_or_null(delivery.recipient.as_deref()),
+                delivery.topic.bits(),
+                quote(&delivery.title),
+                quote(&delivery.body),
+                delivery.created,
+                u32::from(DeliveryStatus::Pending),
+                delivery.created
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        // keep only the most recent deliveries
+        self.execute(&format!(
+            "DELETE FROM notification_deliveries WHERE rowid IN (SELECT rowid FROM notification_deliveries ORDER BY created DESC LIMIT -1 OFFSET {})",
+            HISTORY_SIZE
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+        Ok(delivery)
+    }
+
+    async fn update_delivery(&self, delivery: &NotificationDelivery) -> Result<(), anyhow::Error> {
+        self.execute(&format!(
+            "UPDATE notification_deliveries SET status = {}, attempts = {}, next_attempt = {}, error = {} WHERE rowid = {}",
+            u32::from(delivery.status),
+            delivery.attempts,
+            delivery
+                .next_attempt
+                .map_or("NULL".to_string(), |t| t.to_string()),
+            quote_or_null(delivery.error.as_deref()),
+            delivery.id
+        ))
+        .await
+        .map(|_| ())
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_due_deliveries(
+        &self,
+        now: i64,
+    ) -> Result<Vec<NotificationDelivery>, anyhow::Error> {
+        self.query::<NotificationDelivery>(&format!(
+            "SELECT * FROM notification_deliveries WHERE status = {} AND next_attempt <= {} ORDER BY next_attempt",
+            u32::from(DeliveryStatus::Pending),
+            now
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_deliveries(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<NotificationDelivery>, anyhow::Error> {
+        self.query::<NotificationDelivery>(&format!(
+            "SELECT * FROM notification_deliveries ORDER BY created DESC, rowid DESC LIMIT {}",
+            limit
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+}
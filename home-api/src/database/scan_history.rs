@@ -0,0 +1,157 @@
+use super::{quote, quote_or_null, Database, DbConn};
+use crate::models::db::{ScanChange, ScanHistoryEntry, SensorEntity};
+
+const HISTORY_SIZE: usize = 100;
+
+pub trait ScanHistoryDatabase {
+    async fn create_scan(
+        &self,
+        entry: ScanHistoryEntry,
+        devices: Vec<SensorEntity>,
+        changes: Vec<ScanChange>,
+    ) -> Result<ScanHistoryEntry, anyhow::Error>;
+    async fn get_scans(&self, limit: usize) -> Result<Vec<ScanHistoryEntry>, anyhow::Error>;
+    async fn get_scan_devices(&self, scan_id: i64) -> Result<Vec<SensorEntity>, anyhow::Error>;
+    async fn get_unseen_changes(&self) -> Result<Vec<ScanChange>, anyhow::Error>;
+    async fn mark_changes_seen(&self) -> Result<usize, anyhow::Error>;
+    async fn get_scanner_interval(&self) -> Result<Option<u64>, anyhow::Error>;
+    async fn set_scanner_interval(&self, interval_ms: Option<u64>) -> Result<(), anyhow::Error>;
+}
+
+impl ScanHistoryDatabase for DbConn {
+    async fn create_scan(
+        &self,
+        entry: ScanHistoryEntry,
+        devices: Vec<SensorEntity>,
+        changes: Vec<ScanChange>,
+    ) -> Result<ScanHistoryEntry, anyhow::Error> {
+        let entry = self
+            .query_single::<ScanHistoryEntry>(&format!(
+                "INSERT INTO scan_history (created, duration_ms, target, found) VALUES ({}, {}, {}, {}) RETURNING *",
+                entry.created,
+                entry.duration_ms,
+                quote(&entry.target),
+                entry.found
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?
+            .ok_or(anyhow::anyhow!("Error creating scan history entry"))?;
+        if !devices.is_empty() {
+            self.execute(&format!(
+                "INSERT INTO scan_devices (scan_id, host, name, features) VALUES {}",
+                devices
+                    .iter()
+                    .map(|d| format!(
+                        "({}, {}, {}, {})",
+                        entry.id,
+                        quote(&d.host),
+                        quote(&d.name),
+                        d.features.bits()
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        }
+        if !changes.is_empty() {
+            self.execute(&format!(
+                "INSERT INTO scan_changes (scan_id, kind, host, name, previous_host) VALUES {}",
+                changes
+                    .iter()
+                    .map(|c| format!(
+                        "({}, {}, {}, {}, {})",
+                        entry.id,
+                        u32::from(c.kind),
+                        quote(&c.host),
+                        quote(&c.name),
+                        quote_or_null(c.previous_host.as_deref())
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        }
+        // keep only the most recent scans
+        let stale = format!(
+            "SELECT rowid FROM scan_history ORDER BY created DESC LIMIT -1 OFFSET {}",
+            HISTORY_SIZE
+        );
+        self.execute(&format!(
+            "DELETE FROM scan_devices WHERE scan_id IN ({})",
+            stale
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+        self.execute(&format!(
+            "DELETE FROM scan_changes WHERE scan_id IN ({})",
+            stale
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+        self.execute(&format!(
+            "DELETE FROM scan_history WHERE rowid IN ({})",
+            stale
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+
+        Ok(entry)
+    }
+
+    async fn get_scans(&self, limit: usize) -> Result<Vec<ScanHistoryEntry>, anyhow::Error> {
+        self.query::<ScanHistoryEntry>(&format!(
+            "SELECT * FROM scan_history ORDER BY created DESC LIMIT {}",
+            limit
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_scan_devices(&self, scan_id: i64) -> Result<Vec<SensorEntity>, anyhow::Error> {
+        self.query::<SensorEntity>(&format!(
+            "SELECT name, NULL, features, host, NULL FROM scan_devices WHERE scan_id = {}",
+            scan_id
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_unseen_changes(&self) -> Result<Vec<ScanChange>, anyhow::Error> {
+        self.query::<ScanChange>(
+            "SELECT scan_history.created, kind, host, name, previous_host FROM scan_changes\n\
+                JOIN scan_history ON scan_changes.scan_id = scan_history.rowid\n\
+                WHERE seen = FALSE ORDER BY scan_history.created",
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn mark_changes_seen(&self) -> Result<usize, anyhow::Error> {
+        self.execute("UPDATE scan_changes SET seen = TRUE WHERE seen = FALSE")
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_scanner_interval(&self) -> Result<Option<u64>, anyhow::Error> {
+        self.query_single::<u64>("SELECT interval_ms FROM scanner_schedule LIMIT 1")
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn set_scanner_interval(&self, interval_ms: Option<u64>) -> Result<(), anyhow::Error> {
+        self.execute("DELETE FROM scanner_schedule")
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        if let Some(interval_ms) = interval_ms {
+            self.execute(&format!(
+                "INSERT INTO scanner_schedule (interval_ms) VALUES ({})",
+                interval_ms
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use super::{quote, Database, DbConn};
+use crate::models::db::Calibration;
+
+pub trait SensorCalibrationDatabase {
+    async fn get_calibrations(&self, host: &str) -> Result<Vec<Calibration>, anyhow::Error>;
+    async fn set_calibrations(
+        &self,
+        host: &str,
+        calibrations: Vec<Calibration>,
+    ) -> Result<(), anyhow::Error>;
+}
+
+impl SensorCalibrationDatabase for DbConn {
+    async fn get_calibrations(&self, host: &str) -> Result<Vec<Calibration>, anyhow::Error> {
+        self.query::<Calibration>(&format!(
+            "SELECT host, feature, metric, \"offset\", scale FROM sensor_calibration WHERE host = {}",
+            quote(host)
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    /// Replaces the calibration of the sensor, only corrections that change the readings are kept.
+    async fn set_calibrations(
+        &self,
+        host: &str,
+        calibrations: Vec<Calibration>,
+    ) -> Result<(), anyhow::Error> {
+        self.execute(&format!(
+            "DELETE FROM sensor_calibration WHERE host = {}",
+            quote(host)
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let values = calibrations
+            .iter()
+            .filter(|c| !c.is_identity())
+            .map(|c| {
+                format!(
+                    "({}, {}, {}, {}, {})",
+                    quote(host),
+                    c.feature.bits(),
+                    quote(&c.metric),
+                    c.offset,
+                    c.scale
+                )
+            })
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            return Ok(());
+        }
+        self.execute(&format!(
+            "INSERT INTO sensor_calibration (host, feature, metric, \"offset\", scale) VALUES {}",
+            values.join(", ")
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+        Ok(())
+    }
+}
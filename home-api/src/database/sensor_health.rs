@@ -0,0 +1,86 @@
+use super::{quote, Database, DbConn};
+use crate::models::db::SensorHealthEntry;
+
+/// Health entries older than this are dropped.
+const RETENTION_SECS: i64 = 30 * 24 * 60 * 60;
+
+pub trait SensorHealthDatabase {
+    async fn create_health_entry(&self, entry: SensorHealthEntry) -> Result<(), anyhow::Error>;
+    /// Entries of `host` newer than `after`, oldest first.
+    async fn get_health(
+        &self,
+        host: &str,
+        after: i64,
+    ) -> Result<Vec<SensorHealthEntry>, anyhow::Error>;
+    async fn get_latest_health(
+        &self,
+        host: &str,
+    ) -> Result<Option<SensorHealthEntry>, anyhow::Error>;
+    /// The most recent entry of every sensor.
+    async fn get_latest_health_all(&self) -> Result<Vec<SensorHealthEntry>, anyhow::Error>;
+}
+
+impl SensorHealthDatabase for DbConn {
+    async fn create_health_entry(&self, entry: SensorHealthEntry) -> Result<(), anyhow::Error> {
+        self.execute(&format!(
+            "INSERT INTO sensor_health (host, timestamp, free_mem, uptime, paired_keys, data_used, data_total, pair_used, pair_total, rebooted) VALUES ({}, {}, {}, {}, {}, {}, {}, {}, {}, {})",
+            quote(&entry.host),
+            entry.timestamp,
+            entry.free_mem,
+            entry.uptime,
+            entry.paired_keys,
+            entry.usage.data_used,
+            entry.usage.data_total,
+            entry.usage.pair_used,
+            entry.usage.pair_total,
+            entry.rebooted
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+        self.execute(&format!(
+            "DELETE FROM sensor_health WHERE timestamp < {}",
+            entry.timestamp - RETENTION_SECS
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+
+        Ok(())
+    }
+
+    async fn get_health(
+        &self,
+        host: &str,
+        after: i64,
+    ) -> Result<Vec<SensorHealthEntry>, anyhow::Error> {
+        self.query::<SensorHealthEntry>(&format!(
+            "SELECT * FROM sensor_health WHERE host = {} AND timestamp > {} ORDER BY timestamp",
+            quote(host),
+            after
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_latest_health(
+        &self,
+        host: &str,
+    ) -> Result<Option<SensorHealthEntry>, anyhow::Error> {
+        self.query_single::<SensorHealthEntry>(&format!(
+            "SELECT * FROM sensor_health WHERE host = {} ORDER BY timestamp DESC LIMIT 1",
+            quote(host)
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_latest_health_all(&self) -> Result<Vec<SensorHealthEntry>, anyhow::Error> {
+        self.query::<SensorHealthEntry>(
+            "SELECT sensor_health.* FROM sensor_health\n\
+                JOIN (SELECT host, MAX(timestamp) AS latest FROM sensor_health GROUP BY host) AS latest\n\
+                ON sensor_health.host = latest.host AND sensor_health.timestamp = latest.latest\n\
+                JOIN sensors ON sensor_health.host = sensors.host",
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+}
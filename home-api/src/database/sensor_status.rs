@@ -0,0 +1,70 @@
+use super::{quote, Database, DbConn};
+use crate::models::db::SensorStatus;
+
+pub trait SensorStatusDatabase {
+    async fn get_sensor_status(&self, host: &str) -> Result<Option<SensorStatus>, anyhow::Error>;
+    async fn get_sensor_statuses(&self) -> Result<Vec<SensorStatus>, anyhow::Error>;
+    async fn record_sensor_success(
+        &self,
+        host: &str,
+        timestamp: i64,
+    ) -> Result<SensorStatus, anyhow::Error>;
+    async fn record_sensor_failure(
+        &self,
+        host: &str,
+        error: &str,
+    ) -> Result<SensorStatus, anyhow::Error>;
+}
+
+impl SensorStatusDatabase for DbConn {
+    async fn get_sensor_status(&self, host: &str) -> Result<Option<SensorStatus>, anyhow::Error> {
+        self.query_single::<SensorStatus>(&format!(
+            "SELECT * FROM sensor_status WHERE host = {}",
+            quote(host)
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn get_sensor_statuses(&self) -> Result<Vec<SensorStatus>, anyhow::Error> {
+        self.query::<SensorStatus>(
+            "SELECT sensor_status.* FROM sensor_status JOIN sensors ON sensor_status.host = sensors.host",
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    async fn record_sensor_success(
+        &self,
+        host: &str,
+        timestamp: i64,
+    ) -> Result<SensorStatus, anyhow::Error> {
+        self.query_single::<SensorStatus>(&format!(
+            "INSERT INTO sensor_status (host, last_seen, last_error, failures) VALUES ({}, {}, NULL, 0)\n\
+                ON CONFLICT (host) DO UPDATE SET last_seen = excluded.last_seen, last_error = NULL, failures = 0\n\
+                RETURNING *",
+            quote(host),
+            timestamp
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?
+        .ok_or(anyhow::anyhow!("Error updating sensor status"))
+    }
+
+    async fn record_sensor_failure(
+        &self,
+        host: &str,
+        error: &str,
+    ) -> Result<SensorStatus, anyhow::Error> {
+        self.query_single::<SensorStatus>(&format!(
+            "INSERT INTO sensor_status (host, last_seen, last_error, failures) VALUES ({}, NULL, {}, 1)\n\
+                ON CONFLICT (host) DO UPDATE SET last_error = excluded.last_error, failures = failures + 1\n\
+                RETURNING *",
+            quote(host),
+            quote(error)
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?
+        .ok_or(anyhow::anyhow!("Error updating sensor status"))
+    }
+}
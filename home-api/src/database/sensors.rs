@@ -30,6 +30,11 @@ pub trait SensorDatabase {
         host: &str,
         sensor: SensorEntity,
     ) -> Result<bool, Box<dyn std::error::Error>>;
+    async fn update_pair_id(
+        &self,
+        host: &str,
+        pair_id: &str,
+    ) -> Result<bool, Box<dyn std::error::Error>>;
     async fn delete_sensor(&self, host: &str) -> Result<usize, Box<dyn std::error::Error>>;
 }
 
@@ -125,4 +130,18 @@ impl SensorDatabase for DbConn {
             .await?
             > 0)
     }
+
+    async fn update_pair_id(
+        &self,
+        host: &str,
+        pair_id: &str,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self
+            .execute(&format!(
+                "UPDATE sensors SET pair_id = '{}' WHERE host = '{}'",
+                pair_id, host
+            ))
+            .await?
+            > 0)
+    }
 }
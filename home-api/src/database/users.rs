@@ -1,5 +1,8 @@
 use super::{Database, DbConn};
-use crate::models::{auth::Password, db::UserEntity, NormalizedString};
+use crate::{
+    features::UnitPreferences,
+    models::{auth::Password, db::UserEntity, NormalizedString},
+};
 
 pub trait UserDatabase {
     async fn get_user(
@@ -19,6 +22,11 @@ pub trait UserDatabase {
         password: impl Into<String>,
     ) -> Result<(), Box<dyn std::error::Error>>;
     async fn delete_user(&self, username: &str) -> Result<(), Box<dyn std::error::Error>>;
+    async fn set_unit_preferences(
+        &self,
+        username: &str,
+        units: UnitPreferences,
+    ) -> Result<(), Box<dyn std::error::Error>>;
 }
 
 impl UserDatabase for DbConn {
@@ -28,7 +36,7 @@ impl UserDatabase for DbConn {
     ) -> Result<Option<UserEntity>, Box<dyn std::error::Error>> {
         let username = NormalizedString::new(username);
         self.query_single::<UserEntity>(&format!(
-            "SELECT rowid, name, normalized_name, password FROM users WHERE normalized_name = '{}' LIMIT 1",
+            "SELECT rowid, name, normalized_name, password, temperature_unit FROM users WHERE normalized_name = '{}' LIMIT 1",
             *username
         ))
         .await
@@ -43,7 +51,7 @@ impl UserDatabase for DbConn {
         let normalized_name = NormalizedString::new(&name);
         let password = Password::new(password.into());
         Ok(self.query_single::<UserEntity>(&format!(
-            "INSERT INTO users (name, normalized_name, password) VALUES ('{}', '{}', '{}') RETURNING rowid, name, normalized_name, password",
+            "INSERT INTO users (name, normalized_name, password) VALUES ('{}', '{}', '{}') RETURNING rowid, name, normalized_name, password, temperature_unit",
             &name, *normalized_name, password
         ))
         .await?.ok_or("Failed to create user")?)
@@ -58,8 +66,10 @@ impl UserDatabase for DbConn {
     }
 
     async fn get_users(&self) -> Result<Vec<UserEntity>, Box<dyn std::error::Error>> {
-        self.query::<UserEntity>("SELECT rowid, name, normalized_name, password FROM users")
-            .await
+        self.query::<UserEntity>(
+            "SELECT rowid, name, normalized_name, password, temperature_unit FROM users",
+        )
+        .await
     }
 
     async fn change_password(
@@ -86,4 +96,18 @@ impl UserDatabase for DbConn {
         .await?;
         Ok(())
     }
+
+    async fn set_unit_preferences(
+        &self,
+        username: &str,
+        units: UnitPreferences,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let username = NormalizedString::new(username);
+        self.execute(&format!(
+            "UPDATE users SET temperature_unit = '{}' WHERE normalized_name = '{}'",
+            units.temperature.key, *username
+        ))
+        .await?;
+        Ok(())
+    }
 }
@@ -0,0 +1,284 @@
+use crate::{models::db::SensorFeatures, services::comfort_service};
+
+/// How the data of a feature is collected and stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeatureKind {
+    /// Periodic readings of one or more metrics, stored in `sensor_measurements`.
+    Measurement,
+    /// Periods with a start and an optional end, like motion.
+    Events,
+}
+
+/// A single value measured by a feature.
+#[derive(Debug)]
+pub struct Metric {
+    pub name: &'static str,
+    pub label: &'static str,
+    pub unit: &'static str,
+    pub icon: &'static str,
+    pub color: &'static str,
+    pub grid_color: &'static str,
+    /// Units values of the metric can be given in, starting with the stored `unit`.
+    pub units: &'static [Unit],
+}
+
+impl Metric {
+    pub fn unit(&self, key: &str) -> Option<&'static Unit> {
+        self.units.iter().find(|u| u.key == key)
+    }
+}
+
+/// A metric computed from the metrics a feature stores, instead of being stored itself.
+#[derive(Debug)]
+pub struct DerivedMetric {
+    pub metric: Metric,
+    /// Computes the value from the values of a reading, in the order of [`FeatureInfo::metrics`].
+    pub compute: fn(&[Option<f64>]) -> Option<f64>,
+}
+
+impl DerivedMetric {
+    /// Value of the metric rounded to the precision of the sensors.
+    pub fn value(&self, values: &[Option<f64>]) -> Option<f64> {
+        (self.compute)(values).map(|v| (v * 100.0).round() / 100.0)
+    }
+}
+
+/// A unit of a metric, converted to and from the unit the metric is stored in.
+#[derive(Debug)]
+pub struct Unit {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub to_base: fn(f64) -> f64,
+    pub from_base: fn(f64) -> f64,
+}
+
+impl Unit {
+    /// Converts a difference of two values, like a calibration offset, from the stored unit.
+    pub fn difference_from_base(&self, value: f64) -> f64 {
+        (self.from_base)(value) - (self.from_base)(0.0)
+    }
+
+    /// Converts a difference of two values to the stored unit.
+    pub fn difference_to_base(&self, value: f64) -> f64 {
+        (self.to_base)(value) - (self.to_base)(0.0)
+    }
+}
+
+fn identity(value: f64) -> f64 {
+    value
+}
+
+const CELSIUS: Unit = Unit {
+    key: "c",
+    label: "°C",
+    to_base: identity,
+    from_base: identity,
+};
+
+const FAHRENHEIT: Unit = Unit {
+    key: "f",
+    label: "°F",
+    to_base: |f| (f - 32.0) * 5.0 / 9.0,
+    from_base: |c| c * 9.0 / 5.0 + 32.0,
+};
+
+const KELVIN: Unit = Unit {
+    key: "k",
+    label: "K",
+    to_base: |k| k - 273.15,
+    from_base: |c| c + 273.15,
+};
+
+const GRAMS_PER_CUBIC_METRE: Unit = Unit {
+    key: "g-m3",
+    label: "g/m³",
+    to_base: identity,
+    from_base: identity,
+};
+
+const PERCENT: Unit = Unit {
+    key: "percent",
+    label: "%",
+    to_base: identity,
+    from_base: identity,
+};
+
+const FRACTION: Unit = Unit {
+    key: "fraction",
+    label: "0-1",
+    to_base: |v| v * 100.0,
+    from_base: |v| v / 100.0,
+};
+
+/// Units a temperature can be shown in.
+pub const TEMPERATURE_UNITS: &[Unit] = &[CELSIUS, FAHRENHEIT];
+
+/// Units a user wants values shown in, in place of the units they are stored in.
+#[derive(Debug, Clone, Copy)]
+pub struct UnitPreferences {
+    pub temperature: &'static Unit,
+}
+
+impl Default for UnitPreferences {
+    fn default() -> Self {
+        UnitPreferences {
+            temperature: &TEMPERATURE_UNITS[0],
+        }
+    }
+}
+
+impl UnitPreferences {
+    pub fn new(temperature: &str) -> Option<Self> {
+        Some(UnitPreferences {
+            temperature: TEMPERATURE_UNITS.iter().find(|u| u.key == temperature)?,
+        })
+    }
+
+    /// Unit values of `metric` are shown in, the stored one unless the metric can be shown in a preferred unit.
+    pub fn unit(&self, metric: &Metric) -> &'static Unit {
+        metric
+            .unit(self.temperature.key)
+            .unwrap_or(&metric.units[0])
+    }
+
+    /// Stored value of `metric` in its shown unit, rounded to the precision of the sensors.
+    pub fn value(&self, metric: &Metric, value: f64) -> f64 {
+        ((self.unit(metric).from_base)(value) * 100.0).round() / 100.0
+    }
+}
+
+#[derive(Debug)]
+pub struct FeatureInfo {
+    pub feature: SensorFeatures,
+    /// Identifier used in urls and form fields.
+    pub key: &'static str,
+    pub label: &'static str,
+    pub icon: &'static str,
+    pub kind: FeatureKind,
+    /// Seconds between two readings the sensor stores, for periodically sampled features.
+    pub interval: Option<u64>,
+    /// Path the sensor serves the readings or events of the feature at.
+    pub endpoint: &'static str,
+    /// Readings the sensor firmware keeps for measurements, older ones can't be fetched anymore.
+    pub buffer_size: Option<u64>,
+    pub metrics: &'static [Metric],
+    pub derived: &'static [DerivedMetric],
+}
+
+impl FeatureInfo {
+    /// Time between two stored readings above which the readings in between are missing.
+    pub fn gap_threshold(&self) -> Option<u64> {
+        self.interval.map(|interval| interval * 3 / 2)
+    }
+
+    pub fn derived_metric(&self, name: &str) -> Option<&'static DerivedMetric> {
+        self.derived.iter().find(|d| d.metric.name == name)
+    }
+}
+
+/// All the features the system knows how to store and display.
+pub const FEATURES: &[FeatureInfo] = &[
+    FeatureInfo {
+        feature: SensorFeatures::TEMPERATURE,
+        key: "temp",
+        label: "Temperature",
+        icon: "🌡️",
+        kind: FeatureKind::Measurement,
+        interval: Some(60 * 15),
+        endpoint: "dht",
+        buffer_size: Some(150),
+        metrics: &[
+            Metric {
+                name: "temperature",
+                label: "Temperature",
+                unit: "°C",
+                icon: "🌡️",
+                color: "rgba(255, 99, 132, 0.9)",
+                grid_color: "rgba(255, 99, 132, 0.4)",
+                units: &[CELSIUS, FAHRENHEIT, KELVIN],
+            },
+            Metric {
+                name: "humidity",
+                label: "Humidity",
+                unit: "%",
+                icon: "💧",
+                color: "rgba(54, 162, 235, 0.9)",
+                grid_color: "rgba(54, 162, 235, 0.4)",
+                units: &[PERCENT, FRACTION],
+            },
+        ],
+        // computed from the `temperature` and `humidity` above
+        derived: &[
+            DerivedMetric {
+                metric: Metric {
+                    name: "dew-point",
+                    label: "Dew point",
+                    unit: "°C",
+                    icon: "🌫️",
+                    color: "rgba(75, 192, 192, 0.9)",
+                    grid_color: "rgba(75, 192, 192, 0.4)",
+                    units: &[CELSIUS, FAHRENHEIT, KELVIN],
+                },
+                compute: |v| comfort_service::dew_point(v[0]?, v[1]?),
+            },
+            DerivedMetric {
+                metric: Metric {
+                    name: "absolute-humidity",
+                    label: "Absolute humidity",
+                    unit: "g/m³",
+                    icon: "💦",
+                    color: "rgba(153, 102, 255, 0.9)",
+                    grid_color: "rgba(153, 102, 255, 0.4)",
+                    units: &[GRAMS_PER_CUBIC_METRE],
+                },
+                compute: |v| comfort_service::absolute_humidity(v[0]?, v[1]?),
+            },
+            DerivedMetric {
+                metric: Metric {
+                    name: "heat-index",
+                    label: "Heat index",
+                    unit: "°C",
+                    icon: "🥵",
+                    color: "rgba(255, 159, 64, 0.9)",
+                    grid_color: "rgba(255, 159, 64, 0.4)",
+                    units: &[CELSIUS, FAHRENHEIT, KELVIN],
+                },
+                compute: |v| comfort_service::heat_index(v[0]?, v[1]?),
+            },
+        ],
+    },
+    FeatureInfo {
+        feature: SensorFeatures::MOTION,
+        key: "motion",
+        label: "Motion",
+        icon: "🌪️",
+        kind: FeatureKind::Events,
+        interval: None,
+        endpoint: "motion",
+        buffer_size: None,
+        metrics: &[],
+        derived: &[],
+    },
+];
+
+pub fn by_key(key: &str) -> Option<&'static FeatureInfo> {
+    FEATURES.iter().find(|f| f.key == key)
+}
+
+pub fn by_feature(feature: SensorFeatures) -> Option<&'static FeatureInfo> {
+    FEATURES.iter().find(|f| f.feature == feature)
+}
+
+/// Features stored as measurements.
+pub fn measurements() -> impl Iterator<Item = &'static FeatureInfo> {
+    FEATURES
+        .iter()
+        .filter(|f| f.kind == FeatureKind::Measurement)
+}
+
+/// The bits of `features` not described by the registry.
+pub fn unknown(features: SensorFeatures) -> SensorFeatures {
+    FEATURES
+        .iter()
+        .fold(features, |rest, info| rest - info.feature)
+}
@@ -1,19 +1,27 @@
 use axum::{
+    extract::DefaultBodyLimit,
     routing::{delete, get, post, put},
     Extension, Router,
 };
 use axum_server::tls_rustls::RustlsConfig;
-use database::{users::UserDatabase, DbManager, DbPool};
+use database::{scan_history::ScanHistoryDatabase, users::UserDatabase, DbManager, DbPool};
 use models::db::SensorEntity;
 use r2d2_sqlite::SqliteConnectionManager;
-use services::{scanner_service::ScannerService, sensor_data_service::SensorDataService};
-use std::{net::SocketAddr, sync::Arc};
+use services::{
+    notification_service::{forward_state_changes, start_notification_delivery},
+    retention_service::start_measurement_compaction,
+    scanner_service::{ScanOptions, ScannerService},
+    sensor_data_service::SensorDataService,
+    sensor_health_service::start_sensor_health_polling,
+};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::sync::Mutex;
 use tower_http::{services::ServeDir, trace::TraceLayer};
 
 mod api_error;
 mod auth;
 mod database;
+mod features;
 mod models;
 mod services;
 mod ssl;
@@ -61,13 +69,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build()?;
     let runtime = Arc::new(runtime);
     let mut scanner = ScannerService::<SensorEntity>::new(runtime.clone());
-    scanner.init(pool.clone()).await;
+    scanner.load(&pool).await;
+    scanner.init(pool.clone(), ScanOptions::default()).await;
     let scanner = Mutex::new(scanner);
     let scanner = Arc::new(scanner);
+    let scan_interval = pool.get().await?.get_scanner_interval().await?;
+    ScannerService::schedule(
+        scanner.clone(),
+        pool.clone(),
+        scan_interval.map(Duration::from_millis),
+    )
+    .await;
     let mut data_service = SensorDataService::new(runtime, pool.clone());
     data_service.init().await?;
+    data_service.log_state_changes();
+    forward_state_changes(pool.clone(), data_service.subscribe());
     let data_service = Mutex::new(data_service);
     let data_service = Arc::new(data_service);
+    start_sensor_health_polling(pool.clone());
+    start_measurement_compaction(pool.clone());
+    start_notification_delivery(pool.clone());
     // start a task to delete expired tokens
     auth::start_user_session_watchdog(pool.clone());
     // build app
@@ -76,16 +97,71 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         // register our webapp
         .route("/", get(website::home::home))
         .route("/sensors", get(website::sensors::sensors))
+        .route("/sensors/:host", get(website::sensors::sensor_details))
         .route("/sensors/:host", delete(website::sensors::delete_sensor))
         .route("/sensors/:host", post(website::sensors::update_sensor))
         .route("/sensors/:host/sync", post(website::sensors::sync_sensor))
+        .route(
+            "/sensors/:host/calibration",
+            post(website::sensors::update_calibration),
+        )
+        .route(
+            "/sensors/:host/status",
+            get(website::sensors::sensor_status),
+        )
+        .route("/sensors/:host/pair", post(website::sensors::repair_sensor))
+        .route(
+            "/sensors/:host/identify",
+            post(website::sensors::identify_sensor),
+        )
         .route("/scanner", get(website::scanner::scanner))
         .route("/pair/:host", post(website::scanner::pair_sensor))
         .route("/scan", post(website::scanner::scan))
         .route("/scan/cancel", post(website::scanner::cancel))
         .route("/scan/status", get(website::scanner::status_ws))
+        .route("/scan/schedule", post(website::scanner::update_schedule))
+        .route(
+            "/scan/changes/seen",
+            post(website::scanner::mark_changes_seen),
+        )
+        .route("/alerts", get(website::alerts::alerts))
+        .route("/alerts", put(website::alerts::create_alert_rule))
+        .route("/alerts", delete(website::alerts::delete_alert_rule))
         .route("/data", get(website::data::data))
         .route("/data/browse", get(website::data::browse_data::browse_data))
+        .route("/data/export", get(website::data::export::data_export))
+        .route(
+            "/data/export/download",
+            get(website::data::export::download_export),
+        )
+        .route("/data/import", get(website::data::import::data_import))
+        .route(
+            "/data/import",
+            post(website::data::import::import_data)
+                .layer(DefaultBodyLimit::max(website::data::import::UPLOAD_LIMIT)),
+        )
+        .route(
+            "/data/import/preview",
+            post(website::data::import::preview_import)
+                .layer(DefaultBodyLimit::max(website::data::import::UPLOAD_LIMIT)),
+        )
+        .route("/data/gaps", get(website::data::gaps::data_gaps))
+        .route(
+            "/data/retention",
+            get(website::data::retention::data_retention),
+        )
+        .route(
+            "/data/retention",
+            post(website::data::retention::update_retention),
+        )
+        .route(
+            "/data/validation",
+            get(website::data::validation::data_validation),
+        )
+        .route(
+            "/data/validation",
+            post(website::data::validation::update_validation),
+        )
         .route(
             "/data/schedule",
             get(website::data::schedule::data_schedule),
@@ -98,11 +174,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "/data/schedule",
             delete(website::data::schedule::delete_schedule_entry),
         )
+        .route("/data/collect", post(website::data::schedule::collect_now))
         .route("/areas", get(website::areas::areas))
         .route("/areas", put(website::areas::create_area))
         .route("/areas/:id", delete(website::areas::delete_area))
         .route("/areas/:id", post(website::areas::update_area))
         .route("/areas/:id/chart", get(website::areas::area_chart))
+        .route(
+            "/areas/:id/chart/data",
+            get(website::areas::area_chart_data),
+        )
         .route("/system", get(website::system::system))
         .route("/system/users", get(website::system::users::users))
         .route("/system/users", put(website::system::users::create_user))
@@ -114,6 +195,30 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "/system/users/:name",
             delete(website::system::users::delete_user),
         )
+        .route(
+            "/system/users/units",
+            post(website::system::users::update_unit_preferences),
+        )
+        .route(
+            "/system/notifications",
+            get(website::system::notifications::notifications),
+        )
+        .route(
+            "/system/notifications",
+            put(website::system::notifications::create_channel),
+        )
+        .route(
+            "/system/notifications",
+            delete(website::system::notifications::delete_channel),
+        )
+        .route(
+            "/system/notifications/:id/subscription",
+            post(website::system::notifications::update_subscription),
+        )
+        .route(
+            "/system/notifications/:id/test",
+            post(website::system::notifications::test_channel),
+        )
         .route("/logout", post(website::login::logout))
         .layer(axum::middleware::from_fn_with_state(
             pool.clone(),
@@ -1,4 +1,4 @@
-use crate::{database::DbConn, DbPool};
+use crate::{database::DbConn, features::UnitPreferences, DbPool};
 use auth::Token;
 use axum::{
     extract::FromRequestParts,
@@ -22,7 +22,11 @@ impl FromRequestParts<DbPool> for RequestData {
 
     #[doc = " Perform the extraction."]
     #[must_use]
-    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    #[allow(
+        unused_attributes,
+        clippy::type_complexity,
+        clippy::type_repetition_in_bounds
+    )]
     fn from_request_parts<'life0, 'life1, 'async_trait>(
         parts: &'life0 mut Parts,
         state: &'life1 DbPool,
@@ -60,6 +64,13 @@ impl FromRequestParts<DbPool> for RequestData {
     }
 }
 
+impl RequestData {
+    /// Units the values are shown in, the stored ones for anonymous requests.
+    pub fn units(&self) -> UnitPreferences {
+        self.user.as_ref().map(|u| u.units).unwrap_or_default()
+    }
+}
+
 #[derive(Debug, Clone, Default, Deref)]
 pub struct NormalizedString(String);
 
@@ -69,10 +80,24 @@ impl NormalizedString {
     }
 }
 
+/// Encodes a sensor host for use in element ids and url paths.
+pub fn host_id(host: &str) -> String {
+    host.replace('.', "-").replace(':', "_").replace('%', "s")
+}
+
+/// Decodes a host previously encoded with [`host_id`].
+pub fn host_from_id(id: &str) -> String {
+    match id.contains('_') {
+        true => id.replace('_', ":").replace('s', "%").replace('-', "."),
+        false => id.replace('-', "."),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct User {
     pub id: i64,
     pub name: String,
+    pub units: UnitPreferences,
 }
 
 #[derive(Debug, Clone)]
@@ -82,7 +107,18 @@ pub struct Area {
     pub sensors: Vec<SensorEntity>,
 }
 
+/// Time after the last motion in an area during which it is still considered occupied.
+pub const OCCUPANCY_TIMEOUT_SECS: i64 = 10 * 60;
+
 impl Area {
+    /// Whether any of `events` (of this area's sensors) is ongoing or ended recently.
+    pub fn occupied(&self, events: &[db::MotionDataEntry], now: i64) -> bool {
+        events
+            .iter()
+            .filter(|e| self.sensors.iter().any(|s| s.host == e.host))
+            .any(|e| e.end.is_none_or(|end| now - end < OCCUPANCY_TIMEOUT_SECS))
+    }
+
     pub fn features(&self) -> Vec<SensorFeatures> {
         let mut features = self
             .sensors
@@ -95,8 +131,10 @@ impl Area {
 }
 
 pub mod json {
-    use super::db::SensorFeatures;
+    use super::db::{ActiveHours, ScheduleTrigger, SensorFeatures};
+    use crate::services::scanner_service::{ScanOptions, ScanTarget};
     use serde::{Deserialize, Serialize};
+    use std::str::FromStr;
 
     #[derive(Serialize, Deserialize, Debug, Default, Clone)]
     pub struct ErrorResponse {
@@ -108,6 +146,55 @@ pub mod json {
         pub id: String,
     }
 
+    /// Bucketed series of a chart, drawn by the page with one axis per metric.
+    #[derive(Serialize, Debug, Default, Clone)]
+    pub struct ChartDataResponse {
+        /// Bucket length in seconds.
+        pub bucket: u64,
+        pub bucket_label: String,
+        pub from: u64,
+        pub to: u64,
+        pub axes: Vec<ChartAxis>,
+        pub series: Vec<ChartSeries>,
+        /// A single line is drawn per metric, leaving room to shade the range of its buckets.
+        pub bands: bool,
+        /// The buckets come from rollups, which leave out the flagged readings.
+        pub rollup: bool,
+    }
+
+    #[derive(Serialize, Debug, Default, Clone)]
+    pub struct ChartAxis {
+        pub id: String,
+        pub label: String,
+        pub position: String,
+        pub color: String,
+        pub grid_color: String,
+        /// Missing when there are no values to scale the axis to.
+        pub min: Option<f64>,
+        pub max: Option<f64>,
+    }
+
+    /// One metric of one sensor.
+    #[derive(Serialize, Debug, Default, Clone)]
+    pub struct ChartSeries {
+        pub host: String,
+        pub label: String,
+        pub axis: String,
+        pub color: String,
+        /// Line dash pattern telling the sensors of a metric apart.
+        pub dash: Vec<u8>,
+        pub points: Vec<ChartPoint>,
+    }
+
+    /// Aggregated readings of a bucket, `x` in ms and without values where the line breaks.
+    #[derive(Serialize, Debug, Default, Clone, PartialEq)]
+    pub struct ChartPoint {
+        pub x: u64,
+        pub y: Option<f64>,
+        pub min: Option<f64>,
+        pub max: Option<f64>,
+    }
+
     #[derive(Serialize, Deserialize, Debug, Default, Clone)]
     pub struct SensorFormData {
         pub name: String,
@@ -138,11 +225,12 @@ pub mod json {
     #[derive(Debug, Default, Serialize, Deserialize, Clone)]
     pub struct SensorFullResponse {
         pub name: String,
-        pub location: String,
         pub features: u32,
         pub pairing: bool,
         pub paired_keys: u32,
         pub usage: StoreUsage,
+        pub free_mem: u32,
+        pub uptime: f64,
     }
 
     #[derive(Default, Debug, Serialize, Deserialize, Clone, Copy)]
@@ -158,47 +246,736 @@ pub mod json {
         pub measurements: Vec<Measurement>,
     }
 
+    /// A reading of a measurement feature, its values named after the metrics of the feature.
     #[derive(Debug, Default, Serialize, Deserialize, Clone)]
     pub struct Measurement {
         pub timestamp: u64,
-        pub temperature: f32,
-        pub humidity: f32,
+        #[serde(flatten)]
+        pub values: std::collections::HashMap<String, serde_json::Value>,
+    }
+
+    impl Measurement {
+        /// Entries of the metrics of `info` the reading has a value for.
+        pub fn into_entries(
+            self,
+            host: &str,
+            info: &crate::features::FeatureInfo,
+        ) -> Vec<super::db::MeasurementEntry> {
+            info.metrics
+                .iter()
+                .filter_map(|metric| {
+                    Some(super::db::MeasurementEntry {
+                        host: host.to_string(),
+                        feature: info.feature,
+                        metric: metric.name.to_string(),
+                        timestamp: self.timestamp,
+                        value: self.values.get(metric.name)?.as_f64()?,
+                        flag: None,
+                    })
+                })
+                .collect()
+        }
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct MotionEventsResponse {
+        pub events: Vec<MotionEvent>,
+    }
+
+    /// A motion period reported by the sensor, `end` is missing while it is ongoing.
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct MotionEvent {
+        pub start: i64,
+        pub end: Option<i64>,
     }
 
     #[derive(Debug, Default, Serialize, Deserialize, Clone)]
     pub struct ScheduleEntryFormData {
-        #[serde(rename = "features-temp")]
-        pub features_temp: Option<String>,
-        #[serde(rename = "features-motion")]
-        pub features_motion: Option<String>,
+        pub target: String,
+        /// `interval` or `cron`.
+        pub mode: String,
         pub interval: String,
+        pub offset: String,
+        pub cron: String,
+        #[serde(rename = "active-from")]
+        pub active_from: String,
+        #[serde(rename = "active-to")]
+        pub active_to: String,
+        /// Checked `features-{key}` boxes of the registered features.
+        #[serde(flatten)]
+        pub features: std::collections::HashMap<String, String>,
+    }
+
+    /// Parses a `hh:mm[:ss]` time input into seconds.
+    fn parse_time(s: &str) -> Result<u64, anyhow::Error> {
+        let parts = s
+            .split(':')
+            .map(|p| p.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()?;
+        match parts[..] {
+            [h, m] if m < 60 => Ok(h * 3600 + m * 60),
+            [h, m, s] if m < 60 && s < 60 => Ok(h * 3600 + m * 60 + s),
+            _ => anyhow::bail!("Invalid time `{}`", s),
+        }
     }
 
     impl TryInto<super::db::DataScheduleEntry> for ScheduleEntryFormData {
         type Error = anyhow::Error;
 
         fn try_into(self) -> Result<super::db::DataScheduleEntry, Self::Error> {
-            let mut features = SensorFeatures::empty();
-            if self.features_temp.is_some() {
-                features |= SensorFeatures::TEMPERATURE;
-            }
-            if self.features_motion.is_some() {
-                features |= SensorFeatures::MOTION;
-            }
-            let interval_ms = self.interval.splitn(3, ':').enumerate().try_fold(
-                0u64,
-                |mut interval, (i, s)| {
-                    interval += s.parse::<u64>()? * 60u64.pow(2 - i as u32);
-                    Result::<_, anyhow::Error>::Ok(interval)
-                },
-            )? * 1000;
-            Ok(crate::models::db::DataScheduleEntry {
+            let features = crate::features::FEATURES
+                .iter()
+                .filter(|f| self.features.contains_key(&format!("features-{}", f.key)))
+                .fold(SensorFeatures::empty(), |features, f| features | f.feature);
+            if features.is_empty() {
+                anyhow::bail!("Select at least one feature");
+            }
+            let trigger = match self.mode.as_str() {
+                "interval" => {
+                    let interval_ms = parse_time(&self.interval)? * 1000;
+                    if interval_ms == 0 {
+                        anyhow::bail!("Interval must be greater than zero");
+                    }
+                    let offset_ms = match self.offset.is_empty() {
+                        true => 0,
+                        false => parse_time(&self.offset)? * 1000,
+                    };
+                    if offset_ms >= interval_ms {
+                        anyhow::bail!("Offset must be shorter than the interval");
+                    }
+                    ScheduleTrigger::Interval {
+                        interval_ms,
+                        offset_ms,
+                    }
+                }
+                "cron" => {
+                    // the usual 5 fields run at the start of the minute
+                    let expression = match self.cron.split_whitespace().count() {
+                        5 => format!("0 {}", self.cron.trim()),
+                        _ => self.cron.trim().to_string(),
+                    };
+                    cron::Schedule::from_str(&expression)
+                        .map_err(|e| anyhow::anyhow!("Invalid cron expression: {}", e))?;
+                    ScheduleTrigger::Cron(expression)
+                }
+                _ => anyhow::bail!("Invalid schedule mode `{}`", self.mode),
+            };
+            let active_hours = match (self.active_from.is_empty(), self.active_to.is_empty()) {
+                (true, true) => None,
+                (false, false) => {
+                    let from = (parse_time(&self.active_from)? / 60) as u32;
+                    let to = (parse_time(&self.active_to)? / 60) as u32;
+                    if from == to || from >= 24 * 60 || to > 24 * 60 {
+                        anyhow::bail!("Invalid active hours");
+                    }
+                    Some(ActiveHours { from, to })
+                }
+                _ => anyhow::bail!("Set both ends of the active hours"),
+            };
+            let entry = super::db::DataScheduleEntry {
+                id: 0,
                 features,
-                interval_ms,
+                trigger,
+                active_hours,
+                target: self.target.parse()?,
+            };
+            if entry.next_run(chrono::Local::now()).is_none() {
+                anyhow::bail!("The schedule never runs");
+            }
+            Ok(entry)
+        }
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct CollectFormData {
+        pub target: String,
+        /// Checked `features-{key}` boxes, none collects every feature of the sensors.
+        #[serde(flatten)]
+        pub features: std::collections::HashMap<String, String>,
+    }
+
+    impl TryInto<(SensorFeatures, super::db::ScheduleTarget)> for CollectFormData {
+        type Error = anyhow::Error;
+
+        fn try_into(self) -> Result<(SensorFeatures, super::db::ScheduleTarget), Self::Error> {
+            let features = crate::features::FEATURES
+                .iter()
+                .filter(|f| self.features.contains_key(&format!("features-{}", f.key)))
+                .fold(SensorFeatures::empty(), |features, f| features | f.feature);
+            let features = match features.is_empty() {
+                true => SensorFeatures::all(),
+                false => features,
+            };
+            Ok((features, self.target.parse()?))
+        }
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct ScanFormData {
+        pub targets: String,
+        pub interface: String,
+        pub concurrency: String,
+        pub timeout: String,
+    }
+
+    impl TryInto<ScanOptions> for ScanFormData {
+        type Error = anyhow::Error;
+
+        fn try_into(self) -> Result<ScanOptions, Self::Error> {
+            let targets = self
+                .targets
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|t| !t.is_empty())
+                .map(|t| t.parse::<ScanTarget>().map_err(|e| anyhow::anyhow!(e)))
+                .collect::<Result<Vec<_>, _>>()?;
+            let interface = Some(self.interface).filter(|i| !i.is_empty());
+            let concurrency = self.concurrency.parse::<usize>()?;
+            if !(1..=256).contains(&concurrency) {
+                anyhow::bail!("Concurrency must be between 1 and 256");
+            }
+            let timeout = self.timeout.parse::<u64>()?;
+            if !(100..=30_000).contains(&timeout) {
+                anyhow::bail!("Timeout must be between 100 and 30000 ms");
+            }
+            Ok(ScanOptions {
+                targets,
+                interface,
+                concurrency,
+                timeout: std::time::Duration::from_millis(timeout),
+            })
+        }
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct ScanScheduleFormData {
+        pub interval: String,
+    }
+
+    impl TryInto<Option<std::time::Duration>> for ScanScheduleFormData {
+        type Error = anyhow::Error;
+
+        fn try_into(self) -> Result<Option<std::time::Duration>, Self::Error> {
+            let minutes = self.interval.parse::<u64>()?;
+            if minutes > 7 * 24 * 60 {
+                anyhow::bail!("Interval must be at most a week");
+            }
+            Ok(Some(minutes)
+                .filter(|m| *m > 0)
+                .map(|m| std::time::Duration::from_secs(m * 60)))
+        }
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct AlertRuleFormData {
+        pub name: String,
+        pub target: String,
+        pub condition: String,
+        pub metric: String,
+        pub threshold: String,
+        pub duration: String,
+        pub hysteresis: String,
+        pub cooldown: String,
+    }
+
+    impl TryInto<super::db::AlertRule> for AlertRuleFormData {
+        type Error = anyhow::Error;
+
+        fn try_into(self) -> Result<super::db::AlertRule, Self::Error> {
+            use super::db::AlertCondition;
+
+            let name = self.name.trim().to_string();
+            if name.is_empty() {
+                anyhow::bail!("Name is required");
+            }
+            let condition = match self.condition.as_str() {
+                "offline" => AlertCondition::Offline,
+                kind @ ("above" | "below") => {
+                    let (feature, metric) = self
+                        .metric
+                        .split_once(':')
+                        .and_then(|(feature, metric)| {
+                            let info = crate::features::by_key(feature)?;
+                            let metric = info.metrics.iter().find(|m| m.name == metric)?;
+                            Some((info.feature, metric.name.to_string()))
+                        })
+                        .ok_or(anyhow::anyhow!("Invalid metric `{}`", self.metric))?;
+                    let threshold = self
+                        .threshold
+                        .trim()
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|v| v.is_finite())
+                        .ok_or(anyhow::anyhow!("Invalid threshold `{}`", self.threshold))?;
+                    match kind {
+                        "above" => AlertCondition::Above {
+                            feature,
+                            metric,
+                            threshold,
+                        },
+                        _ => AlertCondition::Below {
+                            feature,
+                            metric,
+                            threshold,
+                        },
+                    }
+                }
+                _ => anyhow::bail!("Invalid condition `{}`", self.condition),
+            };
+            let minutes = |value: &str| -> Result<u64, anyhow::Error> {
+                match value.trim() {
+                    "" => Ok(0),
+                    value => value
+                        .parse::<u64>()?
+                        .checked_mul(60)
+                        .ok_or(anyhow::anyhow!("Invalid minutes `{}`", value)),
+                }
+            };
+            let hysteresis = match self.hysteresis.trim() {
+                "" => 0.0,
+                value => value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|v| v.is_finite())
+                    .ok_or(anyhow::anyhow!("Invalid hysteresis `{}`", value))?,
+            };
+            if hysteresis < 0.0 {
+                anyhow::bail!("Hysteresis can't be negative");
+            }
+            Ok(super::db::AlertRule {
+                id: 0,
+                name,
+                condition,
+                duration_s: minutes(&self.duration)?,
+                hysteresis,
+                cooldown_s: minutes(&self.cooldown)?,
+                target: self.target.parse()?,
+            })
+        }
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct NotificationChannelFormData {
+        pub name: String,
+        pub kind: String,
+        pub url: String,
+        #[serde(default)]
+        pub credentials: String,
+        #[serde(default)]
+        pub sender: String,
+        #[serde(default)]
+        pub template: String,
+    }
+
+    impl TryInto<super::db::NotificationChannel> for NotificationChannelFormData {
+        type Error = anyhow::Error;
+
+        fn try_into(self) -> Result<super::db::NotificationChannel, Self::Error> {
+            use super::db::{ChannelKind, DEFAULT_TEMPLATE};
+
+            let name = self.name.trim().to_string();
+            if name.is_empty() {
+                anyhow::bail!("Name is required");
+            }
+            let kind = self.kind.parse::<ChannelKind>()?;
+            let url = self.url.trim().to_string();
+            match kind {
+                ChannelKind::Smtp => {
+                    let (host, port) = url
+                        .rsplit_once(':')
+                        .ok_or(anyhow::anyhow!("SMTP server must be `host:port`"))?;
+                    if host.is_empty() || port.parse::<u16>().is_err() {
+                        anyhow::bail!("SMTP server must be `host:port`");
+                    }
+                }
+                _ => {
+                    let parsed = reqwest::Url::parse(&url)?;
+                    if !matches!(parsed.scheme(), "http" | "https") {
+                        anyhow::bail!("URL must be http or https");
+                    }
+                }
+            }
+            let optional = |value: String| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+            let sender = optional(self.sender);
+            if let Some(sender) = &sender {
+                if !sender.contains('@') || sender.contains(['\r', '\n', '<', '>']) {
+                    anyhow::bail!("Invalid mail sender `{}`", sender);
+                }
+            }
+            let template = match self.template.trim() {
+                "" => DEFAULT_TEMPLATE.to_string(),
+                _ => self.template.replace("\r\n", "\n"),
+            };
+            Ok(super::db::NotificationChannel {
+                id: 0,
+                name,
+                kind,
+                url,
+                credentials: optional(self.credentials),
+                sender,
+                template,
+            })
+        }
+    }
+
+    /// Checked topics come in as `topic-{key}` fields.
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct NotificationSubscriptionFormData {
+        #[serde(default)]
+        pub address: String,
+        #[serde(flatten)]
+        pub topics: std::collections::HashMap<String, String>,
+    }
+
+    impl NotificationSubscriptionFormData {
+        pub fn into_subscription(
+            self,
+            user_id: i64,
+            channel: &super::db::NotificationChannel,
+        ) -> Result<super::db::NotificationSubscription, anyhow::Error> {
+            use super::db::{ChannelKind, NotificationTopics, NOTIFICATION_TOPICS};
+
+            let topics = NOTIFICATION_TOPICS
+                .iter()
+                .filter(|(_, key, _)| self.topics.contains_key(&format!("topic-{}", key)))
+                .fold(NotificationTopics::empty(), |topics, (topic, _, _)| {
+                    topics | *topic
+                });
+            let address = Some(self.address.trim().to_string()).filter(|a| !a.is_empty());
+            match &address {
+                None if channel.kind == ChannelKind::Smtp && !topics.is_empty() => {
+                    anyhow::bail!("Mail channels need an address")
+                }
+                Some(address)
+                    if !address.contains('@') || address.contains(['\r', '\n', '<', '>']) =>
+                {
+                    anyhow::bail!("Invalid mail address `{}`", address)
+                }
+                _ => {}
+            }
+            Ok(super::db::NotificationSubscription {
+                user_id,
+                channel_id: channel.id,
+                topics,
+                address: address.filter(|_| channel.kind == ChannelKind::Smtp),
+            })
+        }
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct ExportFormData {
+        pub feature: String,
+        pub format: String,
+        pub target: String,
+        #[serde(default)]
+        pub from: String,
+        #[serde(default)]
+        pub to: String,
+        pub timezone: String,
+    }
+
+    impl TryInto<super::db::ExportQuery> for ExportFormData {
+        type Error = anyhow::Error;
+
+        fn try_into(self) -> Result<super::db::ExportQuery, Self::Error> {
+            use super::db::DataTimezone;
+
+            let info = crate::features::by_key(&self.feature)
+                .filter(|info| info.kind == crate::features::FeatureKind::Measurement)
+                .ok_or(anyhow::anyhow!("Invalid feature `{}`", self.feature))?;
+            let timezone = self.timezone.parse::<DataTimezone>()?;
+            let from = timezone.parse_time(&self.from)?;
+            let to = timezone.parse_time(&self.to)?;
+            if let (Some(from), Some(to)) = (from, to) {
+                if from >= to {
+                    anyhow::bail!("The range must end after it starts");
+                }
+            }
+            Ok(super::db::ExportQuery {
+                info,
+                format: self.format.parse()?,
+                target: self.target.parse()?,
+                from,
+                to,
+                timezone,
+            })
+        }
+    }
+
+    /// Metric columns come in as `column-{feature}-{metric}` and `unit-{feature}-{metric}` fields.
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct ImportFormData {
+        pub host: String,
+        pub feature: String,
+        pub delimiter: String,
+        /// Checkbox, present when the file starts with a header row.
+        #[serde(default)]
+        pub header: String,
+        #[serde(rename = "timestamp-column")]
+        pub timestamp_column: String,
+        #[serde(rename = "timestamp-format")]
+        pub timestamp_format: String,
+        #[serde(rename = "timestamp-pattern", default)]
+        pub timestamp_pattern: String,
+        pub timezone: String,
+        #[serde(flatten)]
+        pub mapping: std::collections::HashMap<String, String>,
+    }
+
+    impl TryInto<super::db::ImportOptions> for ImportFormData {
+        type Error = anyhow::Error;
+
+        fn try_into(self) -> Result<super::db::ImportOptions, Self::Error> {
+            use super::db::{ImportColumn, TimestampFormat};
+
+            let info = crate::features::by_key(&self.feature)
+                .filter(|info| info.kind == crate::features::FeatureKind::Measurement)
+                .ok_or(anyhow::anyhow!("Invalid feature `{}`", self.feature))?;
+            let delimiter = match self.delimiter.as_str() {
+                "comma" => b',',
+                "semicolon" => b';',
+                "tab" => b'\t',
+                _ => anyhow::bail!("Invalid delimiter `{}`", self.delimiter),
+            };
+            let timestamp_column = self.timestamp_column.trim().to_string();
+            if timestamp_column.is_empty() {
+                anyhow::bail!("Timestamp column is required");
+            }
+            let timestamp_format = match self.timestamp_format.as_str() {
+                "unix" => TimestampFormat::Unix,
+                "unix-ms" => TimestampFormat::UnixMillis,
+                "rfc3339" => TimestampFormat::Rfc3339,
+                "pattern" => match self.timestamp_pattern.trim() {
+                    "" => anyhow::bail!("Timestamp pattern is required"),
+                    pattern => TimestampFormat::Pattern(pattern.to_string()),
+                },
+                _ => anyhow::bail!("Invalid timestamp format `{}`", self.timestamp_format),
+            };
+            let mut columns = vec![];
+            for metric in info.metrics {
+                let field = |prefix: &str| {
+                    self.mapping
+                        .get(&format!("{}-{}-{}", prefix, info.key, metric.name))
+                        .map(|v| v.trim())
+                        .unwrap_or_default()
+                };
+                let column = field("column");
+                if column.is_empty() {
+                    continue;
+                }
+                let unit = metric
+                    .unit(field("unit"))
+                    .ok_or(anyhow::anyhow!("Invalid unit of {}", metric.label))?;
+                columns.push(ImportColumn {
+                    metric,
+                    column: column.to_string(),
+                    unit,
+                });
+            }
+            if columns.is_empty() {
+                anyhow::bail!("Map a column to at least one metric");
+            }
+            Ok(super::db::ImportOptions {
+                info,
+                host: self.host,
+                delimiter,
+                has_header: !self.header.is_empty(),
+                timestamp_column,
+                timestamp_format,
+                timezone: self.timezone.parse()?,
+                columns,
+            })
+        }
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct RetentionFormData {
+        #[serde(rename = "raw-days")]
+        pub raw_days: String,
+        #[serde(rename = "hourly-days")]
+        pub hourly_days: String,
+        #[serde(rename = "daily-days")]
+        pub daily_days: String,
+    }
+
+    impl TryInto<super::db::RetentionPolicy> for RetentionFormData {
+        type Error = anyhow::Error;
+
+        fn try_into(self) -> Result<super::db::RetentionPolicy, Self::Error> {
+            let raw_days = self.raw_days.trim().parse::<u32>()?;
+            let hourly_days = self.hourly_days.trim().parse::<u32>()?;
+            let daily_days = Some(self.daily_days.trim())
+                .filter(|d| !d.is_empty())
+                .map(str::parse::<u32>)
+                .transpose()?;
+            if raw_days < 1 {
+                anyhow::bail!("Raw data must be kept for at least a day");
+            }
+            if hourly_days < raw_days {
+                anyhow::bail!("Hourly data must be kept at least as long as raw data");
+            }
+            if daily_days.is_some_and(|d| d < hourly_days) {
+                anyhow::bail!("Daily data must be kept at least as long as hourly data");
+            }
+            Ok(super::db::RetentionPolicy {
+                raw_days,
+                hourly_days,
+                daily_days,
             })
         }
     }
 
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct CalibrationFormData {
+        /// `offset-{feature}-{metric}` and `scale-{feature}-{metric}` of every metric of the sensor,
+        /// offsets in the units the user sees.
+        #[serde(flatten)]
+        pub fields: std::collections::HashMap<String, String>,
+    }
+
+    impl CalibrationFormData {
+        pub fn calibrations(
+            &self,
+            sensor: &super::db::SensorEntity,
+            units: crate::features::UnitPreferences,
+        ) -> Result<Vec<super::db::Calibration>, anyhow::Error> {
+            let mut calibrations = vec![];
+            for info in
+                crate::features::measurements().filter(|f| sensor.features.contains(f.feature))
+            {
+                for metric in info.metrics {
+                    let field = |name: &str, default: f64| {
+                        let value = self
+                            .fields
+                            .get(&format!("{}-{}-{}", name, info.key, metric.name))
+                            .map(|v| v.trim())
+                            .filter(|v| !v.is_empty());
+                        match value {
+                            None => Ok(default),
+                            Some(value) => {
+                                value.parse::<f64>().ok().filter(|v| v.is_finite()).ok_or(
+                                    anyhow::anyhow!(
+                                        "Invalid {} {} `{}`",
+                                        metric.label.to_lowercase(),
+                                        name,
+                                        value
+                                    ),
+                                )
+                            }
+                        }
+                    };
+                    let offset = field("offset", 0.0)?;
+                    let scale = field("scale", 1.0)?;
+                    // a scale flipping the readings would turn the minimums into maximums
+                    if scale <= 0.0 {
+                        anyhow::bail!("{} scale must be greater than zero", metric.label);
+                    }
+                    calibrations.push(super::db::Calibration {
+                        host: sensor.host.clone(),
+                        feature: info.feature,
+                        metric: metric.name.to_string(),
+                        offset: units.unit(metric).difference_to_base(offset),
+                        scale,
+                    });
+                }
+            }
+            Ok(calibrations)
+        }
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct ValidationFormData {
+        /// `min-`, `max-`, `rate-`, `window-` and `deviation-{feature}-{metric}` of every metric,
+        /// values in the units the user sees.
+        #[serde(flatten)]
+        pub fields: std::collections::HashMap<String, String>,
+    }
+
+    impl ValidationFormData {
+        pub fn rules(
+            &self,
+            units: crate::features::UnitPreferences,
+        ) -> Result<Vec<super::db::ValidationRule>, anyhow::Error> {
+            let mut rules = vec![];
+            for info in crate::features::measurements() {
+                for metric in info.metrics {
+                    let field = |name: &str| {
+                        let value = self
+                            .fields
+                            .get(&format!("{}-{}-{}", name, info.key, metric.name))
+                            .map(|v| v.trim())
+                            .filter(|v| !v.is_empty());
+                        value
+                            .map(|value| {
+                                value.parse::<f64>().ok().filter(|v| v.is_finite()).ok_or(
+                                    anyhow::anyhow!(
+                                        "Invalid {} {} `{}`",
+                                        metric.label.to_lowercase(),
+                                        name,
+                                        value
+                                    ),
+                                )
+                            })
+                            .transpose()
+                    };
+                    let unit = units.unit(metric);
+                    let min = field("min")?.map(unit.to_base);
+                    let max = field("max")?.map(unit.to_base);
+                    let max_rate = field("rate")?.map(|v| unit.difference_to_base(v));
+                    let max_deviation = field("deviation")?.map(|v| unit.difference_to_base(v));
+                    let median_window = field("window")?.unwrap_or_default();
+                    if let (Some(min), Some(max)) = (min, max) {
+                        if min >= max {
+                            anyhow::bail!("{} minimum must be below its maximum", metric.label);
+                        }
+                    }
+                    if max_rate.is_some_and(|v| v <= 0.0) {
+                        anyhow::bail!("{} rate of change must be greater than zero", metric.label);
+                    }
+                    if max_deviation.is_some_and(|v| v <= 0.0) {
+                        anyhow::bail!("{} deviation must be greater than zero", metric.label);
+                    }
+                    // the median of fewer readings can't tell a spike from a change
+                    if median_window.fract() != 0.0
+                        || median_window < 0.0
+                        || (max_deviation.is_some() && median_window < 3.0)
+                    {
+                        anyhow::bail!(
+                            "{} median window must be a whole number of at least 3 readings",
+                            metric.label
+                        );
+                    }
+                    rules.push(super::db::ValidationRule {
+                        feature: info.feature,
+                        metric: metric.name.to_string(),
+                        min,
+                        max,
+                        max_rate,
+                        median_window: median_window as usize,
+                        max_deviation,
+                    });
+                }
+            }
+            Ok(rules)
+        }
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct IdentifyFormData {
+        pub seconds: String,
+    }
+
+    impl TryInto<std::time::Duration> for IdentifyFormData {
+        type Error = anyhow::Error;
+
+        fn try_into(self) -> Result<std::time::Duration, Self::Error> {
+            let seconds = self.seconds.trim().parse::<u64>()?;
+            if !(1..=300).contains(&seconds) {
+                anyhow::bail!("Identify time must be between 1 and 300 seconds");
+            }
+            Ok(std::time::Duration::from_secs(seconds))
+        }
+    }
+
     #[derive(Debug, Default, Serialize, Deserialize, Clone)]
     pub struct AreaFormData {
         pub name: String,
@@ -207,8 +984,9 @@ pub mod json {
 
 pub mod auth {
     use super::NormalizedString;
-    use super::{db::UserEntity, User};
+    use super::{db::UserEntity, UnitPreferences, User};
     use crate::database::user_sessions::UserSessionDatabase;
+    use crate::database::users::UserDatabase;
     use crate::database::DbConn;
     use axum::http::HeaderMap;
     use axum::{extract::FromRequestParts, http::request::Parts};
@@ -302,7 +1080,17 @@ pub mod auth {
                 return Ok(None);
             }
 
-            Ok(Some(claims.into()))
+            // preferences can change while the session lasts
+            let units = conn
+                .get_user(&claims.sub)
+                .await?
+                .map(|u| u.units)
+                .unwrap_or_default();
+
+            Ok(Some(User {
+                units,
+                ..claims.into()
+            }))
         }
     }
 
@@ -310,7 +1098,6 @@ pub mod auth {
         type Rejection = StatusCode;
 
         #[doc = " Perform the extraction."]
-        #[must_use]
         #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
         fn from_request_parts<'life0, 'life1, 'async_trait>(
             parts: &'life0 mut Parts,
@@ -427,6 +1214,7 @@ pub mod auth {
             User {
                 id: val.acs,
                 name: val.sub,
+                units: UnitPreferences::default(),
             }
         }
     }
@@ -435,12 +1223,19 @@ pub mod auth {
 pub mod db {
     use super::{
         auth::{Password, Token},
-        json::SensorDto,
+        json::{SensorDto, SensorFullResponse, StoreUsage},
         NormalizedString, User,
     };
     use crate::{
-        database::{sensors::SensorDatabase, FromRow},
-        services::{scanner_service::Scannable, sensor_service::SensorService},
+        database::{scan_history::ScanHistoryDatabase, sensors::SensorDatabase, FromRow},
+        features::UnitPreferences,
+        services::{
+            scanner_service::{Scannable, ScannerResult},
+            sensor_service::{sensor_client, SensorService, INFO_ATTEMPTS},
+        },
+    };
+    use chrono::{
+        DateTime, Local, LocalResult, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Timelike,
     };
     use r2d2_sqlite::rusqlite;
     use std::str::FromStr;
@@ -451,6 +1246,7 @@ pub mod db {
         pub name: String,
         pub normalized_name: NormalizedString,
         pub password: Password,
+        pub units: UnitPreferences,
     }
 
     impl FromRow for UserEntity {
@@ -461,6 +1257,7 @@ pub mod db {
                 normalized_name: NormalizedString::new(row.get::<_, String>(2)?),
                 password: Password::from_str(&row.get::<_, String>(3)?)
                     .map_err(|_| rusqlite::Error::InvalidQuery)?,
+                units: UnitPreferences::new(&row.get::<_, String>(4)?).unwrap_or_default(),
             })
         }
     }
@@ -470,6 +1267,7 @@ pub mod db {
             User {
                 id: val.id,
                 name: val.name,
+                units: val.units,
             }
         }
     }
@@ -510,14 +1308,13 @@ pub mod db {
 
     impl Scannable for SensorEntity {
         type Error = String;
+        const ATTEMPTS: usize = INFO_ATTEMPTS;
 
-        fn scan(
-            client: &reqwest::Client,
+        async fn scan(
             host: &str,
-        ) -> impl std::future::Future<
-            Output = Result<Result<Self, Self::Error>, Box<dyn std::error::Error + Send + Sync>>,
-        > + Send {
-            client.get_sensor(host)
+            timeout: std::time::Duration,
+        ) -> Result<Result<Self, Self::Error>, Box<dyn std::error::Error + Send + Sync>> {
+            sensor_client(host).get_sensor(host, timeout).await
         }
 
         async fn check(
@@ -535,6 +1332,117 @@ pub mod db {
             self.area = sensor.as_ref().and_then(|s| s.area.clone());
             Ok(())
         }
+
+        async fn save_result(
+            result: &ScannerResult<Self>,
+            hosts: &[String],
+            pool: &crate::database::DbPool,
+        ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            let conn = pool.get().await.map_err(|e| e.to_string())?;
+            let previous = match conn.get_scans(1).await?.first() {
+                Some(scan) => conn.get_scan_devices(scan.id).await?,
+                None => vec![],
+            };
+            let paired = conn.get_sensors().await.map_err(|e| e.to_string())?;
+            let changes = scan_changes(&result.scanned, &previous, &paired, hosts);
+            for change in changes
+                .iter()
+                .filter(|c| c.kind == ScanChangeKind::NewDevice)
+            {
+                crate::services::notification_service::notify_or_log(
+                    pool,
+                    Notification::new(
+                        NotificationTopics::NEW_DEVICE,
+                        format!("Unknown device {} found", change.name),
+                        format!("{} answered the scan of {}", change.host, result.target),
+                    ),
+                )
+                .await;
+            }
+            conn.create_scan(
+                ScanHistoryEntry {
+                    id: 0,
+                    created: result.created.timestamp(),
+                    duration_ms: result.duration.num_milliseconds(),
+                    target: result.target.clone(),
+                    found: result.scanned.len() as u32,
+                },
+                result.scanned.clone(),
+                changes,
+            )
+            .await?;
+            Ok(())
+        }
+
+        async fn load_result(
+            pool: &crate::database::DbPool,
+        ) -> Result<Option<ScannerResult<Self>>, Box<dyn std::error::Error + Send + Sync>> {
+            let conn = pool.get().await.map_err(|e| e.to_string())?;
+            let Some(scan) = conn.get_scans(1).await?.into_iter().next() else {
+                return Ok(None);
+            };
+            let mut scanned = conn.get_scan_devices(scan.id).await?;
+            for sensor in scanned.iter_mut() {
+                sensor.check(pool).await?;
+            }
+            Ok(Some(ScannerResult {
+                scanned,
+                target: scan.target,
+                created: chrono::DateTime::from_timestamp(scan.created, 0).unwrap_or_default(),
+                duration: chrono::Duration::milliseconds(scan.duration_ms),
+            }))
+        }
+    }
+
+    /// Compares a scan with the previous one, reporting only transitions:
+    /// unpaired devices that just appeared, paired sensors that were found
+    /// previously but not anymore, and paired sensors that show up on a new host.
+    fn scan_changes(
+        scanned: &[SensorEntity],
+        previous: &[SensorEntity],
+        paired: &[SensorEntity],
+        hosts: &[String],
+    ) -> Vec<ScanChange> {
+        let found = |devices: &[SensorEntity], host: &str| devices.iter().any(|d| d.host == host);
+        let mut missing = paired
+            .iter()
+            .filter(|s| !found(scanned, &s.host))
+            .collect::<Vec<_>>();
+        let mut changes = vec![];
+        for device in scanned
+            .iter()
+            .filter(|d| d.pair_id.is_none() && !found(previous, &d.host))
+        {
+            match missing.iter().position(|s| s.name == device.name) {
+                Some(i) => changes.push(ScanChange {
+                    created: 0,
+                    kind: ScanChangeKind::HostChanged,
+                    host: device.host.clone(),
+                    name: device.name.clone(),
+                    previous_host: Some(missing.remove(i).host.clone()),
+                }),
+                None => changes.push(ScanChange {
+                    created: 0,
+                    kind: ScanChangeKind::NewDevice,
+                    host: device.host.clone(),
+                    name: device.name.clone(),
+                    previous_host: None,
+                }),
+            }
+        }
+        for sensor in missing
+            .into_iter()
+            .filter(|s| found(previous, &s.host) && hosts.contains(&s.host))
+        {
+            changes.push(ScanChange {
+                created: 0,
+                kind: ScanChangeKind::Disappeared,
+                host: sensor.host.clone(),
+                name: sensor.name.clone(),
+                previous_host: None,
+            });
+        }
+        changes
     }
 
     impl FromRow for SensorEntity {
@@ -562,52 +1470,1719 @@ pub mod db {
 
     pub type DataSchedule = Vec<DataScheduleEntry>;
 
-    #[derive(Clone, Copy, Debug, PartialEq)]
-    pub struct DataScheduleEntry {
-        pub features: SensorFeatures,
-        pub interval_ms: u64,
+    /// What a collection rule applies to, more specific targets override the less specific ones.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub enum ScheduleTarget {
+        #[default]
+        All,
+        Area(i64),
+        Sensor(String),
     }
 
-    impl FromRow for DataScheduleEntry {
-        fn from_row(row: &r2d2_sqlite::rusqlite::Row) -> r2d2_sqlite::rusqlite::Result<Self> {
-            Ok(DataScheduleEntry {
-                features: SensorFeatures::from_bits_retain(row.get::<_, u64>(0)? as u32),
-                interval_ms: row.get::<_, u64>(1)?,
-            })
+    impl ScheduleTarget {
+        pub fn matches(&self, sensor: &SensorEntity) -> bool {
+            match self {
+                ScheduleTarget::All => true,
+                ScheduleTarget::Area(id) => sensor.area.as_ref().is_some_and(|a| a.id == *id),
+                ScheduleTarget::Sensor(host) => sensor.host == *host,
+            }
+        }
+
+        fn specificity(&self) -> u8 {
+            match self {
+                ScheduleTarget::All => 0,
+                ScheduleTarget::Area(_) => 1,
+                ScheduleTarget::Sensor(_) => 2,
+            }
         }
     }
 
-    #[derive(Debug, Clone)]
-    pub struct TempDataEntry {
-        pub host: String,
-        pub timestamp: u64,
-        pub temperature: f32,
-        pub humidity: f32,
+    impl std::fmt::Display for ScheduleTarget {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ScheduleTarget::All => write!(f, "all"),
+                ScheduleTarget::Area(id) => write!(f, "area:{}", id),
+                ScheduleTarget::Sensor(host) => write!(f, "sensor:{}", host),
+            }
+        }
     }
 
-    impl FromRow for TempDataEntry {
-        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
-            Ok(TempDataEntry {
-                host: row.get::<_, String>(0)?,
-                timestamp: row.get::<_, u64>(1)?,
-                temperature: row.get::<_, f64>(2)? as f32,
-                humidity: row.get::<_, f64>(3)? as f32,
-            })
+    impl FromStr for ScheduleTarget {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.split_once(':') {
+                None if s == "all" => Ok(ScheduleTarget::All),
+                Some(("area", id)) => Ok(ScheduleTarget::Area(id.parse()?)),
+                Some(("sensor", host)) if !host.is_empty() => {
+                    Ok(ScheduleTarget::Sensor(host.to_string()))
+                }
+                _ => Err(anyhow::anyhow!("Invalid schedule target `{}`", s)),
+            }
         }
     }
 
-    #[derive(Debug, Clone)]
-    pub struct AreaEntity {
+    /// When a collection rule runs.
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum ScheduleTrigger {
+        /// Every `interval_ms`, aligned to the epoch and shifted by `offset_ms`.
+        Interval { interval_ms: u64, offset_ms: u64 },
+        /// A cron expression with seconds, in local time.
+        Cron(String),
+    }
+
+    /// Time of day in local minutes during which a rule runs, wrapping over midnight when `from > to`.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct ActiveHours {
+        pub from: u32,
+        pub to: u32,
+    }
+
+    impl ActiveHours {
+        pub fn contains<Tz: TimeZone>(&self, at: &DateTime<Tz>) -> bool {
+            let minute = at.hour() * 60 + at.minute();
+            match self.from <= self.to {
+                true => self.from <= minute && minute < self.to,
+                false => minute >= self.from || minute < self.to,
+            }
+        }
+
+        /// The first start of the window after `at`.
+        fn next_start<Tz: TimeZone>(&self, at: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+            let start_on = |date: NaiveDate| {
+                let start = date.and_hms_opt(self.from / 60, self.from % 60, 0)?;
+                // a start skipped by a DST change opens the window with the first minute after the gap
+                (0..24 * 60).find_map(|minutes| {
+                    (start + chrono::Duration::minutes(minutes))
+                        .and_local_timezone(at.timezone())
+                        .earliest()
+                })
+            };
+            let today = at.date_naive();
+            match start_on(today)? {
+                start if start > *at => Some(start),
+                _ => start_on(today.succ_opt()?),
+            }
+        }
+    }
+
+    impl std::fmt::Display for ActiveHours {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "{:02}:{:02} - {:02}:{:02}",
+                self.from / 60,
+                self.from % 60,
+                self.to / 60,
+                self.to % 60
+            )
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct DataScheduleEntry {
         pub id: i64,
-        pub name: String,
+        pub features: SensorFeatures,
+        pub trigger: ScheduleTrigger,
+        pub active_hours: Option<ActiveHours>,
+        pub target: ScheduleTarget,
     }
 
-    impl FromRow for AreaEntity {
-        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
-            Ok(AreaEntity {
-                id: row.get::<_, i64>(0)?,
-                name: row.get::<_, String>(1)?,
-            })
+    /// Upper bound of cron occurrences searched for one inside the active hours.
+    const CRON_SEARCH_LIMIT: usize = 10_000;
+    /// Days of active hours searched for a run of an interval rule.
+    const INTERVAL_SEARCH_DAYS: i64 = 366;
+    const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+    impl DataScheduleEntry {
+        pub fn is_active<Tz: TimeZone>(&self, at: &DateTime<Tz>) -> bool {
+            self.active_hours.is_none_or(|h| h.contains(at))
+        }
+
+        /// The first run strictly after `after`, `None` if the rule never runs.
+        pub fn next_run<Tz: TimeZone>(&self, after: DateTime<Tz>) -> Option<DateTime<Tz>> {
+            match &self.trigger {
+                ScheduleTrigger::Interval {
+                    interval_ms,
+                    offset_ms,
+                } => {
+                    let (interval, offset) = (*interval_ms as i64, *offset_ms as i64);
+                    if interval == 0 {
+                        return None;
+                    }
+                    let mut after = after;
+                    // every attempt after the first starts from the next window, the runs
+                    // fall into the same windows again once the interval cycled with the day
+                    let cycle_days = interval / gcd(interval, DAY_MS);
+                    for _ in 0..=cycle_days.min(INTERVAL_SEARCH_DAYS) {
+                        let ms = after.timestamp_millis();
+                        let next = ((ms - offset).div_euclid(interval) + 1) * interval + offset;
+                        let next =
+                            DateTime::from_timestamp_millis(next)?.with_timezone(&after.timezone());
+                        if self.is_active(&next) {
+                            return Some(next);
+                        }
+                        after = self.active_hours?.next_start(&next)?
+                            - chrono::Duration::milliseconds(1);
+                    }
+                    None
+                }
+                ScheduleTrigger::Cron(expression) => cron::Schedule::from_str(expression)
+                    .ok()?
+                    .after(&after)
+                    .take(CRON_SEARCH_LIMIT)
+                    .find(|next| self.is_active(next)),
+            }
         }
+
+        /// The next `count` runs from now.
+        pub fn upcoming(&self, count: usize) -> Vec<DateTime<Local>> {
+            std::iter::successors(self.next_run(Local::now()), |last| self.next_run(*last))
+                .take(count)
+                .collect()
+        }
+
+        /// Features of `sensor` this entry is the effective rule for at `at` within `schedule`.
+        pub fn effective_features(
+            &self,
+            schedule: &[DataScheduleEntry],
+            sensor: &SensorEntity,
+            at: &DateTime<Local>,
+        ) -> SensorFeatures {
+            sensor
+                .features
+                .intersection(self.features)
+                .iter()
+                .filter(|feature| {
+                    effective_entry(schedule, sensor, *feature, at).is_some_and(|e| e.id == self.id)
+                })
+                .fold(SensorFeatures::empty(), |features, f| features | f)
+        }
+    }
+
+    fn gcd(a: i64, b: i64) -> i64 {
+        match b {
+            0 => a,
+            _ => gcd(b, a % b),
+        }
+    }
+
+    /// The most specific entry of `schedule` collecting `feature` from `sensor` at `at`,
+    /// rules limited to active hours override the ones always active.
+    pub fn effective_entry<'a>(
+        schedule: &'a [DataScheduleEntry],
+        sensor: &SensorEntity,
+        feature: SensorFeatures,
+        at: &DateTime<Local>,
+    ) -> Option<&'a DataScheduleEntry> {
+        schedule
+            .iter()
+            .filter(|e| e.features.contains(feature) && e.target.matches(sensor))
+            .filter(|e| e.is_active(at))
+            .max_by_key(|e| (e.target.specificity(), e.active_hours.is_some(), e.id))
+    }
+
+    impl FromRow for DataScheduleEntry {
+        fn from_row(row: &r2d2_sqlite::rusqlite::Row) -> r2d2_sqlite::rusqlite::Result<Self> {
+            let target = match (
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<i64>>(4)?,
+            ) {
+                (Some(host), _) => ScheduleTarget::Sensor(host),
+                (None, Some(id)) => ScheduleTarget::Area(id),
+                (None, None) => ScheduleTarget::All,
+            };
+            let trigger = match row.get::<_, Option<String>>(6)? {
+                Some(expression) => ScheduleTrigger::Cron(expression),
+                None => ScheduleTrigger::Interval {
+                    interval_ms: row.get::<_, u64>(2)?,
+                    offset_ms: row.get::<_, u64>(5)?,
+                },
+            };
+            let active_hours = match (row.get::<_, Option<u32>>(7)?, row.get::<_, Option<u32>>(8)?)
+            {
+                (Some(from), Some(to)) => Some(ActiveHours { from, to }),
+                _ => None,
+            };
+            Ok(DataScheduleEntry {
+                id: row.get::<_, i64>(0)?,
+                features: SensorFeatures::from_bits_retain(row.get::<_, u64>(1)? as u32),
+                trigger,
+                active_hours,
+                target,
+            })
+        }
+    }
+
+    /// A single collection of sensor data, `schedule_id` is missing for manual runs.
+    #[derive(Debug, Clone, Default)]
+    pub struct CollectionRun {
+        pub id: i64,
+        pub schedule_id: Option<i64>,
+        pub features: SensorFeatures,
+        pub host: Option<String>,
+        pub started: i64,
+        pub duration_ms: i64,
+        pub sensors: u32,
+        pub rows: u32,
+        pub errors: Vec<String>,
+    }
+
+    impl FromRow for CollectionRun {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(CollectionRun {
+                id: row.get::<_, i64>(0)?,
+                schedule_id: row.get::<_, Option<i64>>(1)?,
+                features: SensorFeatures::from_bits_retain(row.get::<_, u32>(2)?),
+                host: row.get::<_, Option<String>>(3)?,
+                started: row.get::<_, i64>(4)?,
+                duration_ms: row.get::<_, i64>(5)?,
+                sensors: row.get::<_, u32>(6)?,
+                rows: row.get::<_, u32>(7)?,
+                errors: row
+                    .get::<_, String>(8)?
+                    .lines()
+                    .map(str::to_string)
+                    .collect(),
+            })
+        }
+    }
+
+    /// A single reading of one metric of a feature.
+    #[derive(Debug, Clone)]
+    pub struct MeasurementEntry {
+        pub host: String,
+        pub feature: SensorFeatures,
+        pub metric: String,
+        pub timestamp: u64,
+        pub value: f64,
+        /// Why the reading looks wrong, `None` for good readings.
+        pub flag: Option<ReadingFlag>,
+    }
+
+    impl FromRow for MeasurementEntry {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(MeasurementEntry {
+                host: row.get::<_, String>(0)?,
+                feature: SensorFeatures::from_bits_retain(row.get::<_, u32>(1)?),
+                metric: row.get::<_, String>(2)?,
+                timestamp: row.get::<_, u64>(3)?,
+                value: row.get::<_, f64>(4)?,
+                flag: row
+                    .get::<_, Option<String>>(5)?
+                    .and_then(|flag| flag.parse().ok()),
+            })
+        }
+    }
+
+    /// Reason a reading was flagged as bad when it was collected.
+    /// Flagged readings are kept but left out of the charts and alerts.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum ReadingFlag {
+        /// Outside of the physical bounds of the metric.
+        OutOfBounds,
+        /// Changed faster than the metric can.
+        RateOfChange,
+        /// Too far off the median of the readings around it.
+        Spike,
+    }
+
+    impl ReadingFlag {
+        pub fn label(&self) -> &'static str {
+            match self {
+                ReadingFlag::OutOfBounds => "Out of bounds",
+                ReadingFlag::RateOfChange => "Changed too fast",
+                ReadingFlag::Spike => "Spike",
+            }
+        }
+    }
+
+    impl std::fmt::Display for ReadingFlag {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ReadingFlag::OutOfBounds => write!(f, "bounds"),
+                ReadingFlag::RateOfChange => write!(f, "rate"),
+                ReadingFlag::Spike => write!(f, "spike"),
+            }
+        }
+    }
+
+    impl FromStr for ReadingFlag {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "bounds" => Ok(ReadingFlag::OutOfBounds),
+                "rate" => Ok(ReadingFlag::RateOfChange),
+                "spike" => Ok(ReadingFlag::Spike),
+                _ => Err(anyhow::anyhow!("Invalid reading flag `{}`", s)),
+            }
+        }
+    }
+
+    /// Checks a metric of a feature is validated with as it is collected, missing limits are not checked.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ValidationRule {
+        pub feature: SensorFeatures,
+        pub metric: String,
+        pub min: Option<f64>,
+        pub max: Option<f64>,
+        /// Largest change per minute from the last good reading.
+        pub max_rate: Option<f64>,
+        /// Readings the median is taken of, including the checked one.
+        pub median_window: usize,
+        /// Largest difference from the median.
+        pub max_deviation: Option<f64>,
+    }
+
+    impl ValidationRule {
+        /// Whether the rule lets every reading through.
+        pub fn is_empty(&self) -> bool {
+            self.min.is_none()
+                && self.max.is_none()
+                && self.max_rate.is_none()
+                && self.max_deviation.is_none()
+        }
+    }
+
+    impl FromRow for ValidationRule {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(ValidationRule {
+                feature: SensorFeatures::from_bits_retain(row.get::<_, u32>(0)?),
+                metric: row.get::<_, String>(1)?,
+                min: row.get::<_, Option<f64>>(2)?,
+                max: row.get::<_, Option<f64>>(3)?,
+                max_rate: row.get::<_, Option<f64>>(4)?,
+                median_window: row.get::<_, usize>(5)?,
+                max_deviation: row.get::<_, Option<f64>>(6)?,
+            })
+        }
+    }
+
+    /// All metrics of a feature measured by a sensor at one time,
+    /// in the order of [`crate::features::FeatureInfo::metrics`].
+    #[derive(Debug, Clone)]
+    pub struct MeasurementRow {
+        pub host: String,
+        pub timestamp: u64,
+        pub values: Vec<Option<f64>>,
+        /// Flags of the values, only read when the query selects `flag{i}` columns.
+        pub flags: Vec<Option<ReadingFlag>>,
+    }
+
+    impl MeasurementRow {
+        /// Flag of the `i`-th value.
+        pub fn flag(&self, i: usize) -> Option<ReadingFlag> {
+            self.flags.get(i).copied().flatten()
+        }
+
+        /// The `i`-th value, of `metric`, in the unit it is shown in.
+        pub fn value(
+            &self,
+            i: usize,
+            metric: &crate::features::Metric,
+            units: &UnitPreferences,
+        ) -> String {
+            self.values
+                .get(i)
+                .copied()
+                .flatten()
+                .map_or("-".to_string(), |v| units.value(metric, v).to_string())
+        }
+    }
+
+    impl FromRow for MeasurementRow {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            let row_ref = row.as_ref();
+            let (flags, values): (Vec<_>, Vec<_>) = (2..row_ref.column_count())
+                .partition(|&i| row_ref.column_name(i).is_ok_and(|n| n.starts_with("flag")));
+            Ok(MeasurementRow {
+                host: row.get::<_, String>(0)?,
+                timestamp: row.get::<_, u64>(1)?,
+                values: values
+                    .into_iter()
+                    .map(|i| row.get::<_, Option<f64>>(i))
+                    .collect::<Result<_, _>>()?,
+                flags: flags
+                    .into_iter()
+                    .map(|i| {
+                        row.get::<_, Option<String>>(i)
+                            .map(|flag| flag.and_then(|flag| flag.parse().ok()))
+                    })
+                    .collect::<Result<_, _>>()?,
+            })
+        }
+    }
+
+    /// Correction of the readings of a metric of a sensor, applied whenever they are shown.
+    /// The stored readings stay as the sensor sent them.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Calibration {
+        pub host: String,
+        pub feature: SensorFeatures,
+        pub metric: String,
+        pub offset: f64,
+        pub scale: f64,
+    }
+
+    impl Calibration {
+        /// Whether the readings are shown as they are stored.
+        pub fn is_identity(&self) -> bool {
+            self.offset == 0.0 && self.scale == 1.0
+        }
+    }
+
+    impl FromRow for Calibration {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(Calibration {
+                host: row.get::<_, String>(0)?,
+                feature: SensorFeatures::from_bits_retain(row.get::<_, u32>(1)?),
+                metric: row.get::<_, String>(2)?,
+                offset: row.get::<_, f64>(3)?,
+                scale: row.get::<_, f64>(4)?,
+            })
+        }
+    }
+
+    /// Readings of one metric of a sensor aggregated over a chart bucket.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ChartBucket {
+        pub host: String,
+        pub metric: String,
+        /// Start of the bucket.
+        pub timestamp: u64,
+        pub min: f64,
+        pub avg: f64,
+        pub max: f64,
+    }
+
+    impl FromRow for ChartBucket {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(ChartBucket {
+                host: row.get::<_, String>(0)?,
+                metric: row.get::<_, String>(1)?,
+                timestamp: row.get::<_, u64>(2)?,
+                min: row.get::<_, f64>(3)?,
+                avg: row.get::<_, f64>(4)?,
+                max: row.get::<_, f64>(5)?,
+            })
+        }
+    }
+
+    /// Granularity at which measurements are stored.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Resolution {
+        Raw,
+        Hourly,
+        Daily,
+    }
+
+    impl Resolution {
+        /// Length of a rollup bucket in seconds, `None` for raw readings.
+        pub fn seconds(&self) -> Option<u64> {
+            match self {
+                Resolution::Raw => None,
+                Resolution::Hourly => Some(60 * 60),
+                Resolution::Daily => Some(24 * 60 * 60),
+            }
+        }
+    }
+
+    impl std::fmt::Display for Resolution {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Resolution::Raw => write!(f, "raw"),
+                Resolution::Hourly => write!(f, "hourly"),
+                Resolution::Daily => write!(f, "daily"),
+            }
+        }
+    }
+
+    /// Readings drawn over those of a chart.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum ChartComparison {
+        /// The period of the same length just before the charted one.
+        Previous,
+        /// Another area over the same period.
+        Area(i64),
+    }
+
+    impl FromStr for ChartComparison {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.split_once(':') {
+                None if s == "previous" => Ok(ChartComparison::Previous),
+                Some(("area", id)) => Ok(ChartComparison::Area(id.parse()?)),
+                _ => Err(anyhow::anyhow!("Invalid comparison `{}`", s)),
+            }
+        }
+    }
+
+    /// Longest range in days charted from raw readings.
+    const RAW_RANGE_DAYS: u32 = 7;
+    /// Longest range in days charted from hourly rollups.
+    const HOURLY_RANGE_DAYS: u32 = 90;
+
+    /// How long measurements are kept at each resolution, `daily_days` is forever when missing.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct RetentionPolicy {
+        pub raw_days: u32,
+        pub hourly_days: u32,
+        pub daily_days: Option<u32>,
+    }
+
+    impl Default for RetentionPolicy {
+        fn default() -> Self {
+            Self {
+                raw_days: 30,
+                hourly_days: 365,
+                daily_days: None,
+            }
+        }
+    }
+
+    impl RetentionPolicy {
+        /// Raw readings before this at `now` are dropped, aligned to the hourly buckets
+        /// so no rollup loses a part of its source.
+        pub fn raw_cutoff(&self, now: u64) -> u64 {
+            let hour = Resolution::Hourly.seconds().unwrap();
+            let day = Resolution::Daily.seconds().unwrap();
+            now.saturating_sub(self.raw_days as u64 * day) / hour * hour
+        }
+
+        /// The finest resolution still kept for the last `days` that doesn't produce too many points.
+        pub fn resolution_for(&self, days: u32) -> Resolution {
+            if days <= RAW_RANGE_DAYS.min(self.raw_days) {
+                Resolution::Raw
+            } else if days <= HOURLY_RANGE_DAYS.min(self.hourly_days) {
+                Resolution::Hourly
+            } else {
+                Resolution::Daily
+            }
+        }
+
+        /// The finest resolution for `days` starting `age` days ago, coarser once the range
+        /// reaches past the readings still kept raw or hourly.
+        pub fn resolution_for_range(&self, days: u32, age: u32) -> Resolution {
+            match self.resolution_for(days) {
+                Resolution::Raw if age <= self.raw_days => Resolution::Raw,
+                Resolution::Raw | Resolution::Hourly if age <= self.hourly_days => {
+                    Resolution::Hourly
+                }
+                _ => Resolution::Daily,
+            }
+        }
+    }
+
+    impl FromRow for RetentionPolicy {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(RetentionPolicy {
+                raw_days: row.get::<_, u32>(0)?,
+                hourly_days: row.get::<_, u32>(1)?,
+                daily_days: row.get::<_, Option<u32>>(2)?,
+            })
+        }
+    }
+
+    /// Rows changed by a compaction of the stored measurements.
+    #[derive(Debug, Clone, Default)]
+    pub struct CompactionResult {
+        pub hourly: usize,
+        pub daily: usize,
+        pub deleted: usize,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum ExportFormat {
+        Csv,
+        /// One JSON object per line.
+        Ndjson,
+    }
+
+    impl ExportFormat {
+        pub fn content_type(&self) -> &'static str {
+            match self {
+                ExportFormat::Csv => "text/csv; charset=utf-8",
+                ExportFormat::Ndjson => "application/x-ndjson",
+            }
+        }
+
+        pub fn extension(&self) -> &'static str {
+            match self {
+                ExportFormat::Csv => "csv",
+                ExportFormat::Ndjson => "ndjson",
+            }
+        }
+    }
+
+    impl FromStr for ExportFormat {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "csv" => Ok(ExportFormat::Csv),
+                "ndjson" => Ok(ExportFormat::Ndjson),
+                _ => Err(anyhow::anyhow!("Invalid export format `{}`", s)),
+            }
+        }
+    }
+
+    /// Timezone times of exported and imported data are given in.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum DataTimezone {
+        Utc,
+        /// Timezone of the server, following its daylight saving changes.
+        Local,
+    }
+
+    impl DataTimezone {
+        /// Parses a `datetime-local` input value, an empty value leaves the range open.
+        pub fn parse_time(&self, value: &str) -> Result<Option<i64>, anyhow::Error> {
+            let value = value.trim();
+            if value.is_empty() {
+                return Ok(None);
+            }
+            let time = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+                .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+                .map_err(|_| anyhow::anyhow!("Invalid time `{}`", value))?;
+            Ok(Some(self.timestamp(time)?))
+        }
+
+        /// Unix timestamp of a time without an offset, read in this timezone.
+        pub fn timestamp(&self, time: NaiveDateTime) -> Result<i64, anyhow::Error> {
+            match self {
+                DataTimezone::Utc => Ok(time.and_utc().timestamp()),
+                // a time repeated when the clocks go back is taken at its first occurrence
+                DataTimezone::Local => match Local.from_local_datetime(&time) {
+                    LocalResult::Single(time) => Ok(time.timestamp()),
+                    LocalResult::Ambiguous(a, b) => Ok(a.timestamp().min(b.timestamp())),
+                    LocalResult::None => {
+                        anyhow::bail!("`{}` doesn't exist in the local timezone", time)
+                    }
+                },
+            }
+        }
+
+        /// RFC 3339 time with an explicit offset.
+        pub fn format(&self, timestamp: u64) -> String {
+            let time = DateTime::from_timestamp(timestamp as i64, 0).unwrap_or_default();
+            match self {
+                DataTimezone::Utc => time.to_rfc3339_opts(SecondsFormat::Secs, true),
+                DataTimezone::Local => time
+                    .with_timezone(&Local)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+            }
+        }
+    }
+
+    impl FromStr for DataTimezone {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "utc" => Ok(DataTimezone::Utc),
+                "local" => Ok(DataTimezone::Local),
+                _ => Err(anyhow::anyhow!("Invalid timezone `{}`", s)),
+            }
+        }
+    }
+
+    /// Measurements of a feature to export, `from` included and `to` excluded.
+    #[derive(Debug, Clone)]
+    pub struct ExportQuery {
+        pub info: &'static crate::features::FeatureInfo,
+        pub format: ExportFormat,
+        pub target: ScheduleTarget,
+        pub from: Option<i64>,
+        pub to: Option<i64>,
+        pub timezone: DataTimezone,
+    }
+
+    /// How the timestamps of imported rows are written.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum TimestampFormat {
+        /// Seconds since the epoch.
+        Unix,
+        /// Milliseconds since the epoch.
+        UnixMillis,
+        Rfc3339,
+        /// A `strftime` pattern, times without an offset are read in the timezone of the import.
+        Pattern(String),
+    }
+
+    impl TimestampFormat {
+        pub fn parse(&self, value: &str, timezone: DataTimezone) -> Result<i64, anyhow::Error> {
+            let invalid = || anyhow::anyhow!("Invalid timestamp `{}`", value);
+            match self {
+                TimestampFormat::Unix => value.parse::<i64>().map_err(|_| invalid()),
+                TimestampFormat::UnixMillis => value
+                    .parse::<i64>()
+                    .map(|ms| ms.div_euclid(1000))
+                    .map_err(|_| invalid()),
+                TimestampFormat::Rfc3339 => DateTime::parse_from_rfc3339(value)
+                    .map(|time| time.timestamp())
+                    .map_err(|_| invalid()),
+                TimestampFormat::Pattern(pattern) => {
+                    if let Ok(time) = DateTime::parse_from_str(value, pattern) {
+                        return Ok(time.timestamp());
+                    }
+                    let time = NaiveDateTime::parse_from_str(value, pattern)
+                        .or_else(|_| {
+                            NaiveDate::parse_from_str(value, pattern)
+                                .map(|date| date.and_hms_opt(0, 0, 0).unwrap())
+                        })
+                        .map_err(|_| invalid())?;
+                    timezone.timestamp(time)
+                }
+            }
+        }
+    }
+
+    /// A column of an imported file holding the values of a metric.
+    #[derive(Debug, Clone)]
+    pub struct ImportColumn {
+        pub metric: &'static crate::features::Metric,
+        /// Header name or 1-based position of the column.
+        pub column: String,
+        pub unit: &'static crate::features::Unit,
+    }
+
+    /// How to read a CSV file of measurements taken by `host`.
+    #[derive(Debug, Clone)]
+    pub struct ImportOptions {
+        pub info: &'static crate::features::FeatureInfo,
+        pub host: String,
+        pub delimiter: u8,
+        pub has_header: bool,
+        /// Header name or 1-based position of the timestamp column.
+        pub timestamp_column: String,
+        pub timestamp_format: TimestampFormat,
+        pub timezone: DataTimezone,
+        pub columns: Vec<ImportColumn>,
+    }
+
+    /// A row of an imported file that was not stored.
+    #[derive(Debug, Clone)]
+    pub struct RejectedRow {
+        pub line: u64,
+        pub reason: String,
+    }
+
+    #[derive(Debug, Clone, Default)]
+    pub struct ImportResult {
+        pub entries: Vec<MeasurementEntry>,
+        /// Number of valid rows.
+        pub rows: usize,
+        /// The first valid rows, with the values converted to the stored units.
+        pub preview: Vec<MeasurementRow>,
+        pub rejected: Vec<RejectedRow>,
+    }
+
+    /// Column the browsed measurements are ordered by, ties are broken by time and host.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum BrowseSort {
+        Time,
+        Sensor,
+        /// Index of the metric in [`crate::features::FeatureInfo::metrics`].
+        Metric(usize),
+    }
+
+    /// Which measurements are browsed and in what order.
+    #[derive(Debug, Clone)]
+    pub struct BrowseFilter {
+        pub target: ScheduleTarget,
+        pub from: Option<i64>,
+        pub to: Option<i64>,
+        /// Index of a metric with the lowest and highest value it may have.
+        pub range: Option<(usize, Option<f64>, Option<f64>)>,
+        pub sort: BrowseSort,
+        pub descending: bool,
+        /// Whether readings flagged on collection are listed too.
+        pub flagged: bool,
+    }
+
+    /// Sort key of the row a page starts or ends with.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct BrowseCursor {
+        pub timestamp: u64,
+        pub host: String,
+        /// Value of the sorted metric.
+        pub value: Option<f64>,
+    }
+
+    impl BrowseCursor {
+        pub fn of(row: &MeasurementRow, sort: BrowseSort) -> Self {
+            Self {
+                timestamp: row.timestamp,
+                host: row.host.clone(),
+                value: match sort {
+                    BrowseSort::Metric(i) => row.values.get(i).copied().flatten(),
+                    _ => None,
+                },
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum BrowsePage {
+        First,
+        /// The rows following the cursor.
+        After(BrowseCursor),
+        /// The rows preceding the cursor.
+        Before(BrowseCursor),
+    }
+
+    /// Missing readings of a feature between two stored ones, `start` and `end` excluded.
+    #[derive(Debug, Clone)]
+    pub struct MeasurementGap {
+        pub host: String,
+        pub feature: SensorFeatures,
+        pub start: u64,
+        pub end: u64,
+        /// The sensor no longer holds the readings, so they can't be backfilled.
+        pub unrecoverable: bool,
+    }
+
+    impl MeasurementGap {
+        /// Number of readings missing when the sensor stores one every `interval` seconds.
+        pub fn missing(&self, interval: u64) -> u64 {
+            ((self.end - self.start) / interval)
+                .saturating_sub(1)
+                .max(1)
+        }
+    }
+
+    impl FromRow for MeasurementGap {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(MeasurementGap {
+                host: row.get::<_, String>(0)?,
+                feature: SensorFeatures::from_bits_retain(row.get::<_, u32>(1)?),
+                start: row.get::<_, u64>(2)?,
+                end: row.get::<_, u64>(3)?,
+                unrecoverable: row.get::<_, bool>(4)?,
+            })
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct MotionDataEntry {
+        pub host: String,
+        pub start: i64,
+        pub end: Option<i64>,
+    }
+
+    impl FromRow for MotionDataEntry {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(MotionDataEntry {
+                host: row.get::<_, String>(0)?,
+                start: row.get::<_, i64>(1)?,
+                end: row.get::<_, Option<i64>>(2)?,
+            })
+        }
+    }
+
+    /// Consecutive failed requests after which a sensor is considered offline.
+    pub const OFFLINE_FAILURES: u32 = 3;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum SensorState {
+        Unknown,
+        Online,
+        Offline,
+    }
+
+    impl std::fmt::Display for SensorState {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                SensorState::Unknown => write!(f, "unknown"),
+                SensorState::Online => write!(f, "online"),
+                SensorState::Offline => write!(f, "offline"),
+            }
+        }
+    }
+
+    impl SensorState {
+        pub fn badge(&self) -> &'static str {
+            match self {
+                SensorState::Unknown => "badge-ghost",
+                SensorState::Online => "badge-success",
+                SensorState::Offline => "badge-error",
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    pub struct SensorStatus {
+        pub host: String,
+        pub last_seen: Option<i64>,
+        pub last_error: Option<String>,
+        pub failures: u32,
+    }
+
+    impl SensorStatus {
+        pub fn state(&self) -> SensorState {
+            match (self.failures, self.last_seen) {
+                (f, _) if f >= OFFLINE_FAILURES => SensorState::Offline,
+                (_, Some(_)) => SensorState::Online,
+                _ => SensorState::Unknown,
+            }
+        }
+    }
+
+    impl FromRow for SensorStatus {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(SensorStatus {
+                host: row.get::<_, String>(0)?,
+                last_seen: row.get::<_, Option<i64>>(1)?,
+                last_error: row.get::<_, Option<String>>(2)?,
+                failures: row.get::<_, u32>(3)?,
+            })
+        }
+    }
+
+    /// Share of the pair store above which a sensor is reported as nearly full.
+    pub const PAIR_STORE_WARNING: f64 = 0.8;
+
+    #[derive(Debug, Clone)]
+    pub struct SensorHealthEntry {
+        pub host: String,
+        pub timestamp: i64,
+        pub free_mem: u32,
+        pub uptime: f64,
+        pub paired_keys: u32,
+        pub usage: StoreUsage,
+        pub rebooted: bool,
+    }
+
+    impl SensorHealthEntry {
+        pub fn from_response(host: &str, timestamp: i64, response: SensorFullResponse) -> Self {
+            Self {
+                host: host.to_string(),
+                timestamp,
+                free_mem: response.free_mem,
+                uptime: response.uptime,
+                paired_keys: response.paired_keys,
+                usage: response.usage,
+                rebooted: false,
+            }
+        }
+
+        pub fn pair_usage(&self) -> f64 {
+            self.usage.pair_used as f64 / self.usage.pair_total.max(1) as f64
+        }
+
+        pub fn pair_store_nearly_full(&self) -> bool {
+            self.pair_usage() >= PAIR_STORE_WARNING
+        }
+
+        pub fn uptime_text(&self) -> String {
+            let seconds = self.uptime as u64;
+            format!(
+                "{}d {:02}:{:02}:{:02}",
+                seconds / 86400,
+                seconds / 3600 % 24,
+                seconds / 60 % 60,
+                seconds % 60
+            )
+        }
+    }
+
+    impl FromRow for SensorHealthEntry {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(SensorHealthEntry {
+                host: row.get::<_, String>(0)?,
+                timestamp: row.get::<_, i64>(1)?,
+                free_mem: row.get::<_, u32>(2)?,
+                uptime: row.get::<_, f64>(3)?,
+                paired_keys: row.get::<_, u32>(4)?,
+                usage: StoreUsage {
+                    data_used: row.get::<_, u32>(5)?,
+                    data_total: row.get::<_, u32>(6)?,
+                    pair_used: row.get::<_, u32>(7)?,
+                    pair_total: row.get::<_, u32>(8)?,
+                },
+                rebooted: row.get::<_, bool>(9)?,
+            })
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct ScanHistoryEntry {
+        pub id: i64,
+        pub created: i64,
+        pub duration_ms: i64,
+        pub target: String,
+        pub found: u32,
+    }
+
+    impl FromRow for ScanHistoryEntry {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(ScanHistoryEntry {
+                id: row.get::<_, i64>(0)?,
+                created: row.get::<_, i64>(1)?,
+                duration_ms: row.get::<_, i64>(2)?,
+                target: row.get::<_, String>(3)?,
+                found: row.get::<_, u32>(4)?,
+            })
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum ScanChangeKind {
+        NewDevice,
+        Disappeared,
+        HostChanged,
+    }
+
+    impl From<ScanChangeKind> for u32 {
+        fn from(value: ScanChangeKind) -> Self {
+            match value {
+                ScanChangeKind::NewDevice => 0,
+                ScanChangeKind::Disappeared => 1,
+                ScanChangeKind::HostChanged => 2,
+            }
+        }
+    }
+
+    impl TryFrom<u32> for ScanChangeKind {
+        type Error = rusqlite::Error;
+
+        fn try_from(value: u32) -> Result<Self, Self::Error> {
+            match value {
+                0 => Ok(ScanChangeKind::NewDevice),
+                1 => Ok(ScanChangeKind::Disappeared),
+                2 => Ok(ScanChangeKind::HostChanged),
+                _ => Err(rusqlite::Error::IntegralValueOutOfRange(1, value as i64)),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct ScanChange {
+        pub created: i64,
+        pub kind: ScanChangeKind,
+        pub host: String,
+        pub name: String,
+        pub previous_host: Option<String>,
+    }
+
+    impl ScanChange {
+        pub fn text(&self) -> String {
+            match self.kind {
+                ScanChangeKind::NewDevice => {
+                    format!("New device \"{}\" found at {}", self.name, self.host)
+                }
+                ScanChangeKind::Disappeared => {
+                    format!(
+                        "Sensor \"{}\" is no longer found at {}",
+                        self.name, self.host
+                    )
+                }
+                ScanChangeKind::HostChanged => format!(
+                    "Sensor \"{}\" moved from {} to {}",
+                    self.name,
+                    self.previous_host.as_deref().unwrap_or_default(),
+                    self.host
+                ),
+            }
+        }
+    }
+
+    impl FromRow for ScanChange {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(ScanChange {
+                created: row.get::<_, i64>(0)?,
+                kind: row.get::<_, u32>(1)?.try_into()?,
+                host: row.get::<_, String>(2)?,
+                name: row.get::<_, String>(3)?,
+                previous_host: row.get::<_, Option<String>>(4)?,
+            })
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct AreaEntity {
+        pub id: i64,
+        pub name: String,
+    }
+
+    impl FromRow for AreaEntity {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(AreaEntity {
+                id: row.get::<_, i64>(0)?,
+                name: row.get::<_, String>(1)?,
+            })
+        }
+    }
+
+    /// What an alert rule watches for.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum AlertCondition {
+        Above {
+            feature: SensorFeatures,
+            metric: String,
+            threshold: f64,
+        },
+        Below {
+            feature: SensorFeatures,
+            metric: String,
+            threshold: f64,
+        },
+        Offline,
+    }
+
+    impl AlertCondition {
+        /// The feature and metric a threshold condition is evaluated on.
+        pub fn metric(
+            &self,
+        ) -> Option<(
+            &'static crate::features::FeatureInfo,
+            usize,
+            &'static crate::features::Metric,
+        )> {
+            let (feature, metric) = match self {
+                AlertCondition::Above {
+                    feature, metric, ..
+                }
+                | AlertCondition::Below {
+                    feature, metric, ..
+                } => (*feature, metric),
+                AlertCondition::Offline => return None,
+            };
+            let info = crate::features::by_feature(feature)?;
+            info.metrics
+                .iter()
+                .enumerate()
+                .find(|(_, m)| m.name == metric)
+                .map(|(i, m)| (info, i, m))
+        }
+
+        /// Whether `value` starts an alert.
+        pub fn triggered(&self, value: f64) -> bool {
+            match self {
+                AlertCondition::Above { threshold, .. } => value > *threshold,
+                AlertCondition::Below { threshold, .. } => value < *threshold,
+                AlertCondition::Offline => false,
+            }
+        }
+
+        /// Whether `value` ends a firing alert, it has to get past the threshold by `hysteresis`.
+        pub fn cleared(&self, value: f64, hysteresis: f64) -> bool {
+            match self {
+                AlertCondition::Above { threshold, .. } => value <= *threshold - hysteresis,
+                AlertCondition::Below { threshold, .. } => value >= *threshold + hysteresis,
+                AlertCondition::Offline => true,
+            }
+        }
+    }
+
+    /// A user defined condition over the collected data of the sensors of `target`.
+    #[derive(Debug, Clone)]
+    pub struct AlertRule {
+        pub id: i64,
+        pub name: String,
+        pub condition: AlertCondition,
+        /// How long the condition has to hold before the alert fires.
+        pub duration_s: u64,
+        pub hysteresis: f64,
+        /// Minimum time between an alert resolving and the next one firing.
+        pub cooldown_s: u64,
+        pub target: ScheduleTarget,
+    }
+
+    impl AlertRule {
+        /// The condition of the rule with its threshold in `units`.
+        pub fn describe(&self, units: &UnitPreferences) -> String {
+            let condition = match (&self.condition, self.condition.metric()) {
+                (AlertCondition::Above { threshold, .. }, Some((_, _, metric))) => format!(
+                    "{} above {} {}",
+                    metric.label,
+                    units.value(metric, *threshold),
+                    units.unit(metric).label
+                ),
+                (AlertCondition::Below { threshold, .. }, Some((_, _, metric))) => format!(
+                    "{} below {} {}",
+                    metric.label,
+                    units.value(metric, *threshold),
+                    units.unit(metric).label
+                ),
+                (AlertCondition::Offline, _) => "Offline".to_string(),
+                _ => "Unknown metric".to_string(),
+            };
+            match self.duration_s {
+                0 => condition,
+                duration => format!("{} for {} min", condition, duration / 60),
+            }
+        }
+
+        /// The rule with its threshold and hysteresis given in `units` converted to the stored units.
+        pub fn into_base_units(mut self, units: &UnitPreferences) -> Self {
+            let Some((_, _, metric)) = self.condition.metric() else {
+                return self;
+            };
+            let unit = units.unit(metric);
+            self.hysteresis = unit.difference_to_base(self.hysteresis);
+            match &mut self.condition {
+                AlertCondition::Above { threshold, .. }
+                | AlertCondition::Below { threshold, .. } => {
+                    *threshold = (unit.to_base)(*threshold)
+                }
+                AlertCondition::Offline => {}
+            }
+            self
+        }
+    }
+
+    impl FromRow for AlertRule {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            let target = match (
+                row.get::<_, Option<String>>(9)?,
+                row.get::<_, Option<i64>>(10)?,
+            ) {
+                (Some(host), _) => ScheduleTarget::Sensor(host),
+                (None, Some(id)) => ScheduleTarget::Area(id),
+                (None, None) => ScheduleTarget::All,
+            };
+            let feature =
+                SensorFeatures::from_bits_retain(row.get::<_, Option<u32>>(3)?.unwrap_or(0));
+            let metric = row.get::<_, Option<String>>(4)?.unwrap_or_default();
+            let threshold = row.get::<_, Option<f64>>(5)?.unwrap_or_default();
+            let condition = match row.get::<_, String>(2)?.as_str() {
+                "above" => AlertCondition::Above {
+                    feature,
+                    metric,
+                    threshold,
+                },
+                "below" => AlertCondition::Below {
+                    feature,
+                    metric,
+                    threshold,
+                },
+                _ => AlertCondition::Offline,
+            };
+            Ok(AlertRule {
+                id: row.get::<_, i64>(0)?,
+                name: row.get::<_, String>(1)?,
+                condition,
+                duration_s: row.get::<_, u64>(6)?,
+                hysteresis: row.get::<_, f64>(7)?,
+                cooldown_s: row.get::<_, u64>(8)?,
+                target,
+            })
+        }
+    }
+
+    /// An alert raised by a rule for a single sensor, firing until `resolved` is set.
+    #[derive(Debug, Clone, Default)]
+    pub struct Alert {
+        pub id: i64,
+        pub rule_id: i64,
+        pub host: String,
+        pub started: i64,
+        pub resolved: Option<i64>,
+        pub value: Option<f64>,
+        pub message: String,
+    }
+
+    impl FromRow for Alert {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(Alert {
+                id: row.get::<_, i64>(0)?,
+                rule_id: row.get::<_, i64>(1)?,
+                host: row.get::<_, String>(2)?,
+                started: row.get::<_, i64>(3)?,
+                resolved: row.get::<_, Option<i64>>(4)?,
+                value: row.get::<_, Option<f64>>(5)?,
+                message: row.get::<_, String>(6)?,
+            })
+        }
+    }
+
+    bitflags::bitflags! {
+        /// Kinds of events users can be notified about.
+        #[derive(Debug, Default, Clone, Copy, PartialEq)]
+        pub struct NotificationTopics: u32 {
+            const SENSOR_OFFLINE = 1 << 0;
+            const SENSOR_ONLINE = 1 << 1;
+            const COLLECTOR_FAILED = 1 << 2;
+            const NEW_DEVICE = 1 << 3;
+            const ALERT_FIRED = 1 << 4;
+            const ALERT_RESOLVED = 1 << 5;
+        }
+    }
+
+    /// Every topic with the key used in form fields and its label.
+    pub const NOTIFICATION_TOPICS: &[(NotificationTopics, &str, &str)] = &[
+        (
+            NotificationTopics::SENSOR_OFFLINE,
+            "sensor-offline",
+            "Sensor offline",
+        ),
+        (
+            NotificationTopics::SENSOR_ONLINE,
+            "sensor-online",
+            "Sensor back online",
+        ),
+        (
+            NotificationTopics::COLLECTOR_FAILED,
+            "collector-failed",
+            "Collector failure",
+        ),
+        (
+            NotificationTopics::NEW_DEVICE,
+            "new-device",
+            "Unknown device found",
+        ),
+        (
+            NotificationTopics::ALERT_FIRED,
+            "alert-fired",
+            "Alert fired",
+        ),
+        (
+            NotificationTopics::ALERT_RESOLVED,
+            "alert-resolved",
+            "Alert resolved",
+        ),
+    ];
+
+    impl NotificationTopics {
+        /// Label of a single topic, no topic at all is a test notification.
+        pub fn label(&self) -> &'static str {
+            if self.is_empty() {
+                return "Test";
+            }
+            NOTIFICATION_TOPICS
+                .iter()
+                .find(|(topic, _, _)| topic == self)
+                .map_or("Unknown", |(_, _, label)| label)
+        }
+
+        pub fn key(&self) -> &'static str {
+            if self.is_empty() {
+                return "test";
+            }
+            NOTIFICATION_TOPICS
+                .iter()
+                .find(|(topic, _, _)| topic == self)
+                .map_or("unknown", |(_, key, _)| key)
+        }
+    }
+
+    /// Something that happened in the house and is worth telling the users about.
+    #[derive(Debug, Clone)]
+    pub struct Notification {
+        pub topic: NotificationTopics,
+        pub title: String,
+        pub message: String,
+        pub time: i64,
+    }
+
+    impl Notification {
+        pub fn new(
+            topic: NotificationTopics,
+            title: impl Into<String>,
+            message: impl Into<String>,
+        ) -> Self {
+            Self {
+                topic,
+                title: title.into(),
+                message: message.into(),
+                time: chrono::Utc::now().timestamp(),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum ChannelKind {
+        /// JSON `POST` of the notification to `url`.
+        Webhook,
+        /// Plain text mail through the SMTP server at `url` (`host:port`), over TLS unless it
+        /// offers none and no credentials are set.
+        Smtp,
+        /// ntfy style `POST` of the message as the body, with the title in a header.
+        Push,
+    }
+
+    impl std::fmt::Display for ChannelKind {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ChannelKind::Webhook => write!(f, "webhook"),
+                ChannelKind::Smtp => write!(f, "smtp"),
+                ChannelKind::Push => write!(f, "push"),
+            }
+        }
+    }
+
+    impl FromStr for ChannelKind {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "webhook" => Ok(ChannelKind::Webhook),
+                "smtp" => Ok(ChannelKind::Smtp),
+                "push" => Ok(ChannelKind::Push),
+                _ => Err(anyhow::anyhow!("Invalid channel kind `{}`", s)),
+            }
+        }
+    }
+
+    /// Placeholders available in the message template of a channel.
+    pub const TEMPLATE_PLACEHOLDERS: &str = "{title}, {message}, {topic}, {time}";
+    pub const DEFAULT_TEMPLATE: &str = "{title}\n{message}";
+
+    #[derive(Debug, Clone)]
+    pub struct NotificationChannel {
+        pub id: i64,
+        pub name: String,
+        pub kind: ChannelKind,
+        pub url: String,
+        /// Bearer token for webhooks and pushes, `user:password` for SMTP.
+        pub credentials: Option<String>,
+        /// Mail sender of SMTP channels.
+        pub sender: Option<String>,
+        pub template: String,
+    }
+
+    impl NotificationChannel {
+        pub fn render(&self, notification: &Notification) -> String {
+            let time = DateTime::from_timestamp(notification.time, 0)
+                .unwrap_or_default()
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string();
+            self.template
+                .replace("{title}", &notification.title)
+                .replace("{message}", &notification.message)
+                .replace("{topic}", notification.topic.key())
+                .replace("{time}", &time)
+        }
+    }
+
+    impl FromRow for NotificationChannel {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(NotificationChannel {
+                id: row.get::<_, i64>(0)?,
+                name: row.get::<_, String>(1)?,
+                kind: row
+                    .get::<_, String>(2)?
+                    .parse()
+                    .unwrap_or(ChannelKind::Webhook),
+                url: row.get::<_, String>(3)?,
+                credentials: row.get::<_, Option<String>>(4)?,
+                sender: row.get::<_, Option<String>>(5)?,
+                template: row.get::<_, String>(6)?,
+            })
+        }
+    }
+
+    /// The topics a user receives through a channel, `address` is the mail recipient of SMTP channels.
+    #[derive(Debug, Clone)]
+    pub struct NotificationSubscription {
+        pub user_id: i64,
+        pub channel_id: i64,
+        pub topics: NotificationTopics,
+        pub address: Option<String>,
+    }
+
+    impl FromRow for NotificationSubscription {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(NotificationSubscription {
+                user_id: row.get::<_, i64>(0)?,
+                channel_id: row.get::<_, i64>(1)?,
+                topics: NotificationTopics::from_bits_retain(row.get::<_, u32>(2)?),
+                address: row.get::<_, Option<String>>(3)?,
+            })
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+    pub enum DeliveryStatus {
+        #[default]
+        Pending,
+        Sent,
+        Failed,
+    }
+
+    impl DeliveryStatus {
+        pub fn badge(&self) -> &'static str {
+            match self {
+                DeliveryStatus::Pending => "badge-warning",
+                DeliveryStatus::Sent => "badge-success",
+                DeliveryStatus::Failed => "badge-error",
+            }
+        }
+    }
+
+    impl std::fmt::Display for DeliveryStatus {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                DeliveryStatus::Pending => write!(f, "pending"),
+                DeliveryStatus::Sent => write!(f, "sent"),
+                DeliveryStatus::Failed => write!(f, "failed"),
+            }
+        }
+    }
+
+    impl From<DeliveryStatus> for u32 {
+        fn from(value: DeliveryStatus) -> Self {
+            match value {
+                DeliveryStatus::Pending => 0,
+                DeliveryStatus::Sent => 1,
+                DeliveryStatus::Failed => 2,
+            }
+        }
+    }
+
+    impl From<u32> for DeliveryStatus {
+        fn from(value: u32) -> Self {
+            match value {
+                1 => DeliveryStatus::Sent,
+                2 => DeliveryStatus::Failed,
+                _ => DeliveryStatus::Pending,
+            }
+        }
+    }
+
+    /// A notification sent, or to be sent, through a channel, retried until it succeeds or gives up.
+    #[derive(Debug, Clone, Default)]
+    pub struct NotificationDelivery {
+        pub id: i64,
+        pub channel_id: i64,
+        pub recipient: Option<String>,
+        pub topic: NotificationTopics,
+        pub title: String,
+        pub body: String,
+        pub created: i64,
+        pub status: DeliveryStatus,
+        pub attempts: u32,
+        pub next_attempt: Option<i64>,
+        pub error: Option<String>,
+    }
+
+    impl FromRow for NotificationDelivery {
+        fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+            Ok(NotificationDelivery {
+                id: row.get::<_, i64>(0)?,
+                channel_id: row.get::<_, i64>(1)?,
+                recipient: row.get::<_, Option<String>>(2)?,
+                topic: NotificationTopics::from_bits_retain(row.get::<_, u32>(3)?),
+                title: row.get::<_, String>(4)?,
+                body: row.get::<_, String>(5)?,
+                created: row.get::<_, i64>(6)?,
+                status: row.get::<_, u32>(7)?.into(),
+                attempts: row.get::<_, u32>(8)?,
+                next_attempt: row.get::<_, Option<i64>>(9)?,
+                error: row.get::<_, Option<String>>(10)?,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::db::*;
+    use chrono::{TimeZone, Utc};
+    use chrono_tz::Europe::Berlin;
+
+    fn entry(trigger: ScheduleTrigger, active_hours: Option<(u32, u32)>) -> DataScheduleEntry {
+        DataScheduleEntry {
+            id: 1,
+            features: SensorFeatures::empty(),
+            trigger,
+            active_hours: active_hours.map(|(from, to)| ActiveHours { from, to }),
+            target: ScheduleTarget::All,
+        }
+    }
+
+    fn interval(minutes: u64, offset_minutes: u64) -> ScheduleTrigger {
+        ScheduleTrigger::Interval {
+            interval_ms: minutes * 60 * 1000,
+            offset_ms: offset_minutes * 60 * 1000,
+        }
+    }
+
+    #[test]
+    fn interval_runs_are_shifted_by_the_offset() {
+        let rule = entry(interval(15, 5), None);
+        let at = Utc.with_ymd_and_hms(2026, 1, 1, 10, 0, 0).unwrap();
+        assert_eq!(
+            rule.next_run(at),
+            Some(Utc.with_ymd_and_hms(2026, 1, 1, 10, 5, 0).unwrap())
+        );
+        let at = Utc.with_ymd_and_hms(2026, 1, 1, 10, 5, 0).unwrap();
+        assert_eq!(
+            rule.next_run(at),
+            Some(Utc.with_ymd_and_hms(2026, 1, 1, 10, 20, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn interval_runs_inside_window_wrapping_midnight() {
+        let rule = entry(interval(60, 0), Some((22 * 60, 2 * 60)));
+        let at = Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap();
+        assert_eq!(
+            rule.next_run(at),
+            Some(Utc.with_ymd_and_hms(2026, 1, 1, 22, 0, 0).unwrap())
+        );
+        let at = Utc.with_ymd_and_hms(2026, 1, 1, 23, 30, 0).unwrap();
+        assert_eq!(
+            rule.next_run(at),
+            Some(Utc.with_ymd_and_hms(2026, 1, 2, 0, 0, 0).unwrap())
+        );
+        let at = Utc.with_ymd_and_hms(2026, 1, 2, 1, 30, 0).unwrap();
+        assert_eq!(
+            rule.next_run(at),
+            Some(Utc.with_ymd_and_hms(2026, 1, 2, 22, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn interval_longer_than_a_day_reaches_the_window_later() {
+        // runs every 36 hours fall at midnight and noon on alternate days
+        let rule = entry(interval(36 * 60, 0), Some((11 * 60, 13 * 60)));
+        let at = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            rule.next_run(at),
+            Some(Utc.with_ymd_and_hms(2026, 1, 2, 12, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn interval_never_reaching_the_window_never_runs() {
+        let rule = entry(interval(24 * 60, 0), Some((11 * 60, 13 * 60)));
+        let at = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(rule.next_run(at), None);
+    }
+
+    #[test]
+    fn cron_runs_inside_window() {
+        let rule = entry(ScheduleTrigger::Cron("0 30 9 * * *".to_string()), None);
+        let at = Utc.with_ymd_and_hms(2026, 1, 1, 10, 0, 0).unwrap();
+        assert_eq!(
+            rule.next_run(at),
+            Some(Utc.with_ymd_and_hms(2026, 1, 2, 9, 30, 0).unwrap())
+        );
+
+        let rule = entry(
+            ScheduleTrigger::Cron("0 0 * * * *".to_string()),
+            Some((10 * 60, 12 * 60)),
+        );
+        let at = Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap();
+        assert_eq!(
+            rule.next_run(at),
+            Some(Utc.with_ymd_and_hms(2026, 1, 2, 10, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn window_starting_in_dst_gap_opens_after_it() {
+        // 2026-03-29 02:00 to 03:00 does not exist in Berlin
+        let rule = entry(interval(60, 0), Some((2 * 60 + 30, 4 * 60)));
+        let at = Berlin.with_ymd_and_hms(2026, 3, 28, 12, 0, 0).unwrap();
+        assert_eq!(
+            rule.next_run(at),
+            Some(Berlin.with_ymd_and_hms(2026, 3, 29, 3, 0, 0).unwrap())
+        );
     }
 }
@@ -0,0 +1,216 @@
+use crate::{
+    database::{
+        alerts::AlertDatabase, measurements::MeasurementDatabase,
+        sensor_status::SensorStatusDatabase, sensors::SensorDatabase, DbConn, DbPool,
+    },
+    features::UnitPreferences,
+    models::db::{
+        Alert, AlertRule, Notification, NotificationTopics, ScheduleTarget, SensorEntity,
+        SensorState,
+    },
+};
+use chrono::Utc;
+
+/// An alert that fired or resolved during an evaluation.
+#[derive(Clone, Debug)]
+pub enum AlertChange {
+    Fired(Alert),
+    Resolved(Alert),
+}
+
+impl From<AlertChange> for Notification {
+    fn from(value: AlertChange) -> Self {
+        match value {
+            AlertChange::Fired(alert) => Notification::new(
+                NotificationTopics::ALERT_FIRED,
+                "Alert fired",
+                alert.message,
+            ),
+            AlertChange::Resolved(alert) => Notification::new(
+                NotificationTopics::ALERT_RESOLVED,
+                "Alert resolved",
+                alert.message,
+            ),
+        }
+    }
+}
+
+/// What a rule is evaluated on, a single sensor or the average of the sensors of an area.
+struct Subject<'a> {
+    /// Key of the alert state, the host of a sensor or `area:<id>`.
+    key: String,
+    name: &'a str,
+    sensors: Vec<&'a SensorEntity>,
+}
+
+/// Evaluates every alert rule against the latest data of the sensors it targets.
+/// Threshold rules on an area look at the average of its sensors, other rules at each sensor.
+pub async fn evaluate_alerts(pool: &DbPool) -> Result<Vec<AlertChange>, anyhow::Error> {
+    let conn = pool.get().await?;
+    let rules = conn.get_alert_rules().await?;
+    if rules.is_empty() {
+        return Ok(vec![]);
+    }
+    let sensors = conn
+        .get_sensors()
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    let now = Utc::now().timestamp();
+    let mut changes = vec![];
+    for rule in &rules {
+        let targeted = sensors.iter().filter(|s| rule.target.matches(s));
+        let subjects = match (&rule.target, rule.condition.metric()) {
+            (ScheduleTarget::Area(id), Some(_)) => {
+                let targeted = targeted.collect::<Vec<_>>();
+                targeted
+                    .first()
+                    .and_then(|s| s.area.as_ref())
+                    .map(|area| Subject {
+                        key: format!("area:{}", id),
+                        name: &area.name,
+                        sensors: targeted.clone(),
+                    })
+                    .into_iter()
+                    .collect::<Vec<_>>()
+            }
+            _ => targeted
+                .map(|s| Subject {
+                    key: s.host.clone(),
+                    name: &s.name,
+                    sensors: vec![s],
+                })
+                .collect(),
+        };
+        for subject in &subjects {
+            match evaluate(rule, subject, now, &conn).await {
+                Ok(Some(change)) => {
+                    match &change {
+                        AlertChange::Fired(alert) => {
+                            tracing::warn!("Alert fired: {}", alert.message)
+                        }
+                        AlertChange::Resolved(alert) => {
+                            tracing::info!("Alert resolved: {}", alert.message)
+                        }
+                    }
+                    changes.push(change);
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!(
+                    "Failed to evaluate alert rule {} for {}: {}",
+                    rule.name,
+                    subject.name,
+                    e
+                ),
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
+async fn evaluate(
+    rule: &AlertRule,
+    subject: &Subject<'_>,
+    now: i64,
+    conn: &DbConn,
+) -> Result<Option<AlertChange>, anyhow::Error> {
+    let last = conn.get_last_alert(rule.id, &subject.key).await?;
+    let firing = last.as_ref().filter(|a| a.resolved.is_none());
+    // whether the condition holds, and since when that is known
+    let (value, holds, observed) = match rule.condition.metric() {
+        Some((info, index, _)) => {
+            // the latest reading of every sensor, averaged for an area
+            let mut readings = vec![];
+            for sensor in &subject.sensors {
+                if !sensor.features.contains(info.feature) {
+                    continue;
+                }
+                let row = conn
+                    .get_measurements(info, Some(vec![sensor.host.clone()]), Some(1), None, None)
+                    .await?
+                    .pop();
+                if let Some(row) = row {
+                    if let Some(value) = row.values.get(index).copied().flatten() {
+                        readings.push((value, row.timestamp as i64));
+                    }
+                }
+            }
+            let Some(observed) = readings.iter().map(|(_, t)| *t).max() else {
+                return Ok(None);
+            };
+            let value = readings.iter().map(|(v, _)| v).sum::<f64>() / readings.len() as f64;
+            let holds = match firing {
+                Some(_) => !rule.condition.cleared(value, rule.hysteresis),
+                None => rule.condition.triggered(value),
+            };
+            (Some(value), holds, observed)
+        }
+        None => {
+            let Some(sensor) = subject.sensors.first() else {
+                return Ok(None);
+            };
+            let status = conn.get_sensor_status(&sensor.host).await?;
+            let offline = status
+                .as_ref()
+                .is_some_and(|s| s.state() == SensorState::Offline);
+            (
+                None,
+                offline,
+                status.and_then(|s| s.last_seen).unwrap_or(now),
+            )
+        }
+    };
+
+    if !holds {
+        conn.set_pending_since(rule.id, &subject.key, None).await?;
+        let Some(alert) = firing else {
+            return Ok(None);
+        };
+        // an evaluation running alongside may have resolved it already
+        if !conn.resolve_alert(alert.id, now).await? {
+            return Ok(None);
+        }
+        return Ok(Some(AlertChange::Resolved(Alert {
+            resolved: Some(now),
+            ..alert.clone()
+        })));
+    }
+    if firing.is_some() {
+        return Ok(None);
+    }
+    let since = match conn.get_pending_since(rule.id, &subject.key).await? {
+        Some(since) => since,
+        None => {
+            conn.set_pending_since(rule.id, &subject.key, Some(observed))
+                .await?;
+            observed
+        }
+    };
+    if now - since < rule.duration_s as i64 {
+        return Ok(None);
+    }
+    let cooling_down = last
+        .and_then(|a| a.resolved)
+        .is_some_and(|resolved| now - resolved < rule.cooldown_s as i64);
+    if cooling_down {
+        return Ok(None);
+    }
+    conn.set_pending_since(rule.id, &subject.key, None).await?;
+    let alert = conn
+        .create_alert(Alert {
+            rule_id: rule.id,
+            host: subject.key.clone(),
+            started: now,
+            value,
+            message: format!(
+                "{}: {} on {}",
+                rule.name,
+                rule.describe(&UnitPreferences::default()),
+                subject.name
+            ),
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(alert.map(AlertChange::Fired))
+}
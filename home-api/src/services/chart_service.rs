@@ -0,0 +1,649 @@
+use crate::{
+    features::{DerivedMetric, FeatureInfo, UnitPreferences},
+    models::{
+        db::{ChartBucket, SensorEntity},
+        json::{ChartAxis, ChartDataResponse, ChartPoint, ChartSeries},
+    },
+};
+use std::collections::BTreeMap;
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+/// Horizontal pixels taken by a bucket.
+const PIXELS_PER_BUCKET: u32 = 4;
+const MIN_BUCKETS: u64 = 24;
+const MAX_BUCKETS: u64 = 500;
+/// Bucket lengths lining up with the clock, longer buckets span whole days.
+const BUCKET_STEPS: &[u64] = &[
+    60,
+    5 * 60,
+    10 * 60,
+    15 * 60,
+    30 * 60,
+    60 * 60,
+    2 * 60 * 60,
+    3 * 60 * 60,
+    6 * 60 * 60,
+    12 * 60 * 60,
+    DAY_SECS,
+];
+/// Dash patterns of the lines, one per sensor of the chart.
+const DASHES: &[&[u8]] = &[&[], &[6, 3], &[2, 2], &[10, 4, 2, 4]];
+
+/// Length of the buckets splitting `range` seconds into a few pixels of `width` each,
+/// never shorter than the `finest` readings stored for the range.
+pub fn bucket_seconds(range: u64, width: u32, finest: u64) -> u64 {
+    let count = ((width / PIXELS_PER_BUCKET) as u64).clamp(MIN_BUCKETS, MAX_BUCKETS);
+    let length = range.div_ceil(count).max(finest).max(1);
+    BUCKET_STEPS
+        .iter()
+        .copied()
+        .find(|step| *step >= length)
+        .unwrap_or(length.div_ceil(DAY_SECS) * DAY_SECS)
+}
+
+/// Readable length of a bucket, `15 minutes` or `day`.
+pub fn bucket_label(seconds: u64) -> String {
+    let (count, unit) = match seconds {
+        s if s % DAY_SECS == 0 => (s / DAY_SECS, "day"),
+        s if s % 3600 == 0 => (s / 3600, "hour"),
+        s if s % 60 == 0 => (s / 60, "minute"),
+        s => (s, "second"),
+    };
+    match count {
+        1 => unit.to_string(),
+        count => format!("{} {}s", count, unit),
+    }
+}
+
+/// Bounds of an axis showing `values` with a margin of a tenth of their spread, at least 1,
+/// rounded outwards to whole numbers. `None` without any values.
+pub fn axis_range(values: impl Iterator<Item = f64>) -> Option<(f64, f64)> {
+    let (min, max) = values
+        .filter(|v| v.is_finite())
+        .fold(None, |range, v| match range {
+            None => Some((v, v)),
+            Some((min, max)) => Some((v.min(min), v.max(max))),
+        })?;
+    let margin = ((max - min) * 0.1).max(1.0);
+
+    Some(((min - margin).floor(), (max + margin).ceil()))
+}
+
+/// Readings charted as one group of series.
+#[derive(Debug, Clone, Default)]
+pub struct ChartSource {
+    /// Put before the labels of the series, to tell overlays apart.
+    pub name: Option<String>,
+    pub sensors: Vec<SensorEntity>,
+    pub buckets: Vec<ChartBucket>,
+    /// Seconds the readings are moved forward by to line up with the charted period.
+    pub shift: u64,
+    /// Draws every sensor instead of their average.
+    pub breakdown: bool,
+}
+
+/// Average of the sensors per metric and bucket, keeping the lowest and highest reading of any of them.
+fn average_sensors(buckets: &[ChartBucket]) -> Vec<ChartBucket> {
+    let mut result: BTreeMap<(u64, &str), (ChartBucket, usize)> = BTreeMap::new();
+    for b in buckets {
+        result
+            .entry((b.timestamp, &b.metric))
+            .and_modify(|(r, count)| {
+                r.min = r.min.min(b.min);
+                r.max = r.max.max(b.max);
+                r.avg += b.avg;
+                *count += 1;
+            })
+            .or_insert_with(|| {
+                (
+                    ChartBucket {
+                        host: String::new(),
+                        ..b.clone()
+                    },
+                    1,
+                )
+            });
+    }
+    result
+        .into_values()
+        .map(|(b, count)| ChartBucket {
+            avg: b.avg / count as f64,
+            ..b
+        })
+        .collect()
+}
+
+/// Buckets of the `derived` metrics, computed from the averages of the stored metrics of a bucket.
+fn derive_buckets(
+    info: &FeatureInfo,
+    derived: &[&DerivedMetric],
+    buckets: &[ChartBucket],
+) -> Vec<ChartBucket> {
+    let mut readings: BTreeMap<(&str, u64), Vec<Option<f64>>> = BTreeMap::new();
+    for b in buckets {
+        let Some(index) = info.metrics.iter().position(|m| m.name == b.metric) else {
+            continue;
+        };
+        readings
+            .entry((&b.host, b.timestamp))
+            .or_insert_with(|| vec![None; info.metrics.len()])[index] = Some(b.avg);
+    }
+    readings
+        .into_iter()
+        .flat_map(|((host, timestamp), values)| {
+            derived
+                .iter()
+                .filter_map(|d| d.value(&values).map(|value| (d, value)))
+                .map(|(d, value)| ChartBucket {
+                    host: host.to_string(),
+                    metric: d.metric.name.to_string(),
+                    timestamp,
+                    min: value,
+                    avg: value,
+                    max: value,
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Splits the buckets of every source into a series per sensor, or for their average,
+/// and metric, with the `derived` metrics next to the stored ones, in the `units` the user sees.
+/// Metrics of the same unit share an axis. The lines break where the buckets are more than
+/// `threshold` seconds apart.
+pub fn build_chart(
+    info: &FeatureInfo,
+    derived: &[&DerivedMetric],
+    units: UnitPreferences,
+    sources: &[ChartSource],
+    bucket: u64,
+    (from, to): (u64, u64),
+    threshold: u64,
+) -> ChartDataResponse {
+    let metrics = info
+        .metrics
+        .iter()
+        .chain(derived.iter().map(|d| &d.metric))
+        .collect::<Vec<_>>();
+    // the lines of every source with the buckets they are drawn from
+    let sources = sources
+        .iter()
+        .map(|source| {
+            let (lines, mut buckets) = match source.breakdown {
+                true => (
+                    source
+                        .sensors
+                        .iter()
+                        .map(|s| (s.host.clone(), Some(s.name.as_str())))
+                        .collect::<Vec<_>>(),
+                    source.buckets.clone(),
+                ),
+                false => (
+                    vec![(String::new(), None)],
+                    average_sensors(&source.buckets),
+                ),
+            };
+            // derived metrics are computed from the stored units
+            buckets.extend(derive_buckets(info, derived, &buckets));
+            for b in &mut buckets {
+                if let Some(metric) = metrics.iter().find(|m| m.name == b.metric) {
+                    let value = |v| units.value(metric, v);
+                    (b.min, b.avg, b.max) = (value(b.min), value(b.avg), value(b.max));
+                }
+            }
+            (source, lines, buckets)
+        })
+        .collect::<Vec<_>>();
+
+    // an axis per unit with the metrics drawn on it, and the axis of every metric
+    let mut axes: Vec<(ChartAxis, &str, Vec<&str>)> = vec![];
+    let mut metric_axes = vec![];
+    for metric in &metrics {
+        let unit = units.unit(metric).label;
+        let index = match axes.iter().position(|(_, axis_unit, _)| *axis_unit == unit) {
+            Some(index) => index,
+            None => {
+                axes.push((
+                    ChartAxis {
+                        id: format!("y{}", axes.len() + 1),
+                        position: match axes.len() % 2 {
+                            0 => "left",
+                            _ => "right",
+                        }
+                        .to_string(),
+                        color: metric.color.to_string(),
+                        grid_color: metric.grid_color.to_string(),
+                        ..Default::default()
+                    },
+                    unit,
+                    vec![],
+                ));
+                axes.len() - 1
+            }
+        };
+        axes[index].2.push(metric.label);
+        metric_axes.push(index);
+    }
+    let axes = axes
+        .into_iter()
+        .enumerate()
+        .map(|(index, (axis, unit, labels))| {
+            let range = axis_range(
+                sources
+                    .iter()
+                    .flat_map(|(_, _, buckets)| buckets)
+                    .filter(|b| {
+                        metrics
+                            .iter()
+                            .zip(&metric_axes)
+                            .any(|(m, axis)| *axis == index && m.name == b.metric)
+                    })
+                    .flat_map(|b| [b.min, b.max]),
+            );
+            ChartAxis {
+                label: format!("{} ({})", labels.join(", "), unit),
+                min: range.map(|r| r.0),
+                max: range.map(|r| r.1),
+                ..axis
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut series = vec![];
+    // every line of a metric gets the next dash pattern
+    let mut line = 0;
+    for (source, lines, buckets) in &sources {
+        for (host, sensor) in lines {
+            let mut drawn = false;
+            for (metric, axis) in metrics.iter().zip(&metric_axes) {
+                let mut points: Vec<ChartPoint> = vec![];
+                let mut last = None;
+                for b in buckets
+                    .iter()
+                    .filter(|b| b.host == *host && b.metric == metric.name)
+                {
+                    if let Some(last) = last.filter(|last| b.timestamp - last > threshold) {
+                        points.push(ChartPoint {
+                            x: (last + bucket + source.shift) * 1000,
+                            ..Default::default()
+                        });
+                    }
+                    points.push(ChartPoint {
+                        x: (b.timestamp + source.shift) * 1000,
+                        y: Some(b.avg),
+                        min: Some(b.min),
+                        max: Some(b.max),
+                    });
+                    last = Some(b.timestamp);
+                }
+                if points.is_empty() {
+                    continue;
+                }
+                let label = format!("{} ({})", metric.label, units.unit(metric).label);
+                series.push(ChartSeries {
+                    host: host.clone(),
+                    label: source
+                        .name
+                        .as_deref()
+                        .into_iter()
+                        .chain(*sensor)
+                        .chain([label.as_str()])
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    axis: axes[*axis].id.clone(),
+                    color: metric.color.to_string(),
+                    dash: DASHES[line % DASHES.len()].to_vec(),
+                    points,
+                });
+                drawn = true;
+            }
+            if drawn {
+                line += 1;
+            }
+        }
+    }
+
+    ChartDataResponse {
+        bucket,
+        bucket_label: bucket_label(bucket),
+        from: from * 1000,
+        to: to * 1000,
+        axes,
+        bands: sources.len() == 1 && sources.iter().all(|(_, lines, _)| lines.len() == 1),
+        series,
+        rollup: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{features, models::db::SensorFeatures};
+
+    fn sensor(name: &str, host: &str) -> SensorEntity {
+        SensorEntity {
+            name: name.to_string(),
+            host: host.to_string(),
+            features: SensorFeatures::TEMPERATURE,
+            ..Default::default()
+        }
+    }
+
+    fn bucket(host: &str, metric: &str, timestamp: u64, avg: f64) -> ChartBucket {
+        ChartBucket {
+            host: host.to_string(),
+            metric: metric.to_string(),
+            timestamp,
+            min: avg - 1.0,
+            avg,
+            max: avg + 1.0,
+        }
+    }
+
+    #[test]
+    fn bucket_seconds_fits_width() {
+        // a day over 800 pixels is 200 buckets of at least 432 seconds
+        assert_eq!(bucket_seconds(DAY_SECS, 800, 60), 10 * 60);
+        assert_eq!(bucket_seconds(7 * DAY_SECS, 800, 60), 60 * 60);
+        // 30 days never go below the hourly rollups
+        assert_eq!(bucket_seconds(30 * DAY_SECS, 4000, 3600), 2 * 60 * 60);
+    }
+
+    #[test]
+    fn bucket_seconds_keeps_stored_resolution() {
+        assert_eq!(bucket_seconds(DAY_SECS, 4000, 15 * 60), 15 * 60);
+        assert_eq!(bucket_seconds(60 * 60, 800, 15 * 60), 15 * 60);
+        assert_eq!(bucket_seconds(DAY_SECS, 800, DAY_SECS), DAY_SECS);
+    }
+
+    #[test]
+    fn bucket_seconds_limits_bucket_count() {
+        // narrow charts still get a bucket per hour of a day
+        assert_eq!(bucket_seconds(DAY_SECS, 10, 60), 60 * 60);
+        // long ranges use whole days
+        assert_eq!(bucket_seconds(365 * DAY_SECS, 100, DAY_SECS), 15 * DAY_SECS);
+    }
+
+    #[test]
+    fn bucket_label_names_unit() {
+        assert_eq!(bucket_label(15 * 60), "15 minutes");
+        assert_eq!(bucket_label(60 * 60), "hour");
+        assert_eq!(bucket_label(6 * 60 * 60), "6 hours");
+        assert_eq!(bucket_label(DAY_SECS), "day");
+        assert_eq!(bucket_label(2 * DAY_SECS), "2 days");
+        assert_eq!(bucket_label(90), "90 seconds");
+    }
+
+    #[test]
+    fn average_sensors_per_bucket_and_metric() {
+        let averaged = average_sensors(&[
+            bucket("a", "temperature", 600, 20.0),
+            bucket("a", "humidity", 0, 40.0),
+            bucket("a", "temperature", 0, 20.0),
+            bucket("b", "temperature", 0, 22.0),
+            bucket("b", "humidity", 0, 50.0),
+        ]);
+        let values = averaged
+            .iter()
+            .map(|b| (b.timestamp, b.metric.as_str(), b.min, b.avg, b.max))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [
+                (0, "humidity", 39.0, 45.0, 51.0),
+                (0, "temperature", 19.0, 21.0, 23.0),
+                (600, "temperature", 19.0, 20.0, 21.0)
+            ]
+        );
+        assert!(averaged.iter().all(|b| b.host.is_empty()));
+    }
+
+    #[test]
+    fn axis_range_adds_margin() {
+        assert_eq!(
+            axis_range([10.0, 20.0, 30.0].into_iter()),
+            Some((8.0, 32.0))
+        );
+        assert_eq!(axis_range([40.0, 60.0].into_iter()), Some((38.0, 62.0)));
+    }
+
+    #[test]
+    fn axis_range_keeps_minimal_margin() {
+        assert_eq!(axis_range([21.5].into_iter()), Some((20.0, 23.0)));
+        assert_eq!(axis_range([21.2, 21.8].into_iter()), Some((20.0, 23.0)));
+    }
+
+    #[test]
+    fn axis_range_rounds_negative_values_outwards() {
+        assert_eq!(axis_range([-3.5, -0.5].into_iter()), Some((-5.0, 1.0)));
+    }
+
+    #[test]
+    fn axis_range_without_values() {
+        assert_eq!(axis_range(std::iter::empty()), None);
+        assert_eq!(axis_range([f64::NAN].into_iter()), None);
+    }
+
+    #[test]
+    fn build_chart_splits_sensors_and_metrics() {
+        let info = features::by_key("temp").unwrap();
+        let source = ChartSource {
+            sensors: vec![sensor("Kitchen", "10.0.0.1"), sensor("Hall", "10.0.0.2")],
+            buckets: vec![
+                bucket("10.0.0.1", "temperature", 0, 21.0),
+                bucket("10.0.0.1", "humidity", 0, 40.0),
+                bucket("10.0.0.2", "temperature", 0, 19.0),
+            ],
+            breakdown: true,
+            ..Default::default()
+        };
+        let chart = build_chart(
+            info,
+            &[],
+            UnitPreferences::default(),
+            &[source],
+            900,
+            (0, 900),
+            1800,
+        );
+
+        assert_eq!(chart.axes.len(), 2);
+        assert_eq!(chart.axes[0].min, Some(17.0));
+        assert_eq!(chart.axes[0].max, Some(23.0));
+        assert_eq!(chart.axes[1].position, "right");
+        assert_eq!(chart.series.len(), 3);
+        assert!(!chart.bands);
+        assert_eq!(chart.series[0].label, "Kitchen Temperature (°C)");
+        assert_eq!(chart.series[1].axis, "y2");
+        assert_eq!(chart.series[2].host, "10.0.0.2");
+        assert_eq!(chart.series[0].dash, chart.series[1].dash);
+        assert_ne!(chart.series[0].dash, chart.series[2].dash);
+    }
+
+    #[test]
+    fn build_chart_averages_sensors() {
+        let info = features::by_key("temp").unwrap();
+        let source = ChartSource {
+            sensors: vec![sensor("Kitchen", "10.0.0.1"), sensor("Hall", "10.0.0.2")],
+            buckets: vec![
+                bucket("10.0.0.1", "temperature", 0, 21.0),
+                bucket("10.0.0.2", "temperature", 0, 19.0),
+                bucket("10.0.0.2", "temperature", 900, 19.5),
+            ],
+            ..Default::default()
+        };
+        let chart = build_chart(
+            info,
+            &[],
+            UnitPreferences::default(),
+            &[source],
+            900,
+            (0, 1800),
+            1800,
+        );
+
+        assert_eq!(chart.series.len(), 1);
+        assert!(chart.bands);
+        assert_eq!(chart.series[0].label, "Temperature (°C)");
+        assert_eq!(
+            chart.series[0].points,
+            vec![
+                ChartPoint {
+                    x: 0,
+                    y: Some(20.0),
+                    min: Some(18.0),
+                    max: Some(22.0),
+                },
+                ChartPoint {
+                    x: 900_000,
+                    y: Some(19.5),
+                    min: Some(18.5),
+                    max: Some(20.5),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn build_chart_shifts_overlays() {
+        let info = features::by_key("temp").unwrap();
+        let sources = [
+            ChartSource {
+                sensors: vec![sensor("Kitchen", "10.0.0.1")],
+                buckets: vec![bucket("10.0.0.1", "temperature", 7200, 21.0)],
+                ..Default::default()
+            },
+            ChartSource {
+                name: Some("Previous".to_string()),
+                sensors: vec![sensor("Kitchen", "10.0.0.1")],
+                buckets: vec![bucket("10.0.0.1", "temperature", 3600, 20.0)],
+                shift: 3600,
+                ..Default::default()
+            },
+        ];
+        let chart = build_chart(
+            info,
+            &[],
+            UnitPreferences::default(),
+            &sources,
+            900,
+            (7200, 10800),
+            1800,
+        );
+
+        assert_eq!(chart.series.len(), 2);
+        assert_eq!(chart.series[1].label, "Previous Temperature (°C)");
+        assert_eq!(chart.series[1].points[0].x, 7_200_000);
+        assert_ne!(chart.series[0].dash, chart.series[1].dash);
+    }
+
+    #[test]
+    fn build_chart_breaks_lines_at_gaps() {
+        let info = features::by_key("temp").unwrap();
+        let source = ChartSource {
+            sensors: vec![sensor("Kitchen", "10.0.0.1")],
+            buckets: vec![
+                bucket("10.0.0.1", "temperature", 0, 21.0),
+                bucket("10.0.0.1", "temperature", 900, 21.5),
+                bucket("10.0.0.1", "temperature", 9000, 22.0),
+            ],
+            breakdown: true,
+            ..Default::default()
+        };
+        let chart = build_chart(
+            info,
+            &[],
+            UnitPreferences::default(),
+            &[source],
+            900,
+            (0, 9900),
+            1800,
+        );
+
+        assert_eq!(chart.series.len(), 1);
+        assert_eq!(chart.series[0].label, "Kitchen Temperature (°C)");
+        let points = &chart.series[0].points;
+        assert_eq!(points.len(), 4);
+        assert_eq!(
+            points[2],
+            ChartPoint {
+                x: 1_800_000,
+                ..Default::default()
+            }
+        );
+        assert_eq!(points[3].y, Some(22.0));
+        assert_eq!(points[3].min, Some(21.0));
+    }
+
+    #[test]
+    fn build_chart_derives_metrics() {
+        let info = features::by_key("temp").unwrap();
+        let derived = [
+            info.derived_metric("dew-point").unwrap(),
+            info.derived_metric("absolute-humidity").unwrap(),
+        ];
+        let source = ChartSource {
+            sensors: vec![sensor("Kitchen", "10.0.0.1")],
+            buckets: vec![
+                bucket("10.0.0.1", "temperature", 0, 25.0),
+                bucket("10.0.0.1", "humidity", 0, 60.0),
+                bucket("10.0.0.1", "temperature", 900, 25.0),
+            ],
+            ..Default::default()
+        };
+        let chart = build_chart(
+            info,
+            &derived,
+            UnitPreferences::default(),
+            &[source],
+            900,
+            (0, 1800),
+            1800,
+        );
+
+        // the dew point shares the axis of the temperature
+        assert_eq!(chart.axes.len(), 3);
+        assert_eq!(chart.axes[0].label, "Temperature, Dew point (°C)");
+        assert_eq!(chart.axes[2].label, "Absolute humidity (g/m³)");
+        assert_eq!(chart.series.len(), 4);
+        assert_eq!(chart.series[2].label, "Dew point (°C)");
+        assert_eq!(chart.series[2].axis, "y1");
+        assert_eq!(chart.series[2].color, "rgba(75, 192, 192, 0.9)");
+        // no humidity, no dew point
+        assert_eq!(chart.series[2].points.len(), 1);
+        assert_eq!(chart.series[2].points[0].y, Some(16.7));
+        assert_eq!(chart.series[3].points[0].y, Some(13.82));
+    }
+
+    #[test]
+    fn build_chart_converts_units() {
+        let info = features::by_key("temp").unwrap();
+        let derived = [info.derived_metric("dew-point").unwrap()];
+        let source = ChartSource {
+            sensors: vec![sensor("Kitchen", "10.0.0.1")],
+            buckets: vec![
+                bucket("10.0.0.1", "temperature", 0, 25.0),
+                bucket("10.0.0.1", "humidity", 0, 60.0),
+            ],
+            ..Default::default()
+        };
+        let units = UnitPreferences::new("f").unwrap();
+        let chart = build_chart(info, &derived, units, &[source], 900, (0, 900), 1800);
+
+        assert_eq!(chart.axes[0].label, "Temperature, Dew point (°F)");
+        assert_eq!(chart.axes[1].label, "Humidity (%)");
+        assert_eq!(chart.series[0].label, "Temperature (°F)");
+        assert_eq!(
+            chart.series[0].points[0],
+            ChartPoint {
+                x: 0,
+                y: Some(77.0),
+                min: Some(75.2),
+                max: Some(78.8),
+            }
+        );
+        // humidity stays in percent, the dew point is derived before converting
+        assert_eq!(chart.series[1].points[0].y, Some(60.0));
+        assert_eq!(chart.series[2].points[0].y, Some(62.06));
+    }
+}
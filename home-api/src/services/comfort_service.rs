@@ -0,0 +1,123 @@
+/// Magnus coefficients over water, valid from -45 °C to 60 °C.
+const MAGNUS_A: f64 = 17.625;
+const MAGNUS_B: f64 = 243.04;
+/// Below this heat index in °F the simple formula of the NWS is used instead of the regression.
+const HEAT_INDEX_REGRESSION_F: f64 = 80.0;
+
+fn relative_humidity(humidity: f64) -> Option<f64> {
+    (humidity > 0.0 && humidity <= 100.0).then_some(humidity)
+}
+
+/// Temperature in °C at which the air of `temperature` °C and `humidity` % starts condensing.
+pub fn dew_point(temperature: f64, humidity: f64) -> Option<f64> {
+    let humidity = relative_humidity(humidity)?;
+    let gamma = (humidity / 100.0).ln() + MAGNUS_A * temperature / (MAGNUS_B + temperature);
+    let dew_point = MAGNUS_B * gamma / (MAGNUS_A - gamma);
+
+    dew_point.is_finite().then_some(dew_point)
+}
+
+/// Grams of water vapour in a cubic metre of air of `temperature` °C and `humidity` %.
+pub fn absolute_humidity(temperature: f64, humidity: f64) -> Option<f64> {
+    let humidity = relative_humidity(humidity)?;
+    let saturation = 6.112 * (17.67 * temperature / (temperature + 243.5)).exp();
+    let absolute = saturation * humidity * 2.1674 / (273.15 + temperature);
+
+    absolute.is_finite().then_some(absolute)
+}
+
+/// Temperature in °C the air of `temperature` °C and `humidity` % feels like,
+/// following the heat index of the US National Weather Service.
+pub fn heat_index(temperature: f64, humidity: f64) -> Option<f64> {
+    let humidity = relative_humidity(humidity)?;
+    let t = temperature * 9.0 / 5.0 + 32.0;
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + humidity * 0.094);
+    let index = match (simple + t) / 2.0 {
+        index if index < HEAT_INDEX_REGRESSION_F => index,
+        _ => {
+            let regression = -42.379 + 2.04901523 * t + 10.14333127 * humidity
+                - 0.22475541 * t * humidity
+                - 0.00683783 * t * t
+                - 0.05481717 * humidity * humidity
+                + 0.00122874 * t * t * humidity
+                + 0.00085282 * t * humidity * humidity
+                - 0.00000199 * t * t * humidity * humidity;
+            match humidity {
+                h if h < 13.0 && (80.0..=112.0).contains(&t) => {
+                    regression - (13.0 - h) / 4.0 * ((17.0 - (t - 95.0).abs()) / 17.0).sqrt()
+                }
+                h if h > 85.0 && (80.0..=87.0).contains(&t) => {
+                    regression + (h - 85.0) / 10.0 * ((87.0 - t) / 5.0)
+                }
+                _ => regression,
+            }
+        }
+    };
+    let index = (index - 32.0) * 5.0 / 9.0;
+
+    index.is_finite().then_some(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(value: Option<f64>, expected: f64, tolerance: f64) {
+        let value = value.expect("a value");
+        assert!(
+            (value - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            value,
+            tolerance,
+            expected
+        );
+    }
+
+    fn fahrenheit(value: f64) -> f64 {
+        (value - 32.0) * 5.0 / 9.0
+    }
+
+    #[test]
+    fn dew_point_reference_values() {
+        assert_near(dew_point(25.0, 60.0), 16.7, 0.05);
+        assert_near(dew_point(20.0, 50.0), 9.3, 0.05);
+        assert_near(dew_point(0.0, 80.0), -3.0, 0.05);
+        assert_near(dew_point(-10.0, 90.0), -11.3, 0.05);
+    }
+
+    #[test]
+    fn dew_point_of_saturated_air_is_its_temperature() {
+        assert_near(dew_point(30.0, 100.0), 30.0, 0.001);
+        assert_near(dew_point(5.0, 100.0), 5.0, 0.001);
+    }
+
+    #[test]
+    fn absolute_humidity_reference_values() {
+        assert_near(absolute_humidity(25.0, 60.0), 13.8, 0.05);
+        assert_near(absolute_humidity(20.0, 50.0), 8.6, 0.05);
+        assert_near(absolute_humidity(0.0, 80.0), 3.9, 0.05);
+        assert_near(absolute_humidity(30.0, 100.0), 30.4, 0.1);
+    }
+
+    #[test]
+    fn heat_index_reference_values() {
+        // values of the heat index chart of the National Weather Service in °F
+        assert_near(heat_index(fahrenheit(90.0), 70.0), fahrenheit(106.0), 0.5);
+        assert_near(heat_index(fahrenheit(80.0), 40.0), fahrenheit(80.0), 0.5);
+        assert_near(heat_index(fahrenheit(100.0), 40.0), fahrenheit(109.0), 0.5);
+        assert_near(heat_index(fahrenheit(86.0), 90.0), fahrenheit(105.0), 0.5);
+    }
+
+    #[test]
+    fn heat_index_of_mild_air_is_close_to_its_temperature() {
+        assert_near(heat_index(21.0, 50.0), 21.0, 0.5);
+        assert_near(heat_index(fahrenheit(104.0), 10.0), fahrenheit(98.0), 0.5);
+    }
+
+    #[test]
+    fn invalid_humidity_has_no_value() {
+        assert_eq!(dew_point(20.0, 0.0), None);
+        assert_eq!(absolute_humidity(20.0, -5.0), None);
+        assert_eq!(heat_index(20.0, 120.0), None);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::models::db::{
+    ImportOptions, ImportResult, MeasurementEntry, MeasurementRow, RejectedRow,
+};
+use csv::{ReaderBuilder, StringRecord, Trim};
+
+/// Number of valid rows previewed before importing.
+const PREVIEW_ROWS: usize = 10;
+
+/// Reads the measurements of a CSV file, collecting the rows that can't be stored instead of failing.
+pub fn parse_measurements(
+    options: &ImportOptions,
+    data: &[u8],
+) -> Result<ImportResult, anyhow::Error> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .has_headers(options.has_header)
+        .flexible(true)
+        .trim(Trim::All)
+        .from_reader(data);
+    let headers = match options.has_header {
+        true => Some(reader.headers()?.clone()),
+        false => None,
+    };
+    let timestamp_index = column_index(headers.as_ref(), &options.timestamp_column)?;
+    // position of every mapped column within the metrics of the feature
+    let mut columns = vec![];
+    for column in &options.columns {
+        let metric = options
+            .info
+            .metrics
+            .iter()
+            .position(|m| m.name == column.metric.name)
+            .ok_or(anyhow::anyhow!("Invalid metric `{}`", column.metric.name))?;
+        columns.push((
+            metric,
+            column_index(headers.as_ref(), &column.column)?,
+            column,
+        ));
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let mut result = ImportResult::default();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                result.rejected.push(RejectedRow {
+                    line: e.position().map_or(0, |p| p.line()),
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map_or(0, |p| p.line());
+        match parse_row(options, &record, timestamp_index, &columns, now) {
+            Ok(row) => {
+                for (metric, value) in options.info.metrics.iter().zip(&row.values) {
+                    if let Some(value) = value {
+                        result.entries.push(MeasurementEntry {
+                            host: options.host.clone(),
+                            feature: options.info.feature,
+                            metric: metric.name.to_string(),
+                            timestamp: row.timestamp,
+                            value: *value,
+                            flag: None,
+                        });
+                    }
+                }
+                result.rows += 1;
+                if result.preview.len() < PREVIEW_ROWS {
+                    result.preview.push(row);
+                }
+            }
+            Err(e) => result.rejected.push(RejectedRow {
+                line,
+                reason: e.to_string(),
+            }),
+        }
+    }
+
+    Ok(result)
+}
+
+fn parse_row(
+    options: &ImportOptions,
+    record: &StringRecord,
+    timestamp_index: usize,
+    columns: &[(usize, usize, &crate::models::db::ImportColumn)],
+    now: i64,
+) -> Result<MeasurementRow, anyhow::Error> {
+    let timestamp = record
+        .get(timestamp_index)
+        .filter(|v| !v.is_empty())
+        .ok_or(anyhow::anyhow!("Missing timestamp"))?;
+    let timestamp = options
+        .timestamp_format
+        .parse(timestamp, options.timezone)?;
+    if timestamp <= 0 || timestamp > now {
+        anyhow::bail!("Timestamp `{}` is out of range", timestamp);
+    }
+    let mut values = vec![None; options.info.metrics.len()];
+    for (metric, index, column) in columns {
+        let Some(value) = record.get(*index).filter(|v| !v.is_empty()) else {
+            continue;
+        };
+        // decimal commas are common next to semicolon separated columns
+        let number = match options.delimiter {
+            b',' => value.to_string(),
+            _ => value.replace(',', "."),
+        };
+        let number = number.parse::<f64>().map_err(|_| {
+            anyhow::anyhow!("Invalid {} `{}`", column.metric.label.to_lowercase(), value)
+        })?;
+        // converted values are kept to the precision of the sensors, not of the conversion
+        let number = match column.metric.units.first() {
+            Some(base) if base.key != column.unit.key => {
+                ((column.unit.to_base)(number) * 100.0).round() / 100.0
+            }
+            _ => number,
+        };
+        if !number.is_finite() {
+            anyhow::bail!("Invalid {} `{}`", column.metric.label.to_lowercase(), value);
+        }
+        values[*metric] = Some(number);
+    }
+    if values.iter().all(Option::is_none) {
+        anyhow::bail!("No values");
+    }
+
+    Ok(MeasurementRow {
+        host: options.host.clone(),
+        timestamp: timestamp as u64,
+        values,
+        flags: Vec::new(),
+    })
+}
+
+/// Finds a column by its header name, or by its 1-based position.
+fn column_index(headers: Option<&StringRecord>, column: &str) -> Result<usize, anyhow::Error> {
+    if let Some(index) = headers.and_then(|headers| {
+        headers
+            .iter()
+            .position(|header| header.eq_ignore_ascii_case(column))
+    }) {
+        return Ok(index);
+    }
+    match column.parse::<usize>() {
+        Ok(position) if position > 0 => Ok(position - 1),
+        _ => Err(anyhow::anyhow!("Unknown column `{}`", column)),
+    }
+}
@@ -1,4 +1,12 @@
+pub mod alert_service;
+pub mod chart_service;
+pub mod comfort_service;
 pub mod http_client;
+pub mod import_service;
+pub mod notification_service;
+pub mod retention_service;
 pub mod scanner_service;
 pub mod sensor_data_service;
+pub mod sensor_health_service;
 pub mod sensor_service;
+pub mod validation_service;
@@ -0,0 +1,473 @@
+use super::sensor_data_service::SensorStateChange;
+use crate::{
+    database::{notifications::NotificationDatabase, sensors::SensorDatabase, DbConn, DbPool},
+    models::db::{
+        ChannelKind, DeliveryStatus, Notification, NotificationChannel, NotificationDelivery,
+        NotificationTopics, SensorState,
+    },
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use lettre::{
+    message::header::ContentType,
+    transport::smtp::{
+        authentication::Credentials,
+        client::{Tls, TlsParameters},
+        extension::ClientId,
+    },
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+/// Port of SMTP submissions over implicit TLS.
+const SMTPS_PORT: u16 = 465;
+/// Attempts after which a delivery is given up.
+const MAX_ATTEMPTS: u32 = 5;
+const RETRY_MIN_SECS: i64 = 30;
+const RETRY_MAX_SECS: i64 = 60 * 60;
+
+/// Queues `notification` for every channel a user subscribed to its topic on.
+pub async fn notify(pool: &DbPool, notification: Notification) -> Result<usize, anyhow::Error> {
+    let conn = pool.get().await?;
+    let channels = conn.get_channels().await?;
+    // users sharing a webhook or push channel get a single delivery, mails go to every address
+    let mut targets: Vec<(&NotificationChannel, Option<String>)> = vec![];
+    for subscription in conn.get_subscriptions(None).await? {
+        if !subscription.topics.contains(notification.topic) {
+            continue;
+        }
+        let Some(channel) = channels.iter().find(|c| c.id == subscription.channel_id) else {
+            continue;
+        };
+        let recipient = match channel.kind {
+            ChannelKind::Smtp => match subscription.address {
+                Some(address) => Some(address),
+                None => continue,
+            },
+            _ => None,
+        };
+        if !targets
+            .iter()
+            .any(|(c, r)| c.id == channel.id && *r == recipient)
+        {
+            targets.push((channel, recipient));
+        }
+    }
+    for (channel, recipient) in targets.iter() {
+        conn.create_delivery(NotificationDelivery {
+            channel_id: channel.id,
+            recipient: recipient.clone(),
+            topic: notification.topic,
+            title: notification.title.clone(),
+            body: channel.render(&notification),
+            created: notification.time,
+            ..Default::default()
+        })
+        .await?;
+    }
+
+    Ok(targets.len())
+}
+
+/// Queues `notification`, logging the error when that fails.
+pub async fn notify_or_log(pool: &DbPool, notification: Notification) {
+    if let Err(e) = notify(pool, notification).await {
+        tracing::error!("Failed to queue notification: {}", e);
+    }
+}
+
+/// Periodically sends the queued notifications.
+pub fn start_notification_delivery(pool: DbPool) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = deliver_due(&pool).await {
+                tracing::error!("Failed to deliver notifications: {}", e);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+/// Notifies about sensors going offline and coming back.
+pub fn forward_state_changes(pool: DbPool, mut events: broadcast::Receiver<SensorStateChange>) {
+    tokio::spawn(async move {
+        loop {
+            let change = match events.recv().await {
+                Ok(change) => change,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            let name = match pool.get().await {
+                Ok(conn) => conn.get_sensor(&change.host).await.ok().flatten(),
+                Err(_) => None,
+            }
+            .map_or(change.host.clone(), |s| s.name);
+            let notification = match (change.previous, change.current) {
+                (_, SensorState::Offline) => Notification::new(
+                    NotificationTopics::SENSOR_OFFLINE,
+                    format!("Sensor {} is offline", name),
+                    change
+                        .status
+                        .last_error
+                        .unwrap_or("No response".to_string()),
+                ),
+                (SensorState::Offline, SensorState::Online) => Notification::new(
+                    NotificationTopics::SENSOR_ONLINE,
+                    format!("Sensor {} is back online", name),
+                    format!("{} answers again", change.host),
+                ),
+                _ => continue,
+            };
+            notify_or_log(&pool, notification).await;
+        }
+    });
+}
+
+async fn deliver_due(pool: &DbPool) -> Result<(), anyhow::Error> {
+    let conn = pool.get().await?;
+    for delivery in conn.get_due_deliveries(Utc::now().timestamp()).await? {
+        attempt(&conn, delivery).await?;
+    }
+
+    Ok(())
+}
+
+/// Tries to send `delivery` once, scheduling a retry with back-off when that fails.
+pub async fn attempt(
+    conn: &DbConn,
+    mut delivery: NotificationDelivery,
+) -> Result<NotificationDelivery, anyhow::Error> {
+    delivery.attempts += 1;
+    let result = match conn.get_channel(delivery.channel_id).await? {
+        Some(channel) => send(&channel, &delivery).await,
+        None => Err(anyhow::anyhow!("Channel was deleted")),
+    };
+    match result {
+        Ok(()) => {
+            delivery.status = DeliveryStatus::Sent;
+            delivery.next_attempt = None;
+            delivery.error = None;
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Failed to deliver notification {} (attempt {}): {}",
+                delivery.id,
+                delivery.attempts,
+                e
+            );
+            delivery.error = Some(e.to_string());
+            if delivery.attempts >= MAX_ATTEMPTS {
+                delivery.status = DeliveryStatus::Failed;
+                delivery.next_attempt = None;
+            } else {
+                let backoff = (RETRY_MIN_SECS << (delivery.attempts - 1)).min(RETRY_MAX_SECS);
+                delivery.next_attempt = Some(Utc::now().timestamp() + backoff);
+            }
+        }
+    }
+    conn.update_delivery(&delivery).await?;
+
+    Ok(delivery)
+}
+
+async fn send(
+    channel: &NotificationChannel,
+    delivery: &NotificationDelivery,
+) -> Result<(), anyhow::Error> {
+    let client = reqwest::Client::builder().timeout(SEND_TIMEOUT).build()?;
+    let request = match channel.kind {
+        ChannelKind::Webhook => client.post(&channel.url).json(&serde_json::json!({
+            "topic": delivery.topic.key(),
+            "title": delivery.title,
+            "message": delivery.body,
+            "time": delivery.created,
+        })),
+        ChannelKind::Push => client
+            .post(&channel.url)
+            .header("Title", encode_header(&delivery.title))
+            .header("Tags", delivery.topic.key())
+            .body(delivery.body.clone()),
+        ChannelKind::Smtp => {
+            return tokio::time::timeout(SEND_TIMEOUT, send_mail(channel, delivery))
+                .await
+                .map_err(|_| anyhow::anyhow!("SMTP server timed out"))?;
+        }
+    };
+    let request = match &channel.credentials {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    };
+    request.send().await?.error_for_status()?;
+
+    Ok(())
+}
+
+/// Encodes non ASCII header values as an RFC 2047 encoded word.
+fn encode_header(value: &str) -> String {
+    match value.is_ascii() {
+        true => value.to_string(),
+        false => format!("=?UTF-8?B?{}?=", STANDARD.encode(value)),
+    }
+}
+
+/// Sends a plain text mail through the SMTP server of `channel`, over implicit TLS on port 465
+/// and STARTTLS elsewhere; credentials are only ever sent encrypted.
+async fn send_mail(
+    channel: &NotificationChannel,
+    delivery: &NotificationDelivery,
+) -> Result<(), anyhow::Error> {
+    let recipient = delivery
+        .recipient
+        .as_deref()
+        .ok_or(anyhow::anyhow!("Missing mail recipient"))?;
+    let sender = channel.sender.as_deref().unwrap_or("home-api@localhost");
+    // a line break would end the header early and pass the rest on as its own
+    if [sender, recipient, &delivery.title]
+        .iter()
+        .any(|v| v.contains(['\r', '\n']))
+    {
+        anyhow::bail!("Mail addresses and titles can't contain line breaks");
+    }
+    let (host, port) = channel
+        .url
+        .rsplit_once(':')
+        .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+        .ok_or(anyhow::anyhow!("SMTP server must be `host:port`"))?;
+    let parameters = TlsParameters::new(host.to_string())?;
+    let tls = match (port, &channel.credentials) {
+        (SMTPS_PORT, _) => Tls::Wrapper(parameters),
+        (_, Some(_)) => Tls::Required(parameters),
+        // a relay without authentication may still be reached unencrypted
+        (_, None) => Tls::Opportunistic(parameters),
+    };
+    let mut transport = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        .port(port)
+        .tls(tls)
+        .hello_name(ClientId::Domain("home-api".to_string()))
+        .timeout(Some(SEND_TIMEOUT));
+    if let Some(credentials) = &channel.credentials {
+        let (user, password) = credentials
+            .split_once(':')
+            .ok_or(anyhow::anyhow!("SMTP credentials must be `user:password`"))?;
+        transport = transport.credentials(Credentials::new(user.to_string(), password.to_string()));
+    }
+    let message = Message::builder()
+        .from(sender.parse()?)
+        .to(recipient.parse()?)
+        .subject(&delivery.title)
+        .header(ContentType::TEXT_PLAIN)
+        .body(delivery.body.clone())?;
+    transport.build().send(message).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::db::DEFAULT_TEMPLATE;
+    use serde_json::Value;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    fn channel(kind: ChannelKind, url: String, credentials: &str) -> NotificationChannel {
+        NotificationChannel {
+            id: 1,
+            name: "test".to_string(),
+            kind,
+            url,
+            credentials: Some(credentials.to_string()),
+            sender: Some("home@example.com".to_string()),
+            template: DEFAULT_TEMPLATE.to_string(),
+        }
+    }
+
+    fn delivery() -> NotificationDelivery {
+        NotificationDelivery {
+            channel_id: 1,
+            recipient: Some("user@example.com".to_string()),
+            topic: NotificationTopics::ALERT_FIRED,
+            title: "Alert fired".to_string(),
+            body: "Too warm\n.in the attic".to_string(),
+            created: 1700000000,
+            ..Default::default()
+        }
+    }
+
+    /// Answers a single HTTP request with `200 OK`, returning the request it got.
+    async fn http_server() -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/notify", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+            let mut buffer = [0; 1024];
+            // read until the headers and as much body as they announce are in
+            loop {
+                let read = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+                if read == 0 {
+                    break;
+                }
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, server)
+    }
+
+    /// Accepts a single mail, returning the lines it got.
+    async fn smtp_server() -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut read = BufReader::new(read);
+            write.write_all(b"220 localhost\r\n").await.unwrap();
+            let mut lines = vec![];
+            let mut data = false;
+            loop {
+                let mut line = String::new();
+                if read.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end_matches("\r\n").to_string();
+                lines.push(line.clone());
+                let reply: &[u8] = match line.as_str() {
+                    "." if data => {
+                        data = false;
+                        b"250 Queued\r\n"
+                    }
+                    _ if data => continue,
+                    "DATA" => {
+                        data = true;
+                        b"354 Go ahead\r\n"
+                    }
+                    "QUIT" => b"221 Bye\r\n",
+                    l if l.starts_with("EHLO") => b"250-localhost\r\n250 AUTH PLAIN\r\n",
+                    l if l.starts_with("AUTH") => b"235 Authenticated\r\n",
+                    _ => b"250 OK\r\n",
+                };
+                write.write_all(reply).await.unwrap();
+            }
+            lines
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn webhooks_post_the_notification_as_json() {
+        let (url, server) = http_server().await;
+        send(&channel(ChannelKind::Webhook, url, "secret"), &delivery())
+            .await
+            .unwrap();
+        let request = server.await.unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("POST /notify HTTP/1.1"));
+        assert!(head.to_lowercase().contains("authorization: bearer secret"));
+        let body = serde_json::from_str::<Value>(body).unwrap();
+        assert_eq!(body["topic"], NotificationTopics::ALERT_FIRED.key());
+        assert_eq!(body["title"], "Alert fired");
+        assert_eq!(body["message"], "Too warm\n.in the attic");
+        assert_eq!(body["time"], 1700000000);
+    }
+
+    #[tokio::test]
+    async fn pushes_send_the_title_as_header() {
+        let (url, server) = http_server().await;
+        let delivery = NotificationDelivery {
+            title: "Temperatur über 30 °C".to_string(),
+            ..delivery()
+        };
+        send(&channel(ChannelKind::Push, url, "secret"), &delivery)
+            .await
+            .unwrap();
+        let request = server.await.unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        let head = head.to_lowercase();
+        assert!(head.contains("authorization: bearer secret"));
+        assert!(head.contains(&format!(
+            "title: {}",
+            encode_header(&delivery.title).to_lowercase()
+        )));
+        assert!(head.contains(&format!("tags: {}", NotificationTopics::ALERT_FIRED.key())));
+        assert_eq!(body, "Too warm\n.in the attic");
+    }
+
+    #[tokio::test]
+    async fn mails_go_through_the_smtp_dialog() {
+        let (url, server) = smtp_server().await;
+        let channel = NotificationChannel {
+            credentials: None,
+            ..channel(ChannelKind::Smtp, url, "")
+        };
+        send(&channel, &delivery()).await.unwrap();
+        let lines = server.await.unwrap();
+        for line in [
+            "EHLO home-api",
+            "MAIL FROM:<home@example.com>",
+            "RCPT TO:<user@example.com>",
+            "DATA",
+            "Subject: Alert fired",
+            "Too warm",
+            "..in the attic",
+            ".",
+            "QUIT",
+        ] {
+            assert!(lines.iter().any(|l| l == line), "missing `{}`", line);
+        }
+    }
+
+    #[tokio::test]
+    async fn credentials_are_not_sent_unencrypted() {
+        let (url, server) = smtp_server().await;
+        let channel = channel(ChannelKind::Smtp, url, "user:password");
+        assert!(send(&channel, &delivery()).await.is_err());
+        let lines = server.await.unwrap();
+        assert!(!lines.iter().any(|l| l.starts_with("AUTH")));
+    }
+
+    #[tokio::test]
+    async fn mails_with_line_breaks_in_headers_are_rejected() {
+        let (url, server) = smtp_server().await;
+        let channel = channel(ChannelKind::Smtp, url, "user:password");
+        for delivery in [
+            NotificationDelivery {
+                recipient: Some("user@example.com>\r\nRCPT TO:<other@example.com".to_string()),
+                ..delivery()
+            },
+            NotificationDelivery {
+                title: "Alert\r\nBcc: other@example.com".to_string(),
+                ..delivery()
+            },
+        ] {
+            assert!(send(&channel, &delivery).await.is_err());
+        }
+        assert!(!server.is_finished());
+        server.abort();
+    }
+}
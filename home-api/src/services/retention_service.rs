@@ -0,0 +1,32 @@
+use crate::database::{measurement_rollups::MeasurementRollupDatabase, DbPool};
+use std::time::Duration;
+
+const COMPACTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Periodically rolls up the stored measurements and applies the retention policy.
+pub fn start_measurement_compaction(pool: DbPool) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = compact(&pool).await {
+                tracing::error!("Failed to compact measurements: {}", e);
+            }
+            tokio::time::sleep(COMPACTION_INTERVAL).await;
+        }
+    });
+}
+
+async fn compact(pool: &DbPool) -> Result<(), anyhow::Error> {
+    let conn = pool.get().await?;
+    let policy = conn.get_retention_policy().await?;
+    let result = conn
+        .compact_measurements(policy, chrono::Utc::now().timestamp() as u64)
+        .await?;
+    tracing::info!(
+        "Compacted measurements: {} hourly and {} daily rollups updated, {} rows deleted",
+        result.hourly,
+        result.daily,
+        result.deleted
+    );
+
+    Ok(())
+}
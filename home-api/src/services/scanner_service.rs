@@ -1,13 +1,26 @@
 use crate::database::DbPool;
+use pnet::ipnetwork::{IpNetwork, NetworkSize};
 use serde_derive::{Deserialize, Serialize};
-use std::{future::Future, sync::Arc};
-use tokio::{sync::Mutex, task::JoinHandle};
+use std::{
+    collections::HashSet, fmt::Display, future::Future, net::IpAddr, str::FromStr, sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    sync::{broadcast, Mutex, Semaphore},
+    task::JoinHandle,
+};
+
+const MAX_SCAN_HOSTS: u128 = 4096;
+const EVENTS_CAPACITY: usize = 64;
 
 pub trait Scannable: Send + Sync + Clone + Default + std::fmt::Debug + 'static {
     type Error: Send + Sync;
+    /// Requests `scan` makes to a host at most, each given up after the scan timeout.
+    const ATTEMPTS: usize;
+    /// Looks for a device at `host`, each request to it given up after `timeout`.
     fn scan(
-        client: &reqwest::Client,
         host: &str,
+        timeout: Duration,
     ) -> impl Future<
         Output = Result<Result<Self, Self::Error>, Box<dyn std::error::Error + Send + Sync>>,
     > + Send;
@@ -15,6 +28,198 @@ pub trait Scannable: Send + Sync + Clone + Default + std::fmt::Debug + 'static {
         &mut self,
         pool: &DbPool,
     ) -> impl Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send;
+    /// Persists a finished scan, `hosts` being every address that was scanned.
+    fn save_result(
+        result: &ScannerResult<Self>,
+        hosts: &[String],
+        pool: &DbPool,
+    ) -> impl Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send;
+    fn load_result(
+        pool: &DbPool,
+    ) -> impl Future<
+        Output = Result<Option<ScannerResult<Self>>, Box<dyn std::error::Error + Send + Sync>>,
+    > + Send;
+}
+
+#[derive(Clone, Debug)]
+pub struct ScanInterface {
+    pub name: String,
+    pub index: u32,
+    pub networks: Vec<IpNetwork>,
+}
+
+impl ScanInterface {
+    pub fn networks_text(&self) -> String {
+        self.networks
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+pub fn interfaces() -> Vec<ScanInterface> {
+    pnet::datalink::interfaces()
+        .into_iter()
+        .filter(|i| i.is_up() && !i.is_loopback() && !i.ips.is_empty())
+        .map(|i| ScanInterface {
+            name: i.name,
+            index: i.index,
+            networks: i.ips,
+        })
+        .collect()
+}
+
+/// A single CIDR range or address to scan, optionally scoped to an interface
+/// (`fe80::1%eth0`), which is required for IPv6 link-local addresses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanTarget {
+    pub network: IpNetwork,
+    pub scope: Option<String>,
+}
+
+impl FromStr for ScanTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (network, scope) = match s.split_once('%') {
+            Some((network, scope)) => (network, Some(scope.to_string())),
+            None => (s, None),
+        };
+        let network = network
+            .parse::<IpNetwork>()
+            .map_err(|e| format!("Invalid scan target `{}`: {}", s, e))?;
+        Ok(Self { network, scope })
+    }
+}
+
+impl Display for ScanTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.network.prefix(), self.network.is_ipv4()) {
+            (32, true) | (128, false) => write!(f, "{}", self.network.ip())?,
+            _ => write!(f, "{}", self.network)?,
+        }
+        if let Some(scope) = &self.scope {
+            write!(f, "%{}", scope)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ScanOptions {
+    pub targets: Vec<ScanTarget>,
+    pub interface: Option<String>,
+    pub concurrency: usize,
+    pub timeout: Duration,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            targets: vec![],
+            interface: None,
+            concurrency: 32,
+            timeout: Duration::from_millis(1000),
+        }
+    }
+}
+
+impl ScanOptions {
+    pub fn targets_text(&self) -> String {
+        self.targets
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Expands the targets into the list of hosts to scan.
+    /// Without explicit targets the IPv4 networks of the selected (or first) interface are used.
+    pub fn hosts(&self) -> Result<(String, Vec<String>), String> {
+        let interfaces = interfaces();
+        let interface = match &self.interface {
+            Some(name) => Some(
+                interfaces
+                    .iter()
+                    .find(|i| &i.name == name)
+                    .ok_or(format!("Interface `{}` not found", name))?,
+            ),
+            None => None,
+        };
+        let targets = match self.targets.is_empty() {
+            true => interface
+                .or_else(|| {
+                    interfaces
+                        .iter()
+                        .find(|i| i.networks.iter().any(|n| n.is_ipv4()))
+                })
+                .ok_or("No network to scan!")?
+                .networks
+                .iter()
+                .filter(|n| n.is_ipv4())
+                .map(|n| ScanTarget {
+                    network: IpNetwork::new(n.network(), n.prefix()).unwrap(),
+                    scope: None,
+                })
+                .collect(),
+            false => self.targets.clone(),
+        };
+
+        let mut seen = HashSet::new();
+        let mut hosts = vec![];
+        for target in targets.iter() {
+            let size = match target.network.size() {
+                NetworkSize::V4(size) => size as u128,
+                NetworkSize::V6(size) => size,
+            };
+            if hosts.len() as u128 + size > MAX_SCAN_HOSTS {
+                return Err(format!(
+                    "Scan targets exceed the limit of {} hosts",
+                    MAX_SCAN_HOSTS
+                ));
+            }
+            let scope = match &target.scope {
+                Some(scope) => Some(
+                    interfaces
+                        .iter()
+                        .find(|i| &i.name == scope || i.index.to_string() == *scope)
+                        .ok_or(format!("Interface `{}` not found", scope))?
+                        .index,
+                ),
+                None => interface.map(|i| i.index),
+            };
+            let network = target.network;
+            let skip_edges = matches!(network, IpNetwork::V4(n) if n.prefix() < 31);
+            for ip in network.iter() {
+                if skip_edges && (ip == network.network() || ip == network.broadcast()) {
+                    continue;
+                }
+                let host = match ip {
+                    IpAddr::V6(ip) if ip.is_unicast_link_local() => match scope {
+                        Some(scope) => format!("{}%{}", ip, scope),
+                        None => {
+                            return Err(format!(
+                                "Link-local target `{}` requires an interface",
+                                target
+                            ))
+                        }
+                    },
+                    ip => ip.to_string(),
+                };
+                if seen.insert(host.clone()) {
+                    hosts.push(host);
+                }
+            }
+        }
+
+        let description = targets
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        Ok((description, hosts))
+    }
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -48,17 +253,30 @@ impl<T: Scannable> ScannerState<T> {
     }
 }
 
+/// Progress updates published while a scan runs.
+#[derive(Clone, Debug)]
+pub enum ScanEvent<T: Scannable> {
+    Found(T),
+    Progress(ScanProgress<T>),
+    Finished(ScannerState<T>),
+}
+
 #[derive(Clone, Debug)]
 pub struct ScannerResult<T> {
     pub scanned: Vec<T>,
+    pub target: String,
     pub created: chrono::DateTime<chrono::Utc>,
     pub duration: chrono::Duration,
 }
 
 pub struct ScannerService<T: Scannable> {
     pub last_result: Option<ScannerResult<T>>,
+    pub options: ScanOptions,
+    pub interval: Option<Duration>,
     handle: Option<JoinHandle<Result<ScannerResult<T>, String>>>,
+    schedule_handle: Option<JoinHandle<()>>,
     progress: Arc<Mutex<ScanProgress<T>>>,
+    events: broadcast::Sender<ScanEvent<T>>,
     runtime: Arc<tokio::runtime::Runtime>,
 }
 
@@ -66,8 +284,12 @@ impl<T: Scannable> ScannerService<T> {
     pub fn new(runtime: Arc<tokio::runtime::Runtime>) -> Self {
         Self {
             last_result: Default::default(),
+            options: Default::default(),
+            interval: Default::default(),
             handle: Default::default(),
+            schedule_handle: Default::default(),
             progress: Default::default(),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
             runtime,
         }
     }
@@ -75,38 +297,47 @@ impl<T: Scannable> ScannerService<T> {
     async fn scan_inner(
         progress: Arc<Mutex<ScanProgress<T>>>,
         pool: DbPool,
+        options: ScanOptions,
+        events: broadcast::Sender<ScanEvent<T>>,
     ) -> Result<ScannerResult<T>, String> {
         let started = chrono::Utc::now();
-        let Some(target) = pnet::datalink::interfaces().into_iter().find_map(|n| {
-            n.ips
-                .into_iter()
-                .map(|ip| ip.ip())
-                .find(|ip| ip.is_ipv4() && !ip.is_loopback() && !ip.is_unspecified())
-                .map(|ip| ip.to_string())
-        }) else {
-            return Err("No network to scan!".to_string());
-        };
-        let target = target[..target.rfind('.').unwrap() + 1].to_string();
+        let (target, hosts) = options.hosts()?;
         {
             let mut scan_progress = progress.lock().await;
-            scan_progress.target = target.to_string();
-            scan_progress.total = 256;
+            scan_progress.target = target.clone();
+            scan_progress.total = hosts.len() as u32;
         }
+        let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
         let mut handles = tokio::task::JoinSet::new();
-        for i in 0..=255 {
+        for host in hosts.clone() {
+            let permit = semaphore
+                .clone()
+                .acquire_owned()
+                .await
+                .map_err(|e| e.to_string())?;
             let progress = progress.clone();
-            let target = target.clone();
             let pool = pool.clone();
-            let task = tokio::spawn(async move {
-                let host = format!("{}{}", target, i);
-                let client = reqwest::Client::new();
-                if let Ok(Ok(mut scanned)) = T::scan(&client, &host).await {
+            let events = events.clone();
+            let timeout = options.timeout;
+            handles.spawn(async move {
+                let _permit = permit;
+                if let Ok(Ok(Ok(mut scanned))) =
+                    tokio::time::timeout(timeout * T::ATTEMPTS as u32, T::scan(&host, timeout))
+                        .await
+                {
                     scanned.check(&pool).await.ok();
-                    progress.lock().await.scanned.push(scanned);
+                    progress.lock().await.scanned.push(scanned.clone());
+                    events.send(ScanEvent::Found(scanned)).ok();
                 }
-                progress.lock().await.progress += 1;
+                let mut progress = progress.lock().await;
+                progress.progress += 1;
+                events
+                    .send(ScanEvent::Progress(ScanProgress {
+                        scanned: vec![],
+                        ..progress.clone()
+                    }))
+                    .ok();
             });
-            handles.spawn(task);
         }
 
         while handles.join_next().await.is_some() {}
@@ -114,18 +345,67 @@ impl<T: Scannable> ScannerService<T> {
         let created = chrono::Utc::now();
         let duration = created - started;
 
-        Ok(ScannerResult {
+        let result = ScannerResult {
             scanned: progress.lock().await.scanned.clone(),
+            target,
             created,
             duration,
-        })
+        };
+        if let Err(e) = T::save_result(&result, &hosts, &pool).await {
+            tracing::error!("Failed to save scan result: {}", e);
+        }
+
+        Ok(result)
     }
 
-    pub async fn init(&mut self, pool: DbPool) -> ScannerState<T> {
-        self.progress = Default::default();
-        let progress = self.progress.clone();
+    pub async fn load(&mut self, pool: &DbPool) {
+        match T::load_result(pool).await {
+            Ok(result) => self.last_result = result,
+            Err(e) => tracing::error!("Failed to load last scan result: {}", e),
+        }
+    }
+
+    /// (Re)starts background scans running every `interval` with the last used options,
+    /// `None` disables them.
+    pub async fn schedule(scanner: Arc<Mutex<Self>>, pool: DbPool, interval: Option<Duration>) {
+        let mut service = scanner.lock().await;
+        if let Some(handle) = service.schedule_handle.take() {
+            handle.abort();
+        }
+        service.interval = interval;
+        let Some(interval) = interval else {
+            return;
+        };
+        let runtime = service.runtime.clone();
+        let task_scanner = scanner.clone();
+        service.schedule_handle = Some(runtime.spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let mut scanner = task_scanner.lock().await;
+                let options = scanner.options.clone();
+                tracing::info!("Starting scheduled scan");
+                scanner.init(pool.clone(), options).await;
+            }
+        }));
+    }
+
+    pub async fn init(&mut self, pool: DbPool, options: ScanOptions) -> ScannerState<T> {
+        // collect a scan that finished in the background
+        self.state().await;
         if self.handle.is_none() {
-            self.handle = Some(self.runtime.spawn(Self::scan_inner(progress.clone(), pool)));
+            self.progress = Default::default();
+            self.options = options.clone();
+            let progress = self.progress.clone();
+            let events = self.events.clone();
+            self.handle = Some(self.runtime.spawn(async move {
+                let result = Self::scan_inner(progress, pool, options, events.clone()).await;
+                let state = match &result {
+                    Ok(result) => ScannerState::Idle(Some(result.clone())),
+                    Err(e) => ScannerState::Error(e.clone()),
+                };
+                events.send(ScanEvent::Finished(state)).ok();
+                result
+            }));
         }
 
         self.state().await
@@ -135,9 +415,18 @@ impl<T: Scannable> ScannerService<T> {
         if let Some(handle) = &mut self.handle {
             handle.abort();
             self.handle = None;
+            self.events
+                .send(ScanEvent::Finished(ScannerState::Idle(
+                    self.last_result.clone(),
+                )))
+                .ok();
         }
     }
 
+    pub fn subscribe(&self) -> broadcast::Receiver<ScanEvent<T>> {
+        self.events.subscribe()
+    }
+
     pub async fn state(&mut self) -> ScannerState<T> {
         let Some(handle) = &mut self.handle else {
             return ScannerState::Idle(self.last_result.clone());
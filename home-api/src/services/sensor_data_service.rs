@@ -1,35 +1,137 @@
-use super::sensor_service::TempSensorService;
+use super::{
+    alert_service, notification_service,
+    sensor_service::{sensor_client, MeasurementSensorService, MotionSensorService, UnpairedError},
+    validation_service,
+};
 use crate::{
     database::{
-        data_schedule::DataScheduleDatabase, sensors::SensorDatabase, temp_data::TempDataDatabase,
-        DbPool,
+        collection_runs::CollectionRunDatabase, data_schedule::DataScheduleDatabase,
+        measurements::MeasurementDatabase, motion_data::MotionDataDatabase,
+        sensor_status::SensorStatusDatabase, sensors::SensorDatabase, DbPool,
+    },
+    features::{self, FeatureInfo, FeatureKind},
+    models::db::{
+        CollectionRun, DataScheduleEntry, MeasurementEntry, MeasurementGap, MotionDataEntry,
+        Notification, NotificationTopics, ScheduleTarget, SensorEntity, SensorFeatures,
+        SensorState, SensorStatus,
     },
-    models::db::{DataScheduleEntry, SensorFeatures, TempDataEntry},
 };
-use chrono::Utc;
-use std::{sync::Arc, time::SystemTime};
+use chrono::{DateTime, Local, Utc};
+use futures_util::future::join_all;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio::{
+    sync::broadcast,
     task::JoinHandle,
     time::{self, Instant},
 };
 
+const EVENTS_CAPACITY: usize = 64;
+const BACKOFF_MIN: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(5 * 60);
+/// Gaps a single collection tries to fill, the rest wait for the next one.
+const MAX_BACKFILLS: usize = 5;
+/// Time a single sensor gets to deliver its data before its collection is given up.
+const SENSOR_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Published whenever the collector sees a sensor go online or offline.
+#[derive(Clone, Debug)]
+pub struct SensorStateChange {
+    pub host: String,
+    pub previous: SensorState,
+    pub current: SensorState,
+    pub status: SensorStatus,
+}
+
+/// Supervision state of the collection task of a schedule entry.
+#[derive(Clone, Debug, Default)]
+pub struct TaskHealth {
+    pub schedule_id: i64,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    pub last_failure: Option<i64>,
+    /// Set while the task waits to be restarted.
+    pub retry_at: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CollectorHealth {
+    pub running: bool,
+    pub tasks: Vec<TaskHealth>,
+}
+
+impl CollectorHealth {
+    pub fn healthy(&self) -> bool {
+        self.running && self.tasks.iter().all(|t| t.retry_at.is_none())
+    }
+}
+
+/// Aborts the task once dropped, so restarting the service stops the supervised tasks too.
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
 pub struct SensorDataService {
     handle: Option<JoinHandle<()>>,
+    health: Arc<std::sync::Mutex<HashMap<i64, TaskHealth>>>,
     runtime: Arc<tokio::runtime::Runtime>,
     current_schedule: Option<Vec<DataScheduleEntry>>,
     pool: DbPool,
+    events: broadcast::Sender<SensorStateChange>,
 }
 
 impl SensorDataService {
     pub fn new(runtime: Arc<tokio::runtime::Runtime>, pool: DbPool) -> Self {
         Self {
             handle: Default::default(),
+            health: Default::default(),
             runtime,
             current_schedule: Default::default(),
             pool,
+            events: broadcast::channel(EVENTS_CAPACITY).0,
         }
     }
 
+    pub fn subscribe(&self) -> broadcast::Receiver<SensorStateChange> {
+        self.events.subscribe()
+    }
+
+    /// Logs every sensor state change.
+    pub fn log_state_changes(&self) {
+        let mut events = self.subscribe();
+        self.runtime.spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(change) => match (change.current, &change.status.last_error) {
+                        (SensorState::Offline, Some(error)) => tracing::warn!(
+                            "Sensor {} is now {} (was {}): {}",
+                            change.host,
+                            change.current,
+                            change.previous,
+                            error
+                        ),
+                        _ => tracing::info!(
+                            "Sensor {} is now {} (was {})",
+                            change.host,
+                            change.current,
+                            change.previous
+                        ),
+                    },
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Whether the collection tasks are spawned and still running.
+    pub fn is_running(&self) -> bool {
+        self.handle.as_ref().is_some_and(|h| !h.is_finished())
+    }
+
     pub async fn init(&mut self) -> Result<(), anyhow::Error> {
         let schedule = self.pool.get().await?.get_schedule().await?;
         self.current_schedule = Some(schedule);
@@ -47,108 +149,497 @@ impl SensorDataService {
         Ok(())
     }
 
+    /// Supervision state of the collection tasks.
+    pub fn health(&self) -> CollectorHealth {
+        let mut tasks = self
+            .health
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        tasks.sort_by_key(|t| t.schedule_id);
+        CollectorHealth {
+            running: self.is_running(),
+            tasks,
+        }
+    }
+
     fn start(&mut self) {
         let Some(schedule) = self.current_schedule.as_ref() else {
             return;
         };
         let schedule = schedule.clone();
         let pool = self.pool.clone();
+        let events = self.events.clone();
+        let health = self.health.clone();
+        *health.lock().unwrap() = schedule
+            .iter()
+            .map(|e| {
+                (
+                    e.id,
+                    TaskHealth {
+                        schedule_id: e.id,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
         let handle = self.runtime.spawn(async move {
-            let schedule = schedule.clone();
-            let mut handles = tokio::task::JoinSet::<Result<(), anyhow::Error>>::new();
-            for entry in schedule.into_iter() {
-                let pool = pool.clone();
-                handles.spawn(async move {
-                    let pool = pool.clone();
-                    let mut last_dur = time::Duration::from_millis(
-                        SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis() as u64
-                            % entry.interval_ms,
-                    );
-                    loop {
-                        time::sleep(
-                            time::Duration::from_millis(entry.interval_ms)
-                                .checked_sub(last_dur)
-                                .unwrap_or_else(|| {
-                                    tracing::warn!(
-                                        "Sensor data service task took too long to run: {:?}",
-                                        last_dur
-                                    );
-                                    time::Duration::default()
-                                }),
-                        )
-                        .await;
-                        let start = Instant::now();
-                        Self::collect_data(&entry, &pool).await?;
-
-                        last_dur = start.elapsed();
-                    }
-                });
+            let mut handles = tokio::task::JoinSet::<()>::new();
+            for entry in schedule.clone().into_iter() {
+                handles.spawn(Self::supervise(
+                    entry,
+                    schedule.clone(),
+                    pool.clone(),
+                    events.clone(),
+                    health.clone(),
+                ));
             }
 
-            while let Some(e) = handles.join_next().await {
-                // nothing should finish
-                // restart service
-                tracing::error!("Sensor data service task finished unexpectedly: {:?}", e);
+            while let Some(result) = handles.join_next().await {
+                if let Err(e) = result {
+                    tracing::error!("Sensor data service supervisor failed: {:?}", e);
+                }
             }
         });
 
         self.handle = Some(handle);
     }
 
-    async fn collect_data(entry: &DataScheduleEntry, pool: &DbPool) -> Result<(), anyhow::Error> {
-        if entry.features.contains(SensorFeatures::TEMPERATURE) {
+    /// Keeps the task of `entry` running, restarting it with an exponential back-off when it fails or panics.
+    async fn supervise(
+        entry: DataScheduleEntry,
+        schedule: Vec<DataScheduleEntry>,
+        pool: DbPool,
+        events: broadcast::Sender<SensorStateChange>,
+        health: Arc<std::sync::Mutex<HashMap<i64, TaskHealth>>>,
+    ) {
+        let mut backoff = BACKOFF_MIN;
+        loop {
+            let started = Instant::now();
+            let mut task = AbortOnDrop(tokio::spawn(Self::run_entry(
+                entry.clone(),
+                schedule.clone(),
+                pool.clone(),
+                events.clone(),
+            )));
+            let error = match (&mut task.0).await {
+                Ok(Ok(())) => return,
+                Ok(Err(e)) => e.to_string(),
+                Err(e) => format!("Task panicked: {}", e),
+            };
+            // a task that ran fine for a while starts over with a short back-off
+            if started.elapsed() > BACKOFF_MAX {
+                backoff = BACKOFF_MIN;
+            }
+            tracing::warn!(
+                "Sensor data service task {} failed, restarting in {:?}: {}",
+                entry.id,
+                backoff,
+                error
+            );
+            // only the first failure of a streak is worth a notification
+            if backoff == BACKOFF_MIN {
+                notification_service::notify_or_log(
+                    &pool,
+                    Notification::new(
+                        NotificationTopics::COLLECTOR_FAILED,
+                        format!("Collection policy #{} failed", entry.id),
+                        error.clone(),
+                    ),
+                )
+                .await;
+            }
+            let now = Utc::now().timestamp();
+            if let Some(task) = health.lock().unwrap().get_mut(&entry.id) {
+                task.restarts += 1;
+                task.last_error = Some(error);
+                task.last_failure = Some(now);
+                task.retry_at = Some(now + backoff.as_secs() as i64);
+            }
+            time::sleep(backoff).await;
+            if let Some(task) = health.lock().unwrap().get_mut(&entry.id) {
+                task.retry_at = None;
+            }
+            backoff = (backoff * 2).min(BACKOFF_MAX);
+        }
+    }
+
+    /// Collects the data of `entry` on its schedule, returns only when the entry never runs or on error.
+    async fn run_entry(
+        entry: DataScheduleEntry,
+        schedule: Vec<DataScheduleEntry>,
+        pool: DbPool,
+        events: broadcast::Sender<SensorStateChange>,
+    ) -> Result<(), anyhow::Error> {
+        loop {
+            let now = Local::now();
+            let Some(next) = entry.next_run(now) else {
+                tracing::warn!("Schedule entry {} never runs", entry.id);
+                return Ok(());
+            };
+            time::sleep((next - now).to_std().unwrap_or_default()).await;
+            let run = Self::collect_data(&entry, &schedule, &next, &pool, &events).await?;
+
+            if entry
+                .next_run(next)
+                .is_some_and(|following| following < Local::now())
+            {
+                tracing::warn!(
+                    "Sensor data service task took too long to run: {} ms",
+                    run.duration_ms
+                );
+            }
+        }
+    }
+
+    /// Records the outcome of a request to `host`, publishing a [`SensorStateChange`] when the state changed.
+    async fn update_status(
+        host: &str,
+        error: Option<String>,
+        pool: &DbPool,
+        events: &broadcast::Sender<SensorStateChange>,
+    ) -> Result<(), anyhow::Error> {
+        let conn = pool.get().await?;
+        let previous = conn
+            .get_sensor_status(host)
+            .await?
+            .map_or(SensorState::Unknown, |s| s.state());
+        let status = match error {
+            Some(error) => conn.record_sensor_failure(host, &error).await?,
+            None => {
+                conn.record_sensor_success(host, Utc::now().timestamp())
+                    .await?
+            }
+        };
+        let current = status.state();
+        if current != previous {
+            events
+                .send(SensorStateChange {
+                    host: host.to_string(),
+                    previous,
+                    current,
+                    status,
+                })
+                .ok();
+        }
+
+        Ok(())
+    }
+
+    /// Collects the data of the sensors `entry` is the effective rule for.
+    async fn collect_data(
+        entry: &DataScheduleEntry,
+        schedule: &[DataScheduleEntry],
+        at: &DateTime<Local>,
+        pool: &DbPool,
+        events: &broadcast::Sender<SensorStateChange>,
+    ) -> Result<CollectionRun, anyhow::Error> {
+        let sensors = pool
+            .get()
+            .await?
+            .get_sensors()
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?
+            .into_iter()
+            .map(|s| {
+                let features = entry.effective_features(schedule, &s, at);
+                (s, features)
+            })
+            .filter(|(_, features)| !features.is_empty())
+            .collect();
+        Self::collect(sensors, Some(entry.id), entry.features, None, pool, events).await
+    }
+
+    /// Collects `features` right away from the sensors of `target`, regardless of the schedule.
+    pub fn collect_now(
+        &self,
+        features: SensorFeatures,
+        target: ScheduleTarget,
+    ) -> impl std::future::Future<Output = Result<CollectionRun, anyhow::Error>> {
+        let pool = self.pool.clone();
+        let events = self.events.clone();
+        async move {
             let sensors = pool
                 .get()
                 .await?
-                .get_sensors_by_features(SensorFeatures::TEMPERATURE)
+                .get_sensors()
                 .await
-                .map_err(|e| anyhow::anyhow!("{}", e))?;
+                .map_err(|e| anyhow::anyhow!("{}", e))?
+                .into_iter()
+                .filter(|s| target.matches(s))
+                .map(|s| {
+                    let features = s.features.intersection(features);
+                    (s, features)
+                })
+                .filter(|(_, features)| !features.is_empty())
+                .collect();
+            let host = match target {
+                ScheduleTarget::Sensor(host) => Some(host),
+                _ => None,
+            };
+            Self::collect(sensors, None, features, host, &pool, &events).await
+        }
+    }
 
-            for sensor in sensors {
-                const SAVE_INTERVAL: i64 = 60 * 15; // 15 minutes
-                const MAX_COUNT: i64 = 150;
-                let last_measurement = pool
-                    .get()
-                    .await?
-                    .get_temp_data(Some(vec![&sensor.host]), Some(1), None, None)
-                    .await?
-                    .first()
-                    .map_or(0, |t| t.timestamp);
-                let count = (Utc::now().timestamp() - last_measurement as i64) / SAVE_INTERVAL + 4;
-                let count = count.min(MAX_COUNT);
-                let client = reqwest::Client::new();
-                let host = &sensor.host;
-                let pair_id = &sensor.pair_id.unwrap();
-                let Ok(measurements) = client
-                    .get_temp(host, pair_id, Some(count as u64), None)
-                    .await
-                else {
-                    continue;
-                };
-                let _count = pool
-                    .get()
-                    .await?
-                    .create_temp_data_batch(
-                        measurements
-                            .into_iter()
-                            .map(|m| TempDataEntry {
-                                host: sensor.host.clone(),
-                                timestamp: m.timestamp,
-                                temperature: m.temperature,
-                                humidity: m.humidity,
-                            })
-                            .collect(),
-                    )
-                    .await?;
+    /// Polls `sensors` for the given features and records the run.
+    async fn collect(
+        sensors: Vec<(SensorEntity, SensorFeatures)>,
+        schedule_id: Option<i64>,
+        features: SensorFeatures,
+        host: Option<String>,
+        pool: &DbPool,
+        events: &broadcast::Sender<SensorStateChange>,
+    ) -> Result<CollectionRun, anyhow::Error> {
+        let start = Instant::now();
+        let mut run = CollectionRun {
+            schedule_id,
+            features,
+            host,
+            started: Utc::now().timestamp(),
+            sensors: sensors.len() as u32,
+            ..Default::default()
+        };
+        // sensors are polled side by side so an unreachable one cannot hold up the others
+        let results = join_all(sensors.iter().map(|(sensor, features)| {
+            time::timeout(
+                SENSOR_TIMEOUT,
+                Self::collect_sensor(sensor, *features, pool, events),
+            )
+        }))
+        .await;
+        for ((sensor, _), result) in sensors.iter().zip(results) {
+            match result {
+                Ok(Ok(rows)) => run.rows += rows as u32,
+                Ok(Err(e)) => run.errors.push(format!("{}: {}", sensor.host, e)),
+                Err(_) => run.errors.push(format!(
+                    "{}: No response within {}s",
+                    sensor.host,
+                    SENSOR_TIMEOUT.as_secs()
+                )),
+            }
+        }
+        run.duration_ms = start.elapsed().as_millis() as i64;
+        pool.get().await?.create_collection_run(run.clone()).await?;
+        match alert_service::evaluate_alerts(pool).await {
+            Ok(changes) => {
+                for change in changes {
+                    notification_service::notify_or_log(pool, change.into()).await;
+                }
+            }
+            Err(e) => tracing::error!("Failed to evaluate alert rules: {}", e),
+        }
+
+        Ok(run)
+    }
+
+    /// Collects `features` of a single sensor, returning the number of stored rows.
+    async fn collect_sensor(
+        sensor: &SensorEntity,
+        features: SensorFeatures,
+        pool: &DbPool,
+        events: &broadcast::Sender<SensorStateChange>,
+    ) -> Result<usize, anyhow::Error> {
+        let Some(pair_id) = sensor.pair_id.as_deref() else {
+            anyhow::bail!("Sensor is not paired");
+        };
+        let mut rows = 0;
+        for info in features::FEATURES
+            .iter()
+            .filter(|info| features.contains(info.feature))
+        {
+            rows += match info.kind {
+                FeatureKind::Measurement => {
+                    Self::collect_measurements(&sensor.host, pair_id, info, pool, events).await?
+                }
+                FeatureKind::Events => {
+                    Self::collect_motion(&sensor.host, pair_id, info, pool, events).await?
+                }
+            };
+        }
+
+        Ok(rows)
+    }
+
+    /// Collects the readings of the measurement feature `info` since the last stored one.
+    async fn collect_measurements(
+        host: &str,
+        pair_id: &str,
+        info: &FeatureInfo,
+        pool: &DbPool,
+        events: &broadcast::Sender<SensorStateChange>,
+    ) -> Result<usize, anyhow::Error> {
+        let last_measurement = pool
+            .get()
+            .await?
+            .get_last_measurement(host, info.feature)
+            .await?
+            .unwrap_or(0);
+        // without an interval the sensor is asked for everything it holds
+        let count = info.interval.map(|interval| {
+            (Utc::now().timestamp() - last_measurement as i64) / interval as i64 + 4
+        });
+        let count = match (count, info.buffer_size) {
+            (Some(count), Some(size)) => Some(count.min(size as i64) as u64),
+            (Some(count), None) => Some(count as u64),
+            (None, size) => size,
+        };
+        let result = sensor_client(host)
+            .get_readings(host, pair_id, info, count, None)
+            .await;
+        Self::update_status(
+            host,
+            result.as_ref().err().map(|e| e.to_string()),
+            pool,
+            events,
+        )
+        .await?;
+        let measurements = match result {
+            Ok(measurements) => measurements,
+            Err(e) => {
+                if e.is::<UnpairedError>() {
+                    tracing::warn!("Sensor {} lost its pair key: {}", host, e);
+                }
+                return Err(e);
+            }
+        };
+        let mut entries = measurements
+            .into_iter()
+            .flat_map(|m| m.into_entries(host, info))
+            .collect::<Vec<_>>();
+        Self::flag_entries(host, pool, &mut entries).await?;
+        let rows = pool.get().await?.create_measurement_batch(entries).await?;
+        // a failed backfill is retried on the next collection
+        match Self::backfill(host, pair_id, info, pool).await {
+            Ok(backfilled) => Ok(rows + backfilled),
+            Err(e) => {
+                tracing::warn!("Failed to backfill data of sensor {}: {}", host, e);
+                Ok(rows)
             }
         }
-        if entry.features.contains(SensorFeatures::MOTION) {
-            // do motion
+    }
+
+    /// Requests the readings missing between stored ones from the sensor buffer,
+    /// marking the gaps the sensor no longer holds as unrecoverable.
+    async fn backfill(
+        host: &str,
+        pair_id: &str,
+        info: &FeatureInfo,
+        pool: &DbPool,
+    ) -> Result<usize, anyhow::Error> {
+        // gaps only exist between readings taken at an interval
+        let Some(interval) = info.interval else {
+            return Ok(0);
+        };
+        let gaps = pool
+            .get()
+            .await?
+            .get_measurement_gaps(info, Some(host))
+            .await?;
+        let mut rows = 0;
+        for gap in gaps
+            .into_iter()
+            .filter(|gap| !gap.unrecoverable)
+            .take(MAX_BACKFILLS)
+        {
+            let count = gap.missing(interval) + 1;
+            let count = info.buffer_size.map_or(count, |size| count.min(size));
+            let mut entries = sensor_client(host)
+                .get_readings(host, pair_id, info, Some(count), Some(gap.end - 1))
+                .await?
+                .into_iter()
+                .filter(|m| m.timestamp > gap.start && m.timestamp < gap.end)
+                .flat_map(|m| m.into_entries(host, info))
+                .collect::<Vec<_>>();
+            Self::flag_entries(host, pool, &mut entries).await?;
+            let conn = pool.get().await?;
+            if entries.is_empty() {
+                tracing::info!(
+                    "Sensor {} holds no readings between {} and {}",
+                    host,
+                    gap.start,
+                    gap.end
+                );
+                conn.mark_gap_unrecoverable(&gap).await?;
+                continue;
+            }
+            // whatever the sensor didn't return is gone, so it isn't requested again
+            let mut bounds = entries.iter().map(|e| e.timestamp).collect::<Vec<_>>();
+            bounds.extend([gap.start, gap.end]);
+            bounds.sort_unstable();
+            bounds.dedup();
+            rows += conn.create_measurement_batch(entries).await?;
+            let threshold = info.gap_threshold().unwrap_or(interval);
+            for remainder in bounds
+                .windows(2)
+                .filter(|bounds| bounds[1] - bounds[0] > threshold)
+            {
+                conn.mark_gap_unrecoverable(&MeasurementGap {
+                    start: remainder[0],
+                    end: remainder[1],
+                    ..gap.clone()
+                })
+                .await?;
+            }
         }
+        Ok(rows)
+    }
 
+    /// Flags the bad readings among `entries` collected from `host`, they are stored nonetheless.
+    async fn flag_entries(
+        host: &str,
+        pool: &DbPool,
+        entries: &mut [MeasurementEntry],
+    ) -> Result<(), anyhow::Error> {
+        let flagged = validation_service::flag_entries(pool, entries).await?;
+        if flagged > 0 {
+            tracing::warn!(
+                "Flagged {} of {} readings of sensor {}",
+                flagged,
+                entries.len(),
+                host
+            );
+        }
         Ok(())
     }
+
+    async fn collect_motion(
+        host: &str,
+        pair_id: &str,
+        info: &FeatureInfo,
+        pool: &DbPool,
+        events: &broadcast::Sender<SensorStateChange>,
+    ) -> Result<usize, anyhow::Error> {
+        // refetch ongoing events so their end gets stored
+        let after = pool
+            .get()
+            .await?
+            .get_motion_sync_point(host)
+            .await?
+            .map(|start| start - 1);
+        let result = sensor_client(host)
+            .get_motion(host, pair_id, info, after, None)
+            .await;
+        Self::update_status(
+            host,
+            result.as_ref().err().map(|e| e.to_string()),
+            pool,
+            events,
+        )
+        .await?;
+        pool.get()
+            .await?
+            .create_motion_data_batch(
+                result?
+                    .into_iter()
+                    .map(|e| MotionDataEntry {
+                        host: host.to_string(),
+                        start: e.start,
+                        end: e.end,
+                    })
+                    .collect(),
+            )
+            .await
+    }
 }
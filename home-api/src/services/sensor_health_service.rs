@@ -0,0 +1,64 @@
+use super::sensor_service::{sensor_client, SensorService};
+use crate::{
+    database::{sensor_health::SensorHealthDatabase, sensors::SensorDatabase, DbPool},
+    models::db::{SensorEntity, SensorHealthEntry},
+};
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Periodically stores the diagnostics of every paired sensor.
+pub fn start_sensor_health_polling(pool: DbPool) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = poll_health(&pool).await {
+                tracing::error!("Failed to poll sensor health: {}", e);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+async fn poll_health(pool: &DbPool) -> Result<(), anyhow::Error> {
+    let sensors = pool
+        .get()
+        .await?
+        .get_sensors()
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    for sensor in sensors {
+        if let Err(e) = poll_sensor(&sensor, pool).await {
+            tracing::warn!("Failed to get health of sensor {}: {}", sensor.host, e);
+        }
+    }
+
+    Ok(())
+}
+
+async fn poll_sensor(sensor: &SensorEntity, pool: &DbPool) -> Result<(), anyhow::Error> {
+    let Some(pair_id) = &sensor.pair_id else {
+        return Ok(());
+    };
+    let host = &sensor.host;
+    let response = sensor_client(host)
+        .get_sensor_full(host, pair_id)
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    let conn = pool.get().await?;
+    let previous = conn.get_latest_health(host).await?;
+    let mut entry =
+        SensorHealthEntry::from_response(host, chrono::Utc::now().timestamp(), response);
+    // the uptime only goes down when the sensor restarted
+    entry.rebooted = previous.is_some_and(|p| entry.uptime < p.uptime);
+    if entry.rebooted {
+        tracing::info!("Sensor {} rebooted", host);
+    }
+    if entry.pair_store_nearly_full() {
+        tracing::warn!(
+            "Pair store of sensor {} is {:.0}% full",
+            host,
+            entry.pair_usage() * 100.0
+        );
+    }
+    conn.create_health_entry(entry).await
+}
@@ -1,42 +1,146 @@
 use super::http_client::HttpRequest;
+use crate::features::FeatureInfo;
 use crate::models::{
     db::{SensorEntity, SensorFeatures},
+    host_id,
     json::{
-        ErrorResponse, Measurement, MeasurementsResponse, PairResponse, SensorDto, SensorFormData,
-        SensorResponse,
+        ErrorResponse, Measurement, MeasurementsResponse, MotionEvent, MotionEventsResponse,
+        PairResponse, SensorDto, SensorFormData, SensorFullResponse, SensorResponse,
     },
 };
 use anyhow::anyhow;
 use serde::Serialize;
-use std::{error::Error, time::Duration};
+use std::{
+    error::Error,
+    fmt::Display,
+    net::{Ipv6Addr, SocketAddr, SocketAddrV6},
+    time::Duration,
+};
 
 const SENSOR_PORT: u16 = 42069;
 const PAIR_HEADER_NAME: &str = "X-Pair-Id";
+/// Time the sensor is given to answer a request for its public info outside of scans.
+pub const INFO_TIMEOUT: Duration = Duration::from_millis(200);
+/// Requests made for the public info of a sensor before it is given up.
+pub const INFO_ATTEMPTS: usize = 3;
+/// Sent by the sensor for requests with a pair key it does not know.
+const UNPAIRED_ERROR_MESSAGE: &str =
+    "To connect use /pair endpoint and pairing button on the device.";
+
+/// The sensor no longer accepts the stored pair key, e.g. after its key store was wiped.
+#[derive(Debug)]
+pub struct UnpairedError;
+
+impl Display for UnpairedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Sensor does not recognize its pair key, press the pairing button on the device and re-pair it"
+        )
+    }
+}
+
+impl Error for UnpairedError {}
+
+fn sensor_error(response: ErrorResponse) -> Box<dyn Error + Send + Sync> {
+    match response.error == UNPAIRED_ERROR_MESSAGE {
+        true => UnpairedError.into(),
+        false => response.error.into(),
+    }
+}
+
+/// Urls can't carry the zone of IPv6 link-local hosts (`fe80::1%3`),
+/// so those are addressed by a placeholder name resolved in [`sensor_client`].
+fn scoped_addr(host: &str) -> Option<(String, SocketAddr)> {
+    let (ip, scope) = host.split_once('%')?;
+    let ip = ip.parse::<Ipv6Addr>().ok()?;
+    let scope = scope.parse::<u32>().ok()?;
+    Some((
+        format!("{}.link-local", host_id(host)),
+        SocketAddrV6::new(ip, SENSOR_PORT, 0, scope).into(),
+    ))
+}
+
+fn host_uri(host: &str) -> String {
+    if let Some((name, _)) = scoped_addr(host) {
+        return format!("http://{}:{}/", name, SENSOR_PORT);
+    }
+    match host.parse::<Ipv6Addr>() {
+        Ok(_) => format!("http://[{}]:{}/", host, SENSOR_PORT),
+        Err(_) => format!("http://{}:{}/", host, SENSOR_PORT),
+    }
+}
+
+/// Creates a client able to reach the sensor at `host`.
+pub fn sensor_client(host: &str) -> reqwest::Client {
+    match scoped_addr(host) {
+        Some((name, addr)) => reqwest::Client::builder()
+            .resolve(&name, addr)
+            .build()
+            .unwrap_or_default(),
+        None => reqwest::Client::new(),
+    }
+}
 
 pub trait SensorService {
+    /// Public info of the sensor, each attempt given up after `timeout`.
     async fn get_sensor(
         &self,
         host: &str,
+        timeout: Duration,
     ) -> Result<Result<SensorEntity, String>, Box<dyn Error + Send + Sync>>;
+    /// Sensor diagnostics, only available to paired clients.
+    async fn get_sensor_full(
+        &self,
+        host: &str,
+        pair_id: &str,
+    ) -> Result<SensorFullResponse, Box<dyn Error + Send + Sync>>;
     async fn pair(&self, host: &str) -> Result<SensorEntity, Box<dyn Error + Send + Sync>>;
+    /// Asks the sensor to drop `pair_id`, each attempt given up after `timeout`.
+    async fn unpair(
+        &self,
+        host: &str,
+        pair_id: &str,
+        timeout: Duration,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    /// Replaces `pair_id` on the sensor, returning the new key.
+    async fn rotate_pair(
+        &self,
+        host: &str,
+        pair_id: &str,
+    ) -> Result<String, Box<dyn Error + Send + Sync>>;
     async fn update_sensor(
         &self,
         host: &str,
         pair_id: &str,
         sensor: SensorFormData,
     ) -> Result<SensorResponse, Box<dyn Error + Send + Sync>>;
+    async fn set_led(
+        &self,
+        host: &str,
+        pair_id: &str,
+        blinking: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    /// Blinks the sensor LED for `duration`, returns once blinking started.
+    async fn identify(
+        &self,
+        host: &str,
+        pair_id: &str,
+        duration: Duration,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
 }
 
 impl SensorService for reqwest::Client {
     async fn get_sensor(
         &self,
         host: &str,
+        timeout: Duration,
     ) -> Result<Result<SensorEntity, String>, Box<dyn Error + Send + Sync>> {
-        let host_uri = format!("http://{}:{}/", host, SENSOR_PORT);
+        let host_uri = host_uri(host);
         let response = self
             .get(host_uri.clone() + "sensor")
-            .timeout(std::time::Duration::from_secs_f32(0.2))
-            .send_parse_retry::<SensorResponse, ErrorResponse>(3)
+            .timeout(timeout)
+            .send_parse_retry::<SensorResponse, ErrorResponse>(INFO_ATTEMPTS)
             .await?
             .map_err(|e| anyhow!("{}", e.error))?;
 
@@ -51,8 +155,24 @@ impl SensorService for reqwest::Client {
         Ok(Ok(sensor_entity))
     }
 
+    async fn get_sensor_full(
+        &self,
+        host: &str,
+        pair_id: &str,
+    ) -> Result<SensorFullResponse, Box<dyn Error + Send + Sync>> {
+        let host_uri = host_uri(host);
+        let response = self
+            .get(host_uri + "sensor/full")
+            .header(PAIR_HEADER_NAME, pair_id)
+            .send_parse_retry::<SensorFullResponse, ErrorResponse>(3)
+            .await?
+            .map_err(sensor_error)?;
+
+        Ok(response)
+    }
+
     async fn pair(&self, host: &str) -> Result<SensorEntity, Box<dyn Error + Send + Sync>> {
-        let host_uri = format!("http://{}:{}/", host, SENSOR_PORT);
+        let host_uri = host_uri(host);
         let response = self
             .post(host_uri.clone() + "pair")
             .send_parse_retry::<PairResponse, ErrorResponse>(3)
@@ -74,7 +194,7 @@ impl SensorService for reqwest::Client {
         if response.is_success() {
             // Wait for the sensor to reopen the socket
             tokio::time::sleep(Duration::from_secs_f32(0.2)).await;
-            let mut sensor = self.get_sensor(host).await??;
+            let mut sensor = self.get_sensor(host, INFO_TIMEOUT).await??;
             sensor.pair_id = Some(id.to_string());
             Ok(sensor)
         } else {
@@ -82,13 +202,51 @@ impl SensorService for reqwest::Client {
         }
     }
 
+    async fn unpair(
+        &self,
+        host: &str,
+        pair_id: &str,
+        timeout: Duration,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let host_uri = host_uri(host);
+        let response = self
+            .post(host_uri.clone() + "pair/revoke")
+            .header(PAIR_HEADER_NAME, pair_id)
+            .timeout(timeout)
+            .send_parse_err_retry::<ErrorResponse>(3)
+            .await?
+            .map_err(sensor_error)?;
+
+        if response.is_success() {
+            Ok(())
+        } else {
+            Err("Unpairing failed".into())
+        }
+    }
+
+    async fn rotate_pair(
+        &self,
+        host: &str,
+        pair_id: &str,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let host_uri = host_uri(host);
+        let response = self
+            .post(host_uri.clone() + "pair/rotate")
+            .header(PAIR_HEADER_NAME, pair_id)
+            .send_parse_retry::<PairResponse, ErrorResponse>(3)
+            .await?
+            .map_err(sensor_error)?;
+
+        Ok(response.id)
+    }
+
     async fn update_sensor(
         &self,
         host: &str,
         pair_id: &str,
         sensor: SensorFormData,
     ) -> Result<SensorResponse, Box<dyn Error + Send + Sync>> {
-        let host_uri = format!("http://{}:{}/", host, SENSOR_PORT);
+        let host_uri = host_uri(host);
         let sensor_dto = sensor.into();
         let response = self
             .post(host_uri.clone() + "sensor")
@@ -96,7 +254,7 @@ impl SensorService for reqwest::Client {
             .json::<SensorDto>(&sensor_dto)
             .send_parse_err_retry::<ErrorResponse>(3)
             .await?
-            .map_err(|e| e.error.to_string());
+            .map_err(sensor_error);
 
         let response = response?;
 
@@ -114,47 +272,145 @@ impl SensorService for reqwest::Client {
 
         Err("Update failed".into())
     }
+
+    async fn set_led(
+        &self,
+        host: &str,
+        pair_id: &str,
+        blinking: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let host_uri = host_uri(host);
+        let path = match blinking {
+            true => "led/on",
+            false => "led/off",
+        };
+        let response = self
+            .post(host_uri + path)
+            .header(PAIR_HEADER_NAME, pair_id)
+            .send_parse_err_retry::<ErrorResponse>(3)
+            .await?
+            .map_err(sensor_error)?;
+
+        if response.is_success() {
+            Ok(())
+        } else {
+            Err("Setting LED failed".into())
+        }
+    }
+
+    async fn identify(
+        &self,
+        host: &str,
+        pair_id: &str,
+        duration: Duration,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.set_led(host, pair_id, true).await?;
+
+        let client = self.clone();
+        let host = host.to_string();
+        let pair_id = pair_id.to_string();
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+            if let Err(e) = client.set_led(&host, &pair_id, false).await {
+                tracing::error!("Failed to turn off LED of sensor {}: {}", host, e);
+            }
+        });
+
+        Ok(())
+    }
 }
 
-pub trait TempSensorService {
-    async fn get_temp(
+pub trait MeasurementSensorService {
+    /// Up to `count` readings of the measurement feature `info`, none newer than `max_age`.
+    async fn get_readings(
         &self,
         host: &str,
         pair_id: &str,
+        info: &FeatureInfo,
         count: Option<u64>,
         max_age: Option<u64>,
     ) -> Result<Vec<Measurement>, anyhow::Error>;
 }
 
 #[derive(Serialize)]
-struct TempRequest {
+struct ReadingsRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     count: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     timestamp: Option<u64>,
 }
 
-impl TempSensorService for reqwest::Client {
-    async fn get_temp(
+impl MeasurementSensorService for reqwest::Client {
+    async fn get_readings(
         &self,
         host: &str,
         pair_id: &str,
+        info: &FeatureInfo,
         count: Option<u64>,
         max_age: Option<u64>,
     ) -> Result<Vec<Measurement>, anyhow::Error> {
-        let host_uri = format!("http://{}:{}/", host, SENSOR_PORT);
+        let host_uri = host_uri(host);
         let response = self
-            .get(host_uri.clone() + "dht")
+            .get(host_uri.clone() + info.endpoint)
             .header(PAIR_HEADER_NAME, pair_id)
-            .json(&TempRequest {
+            .json(&ReadingsRequest {
                 count,
                 timestamp: max_age,
             })
             .send_parse_retry::<MeasurementsResponse, ErrorResponse>(3)
             .await
             .map_err(|e| anyhow::anyhow!("{}", e))?
-            .map_err(|e| anyhow::anyhow!("{}", e.error))?;
+            .map_err(|e| match e.error == UNPAIRED_ERROR_MESSAGE {
+                true => anyhow::Error::new(UnpairedError),
+                false => anyhow::anyhow!("{}", e.error),
+            })?;
 
         Ok(response.measurements)
     }
 }
+
+pub trait MotionSensorService {
+    /// Motion events of the feature `info` that started after `after`, newest first.
+    async fn get_motion(
+        &self,
+        host: &str,
+        pair_id: &str,
+        info: &FeatureInfo,
+        after: Option<i64>,
+        count: Option<u64>,
+    ) -> Result<Vec<MotionEvent>, anyhow::Error>;
+}
+
+#[derive(Serialize)]
+struct MotionRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    count: Option<u64>,
+}
+
+impl MotionSensorService for reqwest::Client {
+    async fn get_motion(
+        &self,
+        host: &str,
+        pair_id: &str,
+        info: &FeatureInfo,
+        after: Option<i64>,
+        count: Option<u64>,
+    ) -> Result<Vec<MotionEvent>, anyhow::Error> {
+        let host_uri = host_uri(host);
+        let response = self
+            .get(host_uri + info.endpoint)
+            .header(PAIR_HEADER_NAME, pair_id)
+            .json(&MotionRequest { after, count })
+            .send_parse_retry::<MotionEventsResponse, ErrorResponse>(3)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?
+            .map_err(|e| match e.error == UNPAIRED_ERROR_MESSAGE {
+                true => anyhow::Error::new(UnpairedError),
+                false => anyhow::anyhow!("{}", e.error),
+            })?;
+
+        Ok(response.events)
+    }
+}
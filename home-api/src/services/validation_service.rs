@@ -0,0 +1,232 @@
+use crate::{
+    database::{
+        measurement_validation::MeasurementValidationDatabase, measurements::MeasurementDatabase,
+        DbPool,
+    },
+    models::db::{MeasurementEntry, ReadingFlag, ValidationRule},
+};
+use std::collections::VecDeque;
+
+/// Stored readings looked back at for the last good one, when the median window is shorter.
+const HISTORY: usize = 10;
+
+/// Flags the collected `entries` breaking the validation rule of their metric,
+/// checking them against the readings stored before them. Returns the number of flagged entries.
+pub async fn flag_entries(
+    pool: &DbPool,
+    entries: &mut [MeasurementEntry],
+) -> Result<usize, anyhow::Error> {
+    let conn = pool.get().await?;
+    let rules = conn.get_validation_rules().await?;
+    entries.sort_by(|a, b| {
+        (&a.host, a.feature.bits(), &a.metric, a.timestamp).cmp(&(
+            &b.host,
+            b.feature.bits(),
+            &b.metric,
+            b.timestamp,
+        ))
+    });
+    let mut flagged = 0;
+    for readings in entries
+        .chunk_by_mut(|a, b| a.host == b.host && a.feature == b.feature && a.metric == b.metric)
+    {
+        let first = &readings[0];
+        let Some(rule) = rules
+            .iter()
+            .find(|r| r.feature == first.feature && r.metric == first.metric)
+        else {
+            continue;
+        };
+        let previous = conn
+            .get_recent_entries(
+                &first.host,
+                first.feature,
+                &first.metric,
+                first.timestamp,
+                HISTORY.max(rule.median_window),
+            )
+            .await?;
+        flagged += flag_readings(rule, &previous, readings);
+    }
+
+    Ok(flagged)
+}
+
+/// Flags the `readings` of one metric of a sensor breaking `rule`, both them and the `previous`
+/// readings oldest first. Returns the number of flagged readings.
+///
+/// The rate of change is checked against the last good reading, the median is taken of the
+/// readings just before, flagged ones included, once there are enough of them.
+pub fn flag_readings(
+    rule: &ValidationRule,
+    previous: &[MeasurementEntry],
+    readings: &mut [MeasurementEntry],
+) -> usize {
+    let mut last_good = previous
+        .iter()
+        .rev()
+        .find(|e| e.flag.is_none())
+        .map(|e| (e.timestamp, e.value));
+    let before = rule.median_window.saturating_sub(1);
+    let mut window = previous
+        .iter()
+        .rev()
+        .take(before)
+        .rev()
+        .map(|e| e.value)
+        .collect::<VecDeque<_>>();
+    let mut flagged = 0;
+    for reading in readings {
+        reading.flag = check(rule, last_good, &window, reading);
+        match reading.flag {
+            Some(_) => flagged += 1,
+            None => last_good = Some((reading.timestamp, reading.value)),
+        }
+        window.push_back(reading.value);
+        if window.len() > before {
+            window.pop_front();
+        }
+    }
+
+    flagged
+}
+
+fn check(
+    rule: &ValidationRule,
+    last_good: Option<(u64, f64)>,
+    window: &VecDeque<f64>,
+    reading: &MeasurementEntry,
+) -> Option<ReadingFlag> {
+    if rule.min.is_some_and(|min| reading.value < min)
+        || rule.max.is_some_and(|max| reading.value > max)
+    {
+        return Some(ReadingFlag::OutOfBounds);
+    }
+    if let (Some(max_rate), Some((timestamp, value))) = (rule.max_rate, last_good) {
+        let minutes = reading.timestamp.saturating_sub(timestamp) as f64 / 60.0;
+        if minutes > 0.0 && (reading.value - value).abs() / minutes > max_rate {
+            return Some(ReadingFlag::RateOfChange);
+        }
+    }
+    if let Some(max_deviation) = rule.max_deviation {
+        if rule.median_window > 1 && window.len() + 1 >= rule.median_window {
+            let mut values = window.iter().copied().collect::<Vec<_>>();
+            values.push(reading.value);
+            if (reading.value - median(values)).abs() > max_deviation {
+                return Some(ReadingFlag::Spike);
+            }
+        }
+    }
+
+    None
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    match values.len() % 2 {
+        0 => (values[middle - 1] + values[middle]) / 2.0,
+        _ => values[middle],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::db::SensorFeatures;
+
+    fn rule() -> ValidationRule {
+        ValidationRule {
+            feature: SensorFeatures::TEMPERATURE,
+            metric: "temperature".to_string(),
+            min: Some(-40.0),
+            max: Some(80.0),
+            max_rate: None,
+            median_window: 5,
+            max_deviation: Some(5.0),
+        }
+    }
+
+    fn entries(values: &[f64]) -> Vec<MeasurementEntry> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| MeasurementEntry {
+                host: "sensor".to_string(),
+                feature: SensorFeatures::TEMPERATURE,
+                metric: "temperature".to_string(),
+                timestamp: i as u64 * 60,
+                value: *value,
+                flag: None,
+            })
+            .collect()
+    }
+
+    fn flags(readings: &[MeasurementEntry]) -> Vec<Option<ReadingFlag>> {
+        readings.iter().map(|r| r.flag).collect()
+    }
+
+    #[test]
+    fn readings_outside_of_the_bounds_are_flagged() {
+        let mut readings = entries(&[21.0, -50.0, 21.0, 95.0]);
+        assert_eq!(flag_readings(&rule(), &[], &mut readings), 2);
+        assert_eq!(
+            flags(&readings),
+            [
+                None,
+                Some(ReadingFlag::OutOfBounds),
+                None,
+                Some(ReadingFlag::OutOfBounds)
+            ]
+        );
+    }
+
+    #[test]
+    fn spikes_are_flagged_once_the_window_is_full() {
+        let mut readings = entries(&[35.0, 21.0, 21.2, 21.1, 21.0, 35.0, 21.1]);
+        assert_eq!(flag_readings(&rule(), &[], &mut readings), 1);
+        assert_eq!(readings[5].flag, Some(ReadingFlag::Spike));
+        assert_eq!(readings[0].flag, None);
+    }
+
+    #[test]
+    fn previous_readings_fill_the_window() {
+        let previous = entries(&[21.0, 21.2, 21.1, 21.0]);
+        let mut readings = entries(&[35.0]);
+        readings[0].timestamp = 240;
+        assert_eq!(flag_readings(&rule(), &previous, &mut readings), 1);
+        assert_eq!(readings[0].flag, Some(ReadingFlag::Spike));
+    }
+
+    #[test]
+    fn rate_is_checked_against_the_last_good_reading() {
+        let rule = ValidationRule {
+            max_rate: Some(0.5),
+            max_deviation: None,
+            ..rule()
+        };
+        let mut readings = entries(&[20.0, 25.0, 20.4, 21.5]);
+        assert_eq!(flag_readings(&rule, &[], &mut readings), 2);
+        assert_eq!(
+            flags(&readings),
+            [
+                None,
+                Some(ReadingFlag::RateOfChange),
+                None,
+                Some(ReadingFlag::RateOfChange)
+            ]
+        );
+    }
+
+    #[test]
+    fn slow_changes_pass_the_rate_check() {
+        let rule = ValidationRule {
+            max_rate: Some(0.5),
+            max_deviation: None,
+            ..rule()
+        };
+        let mut readings = entries(&[20.0, 25.0]);
+        readings[1].timestamp = 20 * 60;
+        assert_eq!(flag_readings(&rule, &[], &mut readings), 0);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{
+    api_error::{into_api_err, ApiErrorResponse},
+    database::{alerts::AlertDatabase, areas::AreaDatabase, sensors::SensorDatabase},
+    features::UnitPreferences,
+    models::{
+        db::{Alert, AlertRule, AreaEntity, ScheduleTarget, SensorEntity},
+        json::AlertRuleFormData,
+        RequestData, User,
+    },
+};
+use askama::Template;
+use axum::{extract::Query, response::Html, Form};
+use reqwest::StatusCode;
+use std::collections::BTreeMap;
+
+/// Number of resolved alerts listed on the alerts page.
+const HISTORY_LIMIT: usize = 50;
+
+#[derive(Template)]
+#[template(path = "pages/alerts.html")]
+pub struct AlertsTemplate {
+    pub current_user: Option<User>,
+    pub page: AlertsPage,
+}
+
+#[derive(Template)]
+#[template(path = "pages/alerts-inner.html")]
+pub struct AlertsInnerTemplate {
+    pub page: AlertsPage,
+}
+
+pub struct AlertsPage {
+    pub rules: Vec<AlertRule>,
+    pub active: Vec<Alert>,
+    pub history: Vec<Alert>,
+    pub sensors: Vec<SensorEntity>,
+    pub areas: Vec<AreaEntity>,
+    pub units: UnitPreferences,
+}
+
+impl AlertsPage {
+    async fn load(req_data: &RequestData) -> Result<Self, ApiErrorResponse> {
+        let rules = into_api_err(
+            req_data.conn.get_alert_rules().await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            req_data,
+        )?;
+        let active = into_api_err(
+            req_data.conn.get_active_alerts().await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            req_data,
+        )?;
+        let history = into_api_err(
+            req_data.conn.get_resolved_alerts(HISTORY_LIMIT).await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            req_data,
+        )?;
+        let sensors = into_api_err(
+            req_data
+                .conn
+                .get_sensors()
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e)),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            req_data,
+        )?;
+        let areas = into_api_err(
+            req_data.conn.get_area_entities().await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            req_data,
+        )?;
+        Ok(Self {
+            rules,
+            active,
+            history,
+            sensors,
+            areas,
+            units: req_data.units(),
+        })
+    }
+
+    pub fn target_name(&self, rule: &AlertRule) -> String {
+        match &rule.target {
+            ScheduleTarget::All => "All sensors".to_string(),
+            ScheduleTarget::Area(id) => self
+                .areas
+                .iter()
+                .find(|a| a.id == *id)
+                .map_or(format!("Area {}", id), |a| format!("Area {}", a.name)),
+            ScheduleTarget::Sensor(host) => self
+                .sensors
+                .iter()
+                .find(|s| s.host == *host)
+                .map_or(format!("Sensor {}", host), |s| format!("Sensor {}", s.name)),
+        }
+    }
+
+    /// Hysteresis of `rule` in the unit its metric is shown in.
+    pub fn hysteresis(&self, rule: &AlertRule) -> String {
+        match rule.condition.metric() {
+            Some((_, _, metric)) => {
+                let unit = self.units.unit(metric);
+                format!(
+                    "{} {}",
+                    (unit.difference_from_base(rule.hysteresis) * 100.0).round() / 100.0,
+                    unit.label
+                )
+            }
+            None => rule.hysteresis.to_string(),
+        }
+    }
+
+    /// Value that fired `alert`, in the unit the metric of its rule is shown in.
+    pub fn value(&self, alert: &Alert) -> String {
+        let Some(value) = alert.value else {
+            return "-".to_string();
+        };
+        match self
+            .rules
+            .iter()
+            .find(|r| r.id == alert.rule_id)
+            .and_then(|r| r.condition.metric())
+        {
+            Some((_, _, metric)) => format!(
+                "{} {}",
+                self.units.value(metric, value),
+                self.units.unit(metric).label
+            ),
+            None => value.to_string(),
+        }
+    }
+
+    /// Whether `rule` has a firing alert.
+    pub fn is_firing(&self, rule: &AlertRule) -> bool {
+        self.active.iter().any(|a| a.rule_id == rule.id)
+    }
+}
+
+pub async fn alerts(req_data: RequestData) -> Result<Html<String>, ApiErrorResponse> {
+    let page = AlertsPage::load(&req_data).await?;
+
+    if req_data.is_hx_request {
+        return Ok(Html(AlertsInnerTemplate { page }.render().unwrap()));
+    }
+
+    Ok(Html(
+        AlertsTemplate {
+            current_user: req_data.user,
+            page,
+        }
+        .render()
+        .unwrap(),
+    ))
+}
+
+pub async fn create_alert_rule(
+    req_data: RequestData,
+    Form(rule): Form<AlertRuleFormData>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let rule: AlertRule = into_api_err(rule.try_into(), StatusCode::BAD_REQUEST, &req_data)?;
+    // thresholds are entered in the units the user sees
+    let rule = rule.into_base_units(&req_data.units());
+    into_api_err(
+        req_data.conn.create_alert_rule(rule).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let page = AlertsPage::load(&req_data).await?;
+
+    Ok(Html(AlertsInnerTemplate { page }.render().unwrap()))
+}
+
+pub async fn delete_alert_rule(
+    req_data: RequestData,
+    Query(query): Query<BTreeMap<String, String>>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let id = into_api_err(
+        query
+            .get("id")
+            .ok_or(anyhow::anyhow!("Missing `id` field"))
+            .and_then(|id| Ok(id.parse::<i64>()?)),
+        StatusCode::BAD_REQUEST,
+        &req_data,
+    )?;
+    into_api_err(
+        req_data.conn.delete_alert_rule(id).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let page = AlertsPage::load(&req_data).await?;
+
+    Ok(Html(AlertsInnerTemplate { page }.render().unwrap()))
+}
@@ -2,21 +2,27 @@ use crate::{
     api_error::api_err,
     api_error::into_api_err,
     api_error::ApiErrorResponse,
-    database::{areas::AreaDatabase, sensors::SensorDatabase, temp_data::TempDataDatabase},
+    database::{
+        areas::AreaDatabase, measurement_rollups::MeasurementRollupDatabase,
+        motion_data::MotionDataDatabase, sensors::SensorDatabase,
+    },
+    features::{self, DerivedMetric, FeatureInfo, FeatureKind},
     models::{
-        db::{AreaEntity, SensorEntity, SensorFeatures},
-        json::AreaFormData,
+        db::{AreaEntity, ChartComparison, DataTimezone, MotionDataEntry, SensorEntity},
+        host_from_id, host_id,
+        json::{AreaFormData, ChartDataResponse},
         Area, RequestData, User,
     },
+    services::chart_service::{self, ChartSource},
 };
 use askama::Template;
 use axum::{
     extract::{Path, Query},
     response::Html,
-    Form,
+    Form, Json,
 };
 use reqwest::StatusCode;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Template)]
 #[template(path = "pages/areas.html")]
@@ -41,28 +47,160 @@ pub struct AreaTemplate {
 #[template(path = "components/area-chart.html")]
 pub struct AreaChartTemplate {
     pub area: Area,
-    pub feature: String,
+    pub info: &'static FeatureInfo,
     pub sensor: Option<SensorEntity>,
-    pub last: usize,
+    /// Areas the chart can be compared with.
+    pub areas: Vec<AreaEntity>,
+    pub query: AreaChartQuery,
     pub no_control: bool,
-    pub chart: Chart,
 }
 
-pub struct Chart {
-    pub _type: String,
-    pub labels: String,
-    pub data: Vec<ChartData>,
+impl AreaChartTemplate {
+    fn link(&self, query: AreaChartQuery) -> String {
+        format!(
+            "/areas/{}/chart?{}",
+            self.area.id,
+            serde_urlencoded::to_string(query).unwrap_or_default()
+        )
+    }
+
+    /// Address of the chart data, completed with the width of the chart by the page.
+    pub fn data_url(&self) -> String {
+        format!(
+            "/areas/{}/chart/data?{}",
+            self.area.id,
+            serde_urlencoded::to_string(&self.query).unwrap_or_default()
+        )
+    }
+
+    pub fn sensor_link(&self, sensor: Option<&SensorEntity>) -> String {
+        self.link(AreaChartQuery {
+            sensor: sensor.map(|s| host_id(&s.host)),
+            breakdown: None,
+            ..self.query.clone()
+        })
+    }
+
+    pub fn last_link(&self, days: usize) -> String {
+        self.link(AreaChartQuery {
+            last: Some(days),
+            from: None,
+            to: None,
+            ..self.query.clone()
+        })
+    }
+
+    /// Link drawing the derived metric `name` too, or no longer drawing it.
+    pub fn derived_link(&self, name: &str) -> String {
+        let mut names = self
+            .query
+            .derived_names()
+            .filter(|n| *n != name)
+            .collect::<Vec<_>>();
+        if !self.is_derived(name) {
+            names.push(name);
+        }
+        self.link(AreaChartQuery {
+            derived: (!names.is_empty()).then(|| names.join(",")),
+            ..self.query.clone()
+        })
+    }
+
+    pub fn is_derived(&self, name: &str) -> bool {
+        self.query.derived_names().any(|n| n == name)
+    }
+
+    pub fn breakdown_link(&self, breakdown: bool) -> String {
+        self.link(AreaChartQuery {
+            breakdown: breakdown.then(String::new),
+            ..self.query.clone()
+        })
+    }
+
+    /// Whether the last `days` are charted, rather than a custom range.
+    pub fn is_last(&self, days: usize) -> bool {
+        !self.query.is_custom() && self.query.last.unwrap_or(1) == days
+    }
+
+    pub fn is_breakdown(&self) -> bool {
+        self.query.breakdown.is_some()
+    }
+
+    pub fn flagged_link(&self, flagged: bool) -> String {
+        self.link(AreaChartQuery {
+            flagged: flagged.then(String::new),
+            ..self.query.clone()
+        })
+    }
+
+    pub fn is_flagged(&self) -> bool {
+        self.query.flagged.is_some()
+    }
+
+    pub fn value(&self, name: &str) -> String {
+        match name {
+            "from" => self.query.from.clone(),
+            "to" => self.query.to.clone(),
+            "compare" => self.query.compare.clone(),
+            _ => None,
+        }
+        .unwrap_or_default()
+    }
 }
 
-pub struct ChartData {
-    pub label: String,
-    pub data: String,
-    pub color: String,
-    pub grid_color: String,
+/// Floating bars of motion periods per sensor, times in ms.
+#[derive(Serialize)]
+pub struct MotionTimeline {
+    pub labels: Vec<String>,
+    pub data: Vec<MotionBar>,
     pub min: i64,
     pub max: i64,
 }
 
+/// A motion period of the sensor labeled `y`.
+#[derive(Serialize)]
+pub struct MotionBar {
+    pub x: [i64; 2],
+    pub y: String,
+}
+
+impl MotionTimeline {
+    pub fn new(sensors: &[SensorEntity], events: &[MotionDataEntry], from: i64, to: i64) -> Self {
+        let name = |host: &str| {
+            sensors
+                .iter()
+                .find(|s| s.host == host)
+                .map_or(host.to_string(), |s| s.name.clone())
+        };
+        Self {
+            labels: sensors.iter().map(|s| s.name.clone()).collect(),
+            data: events
+                .iter()
+                .map(|e| MotionBar {
+                    x: [e.start * 1000, e.end.unwrap_or(to) * 1000],
+                    y: name(&e.host),
+                })
+                .collect(),
+            min: from * 1000,
+            max: to * 1000,
+        }
+    }
+
+    /// The timeline as JSON, read by the chart script from an attribute of the canvas.
+    pub fn json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+#[derive(Template)]
+#[template(path = "components/motion-chart.html")]
+pub struct MotionChartTemplate {
+    pub target: String,
+    pub control_url: Option<String>,
+    pub last: usize,
+    pub timeline: MotionTimeline,
+}
+
 pub async fn areas(req_data: RequestData) -> Result<Html<String>, ApiErrorResponse> {
     let areas = into_api_err(
         req_data.conn.get_areas().await,
@@ -183,149 +321,323 @@ pub async fn delete_area(
     ))
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct AreaChartQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
     feature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     last: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     sensor: Option<String>,
-    #[serde(rename = "no-control")]
+    /// Start of a custom range, replacing `last`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<String>,
+    /// End of a custom range, now when missing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to: Option<String>,
+    /// `previous` or `area:<id>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compare: Option<String>,
+    /// Draws every sensor of the area instead of their average.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    breakdown: Option<String>,
+    /// Comma separated names of the derived metrics drawn next to the stored ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    derived: Option<String>,
+    /// Includes the readings flagged on collection.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flagged: Option<String>,
+    #[serde(rename = "no-control", skip_serializing_if = "Option::is_none")]
     no_control: Option<String>,
+    /// Width of the chart in pixels.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<u32>,
+}
+
+/// Default width of a chart in pixels.
+const CHART_WIDTH: u32 = 800;
+const DAY_SECS: i64 = 24 * 60 * 60;
+/// Longest period `last` may chart.
+const MAX_LAST_DAYS: usize = 10 * 366;
+
+impl AreaChartQuery {
+    /// A field left empty in the chart controls is not set.
+    fn field(value: &Option<String>) -> Option<&str> {
+        value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+    }
+
+    fn is_custom(&self) -> bool {
+        Self::field(&self.from).is_some() || Self::field(&self.to).is_some()
+    }
+
+    /// Start and end of the charted period, the last `last` days unless a custom range is given.
+    fn range(&self, now: i64) -> Result<(i64, i64), anyhow::Error> {
+        let to = DataTimezone::Local
+            .parse_time(Self::field(&self.to).unwrap_or_default())?
+            .unwrap_or(now)
+            .min(now);
+        let from =
+            match DataTimezone::Local.parse_time(Self::field(&self.from).unwrap_or_default())? {
+                Some(from) => from,
+                None => {
+                    let last = self.last.unwrap_or(1);
+                    if last > MAX_LAST_DAYS {
+                        anyhow::bail!("The range can't be longer than {} days", MAX_LAST_DAYS);
+                    }
+                    to - last as i64 * DAY_SECS
+                }
+            };
+        if from < 0 {
+            anyhow::bail!("The range can't start before 1970");
+        }
+        if from >= to {
+            anyhow::bail!("The start of the range must be before its end");
+        }
+
+        Ok((from, to))
+    }
+
+    fn comparison(&self) -> Result<Option<ChartComparison>, anyhow::Error> {
+        Self::field(&self.compare).map(str::parse).transpose()
+    }
+
+    fn derived_names(&self) -> impl Iterator<Item = &str> {
+        Self::field(&self.derived)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+    }
+
+    fn derived(&self, info: &FeatureInfo) -> Result<Vec<&'static DerivedMetric>, anyhow::Error> {
+        self.derived_names()
+            .map(|name| {
+                info.derived_metric(name)
+                    .ok_or(anyhow::anyhow!("Invalid derived metric `{}`", name))
+            })
+            .collect()
+    }
 }
 
 pub async fn area_chart(
     Path(id): Path<i64>,
     req_data: RequestData,
-    Query(feature): Query<AreaChartQuery>,
+    Query(query): Query<AreaChartQuery>,
 ) -> Result<Html<String>, ApiErrorResponse> {
-    let AreaChartQuery {
-        feature,
-        last,
-        sensor,
-        no_control,
-    } = feature;
-    match feature.as_deref() {
-        Some("temp") => {
+    let Some(info) = query.feature.as_deref().and_then(features::by_key) else {
+        return api_err("Invalid feature", StatusCode::BAD_REQUEST, &req_data);
+    };
+    let now = chrono::offset::Utc::now().timestamp();
+    let (from, to) = into_api_err(query.range(now), StatusCode::BAD_REQUEST, &req_data)?;
+    match info.kind {
+        FeatureKind::Measurement => {
+            into_api_err(query.comparison(), StatusCode::BAD_REQUEST, &req_data)?;
+            into_api_err(query.derived(info), StatusCode::BAD_REQUEST, &req_data)?;
             let area = into_api_err(
                 req_data.conn.get_area(id).await,
                 StatusCode::INTERNAL_SERVER_ERROR,
                 &req_data,
             )?;
-            let sensor = match sensor {
+            let sensor = match query.sensor.as_deref() {
                 Some(host) => into_api_err(
                     req_data
                         .conn
-                        .get_sensor(host.replace('-', ".").trim())
+                        .get_sensor(host_from_id(host.trim()).as_str())
                         .await,
                     StatusCode::INTERNAL_SERVER_ERROR,
                     &req_data,
                 )?,
                 None => None,
             };
-            let last = last.unwrap_or(1);
-            let mut temp_data = into_api_err(
+            let areas = into_api_err(
+                req_data.conn.get_area_entities().await,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &req_data,
+            )?
+            .into_iter()
+            .filter(|a| a.id != area.id)
+            .collect();
+            Ok(Html(
+                AreaChartTemplate {
+                    area,
+                    info,
+                    sensor,
+                    areas,
+                    no_control: query.no_control.is_some(),
+                    query: AreaChartQuery {
+                        feature: Some(info.key.to_string()),
+                        no_control: None,
+                        width: None,
+                        ..query
+                    },
+                }
+                .render()
+                .unwrap(),
+            ))
+        }
+        FeatureKind::Events => {
+            let area = into_api_err(
+                req_data.conn.get_area(id).await,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &req_data,
+            )?;
+            let sensors = area
+                .sensors
+                .iter()
+                .filter(|s| s.features.contains(info.feature))
+                .cloned()
+                .collect::<Vec<_>>();
+            let events = into_api_err(
                 req_data
                     .conn
-                    .get_temp_data(
-                        sensor.as_ref().map(|s| vec![s.host.clone()]).or_else(|| {
-                            Some(area.sensors.iter().map(|s| s.host.clone()).collect())
-                        }),
+                    .get_motion_data(
+                        Some(sensors.iter().map(|s| s.host.clone()).collect()),
                         None,
                         None,
-                        Some(
-                            chrono::offset::Utc::now()
-                                .checked_sub_days(chrono::Days::new(last as u64))
-                                .unwrap()
-                                .timestamp(),
-                        ),
+                        Some((from, to)),
                     )
                     .await,
                 StatusCode::INTERNAL_SERVER_ERROR,
                 &req_data,
             )?;
-            temp_data.reverse();
-            let chart = Chart {
-                _type: "line".to_string(),
-                labels: format!(
-                    "[{}]",
-                    temp_data
-                        .iter()
-                        .map(|t| format!("new Date({} * 1000).toLocaleString()", t.timestamp))
-                        .collect::<Vec<String>>()
-                        .join(", ")
-                ),
-                data: vec![
-                    {
-                        let temps = temp_data.iter().map(|t| t.temperature);
-                        let min = temps
-                            .clone()
-                            .min_by(|a, b| a.partial_cmp(b).unwrap())
-                            .unwrap_or_default();
-                        let max = temps
-                            .clone()
-                            .max_by(|a, b| a.partial_cmp(b).unwrap())
-                            .unwrap_or_default();
-                        let margin = ((max - min) * 0.1).max(1.0);
-                        let min = min - margin;
-                        let max = max + margin;
-                        ChartData {
-                            label: "Temperature".to_string(),
-                            data: format!(
-                                "[{}]",
-                                temps
-                                    .map(|t| t.to_string())
-                                    .collect::<Vec<String>>()
-                                    .join(", ")
-                            ),
-                            color: "rgba(255, 99, 132, 0.9)".to_string(),
-                            grid_color: "rgba(255, 99, 132, 0.4)".to_string(),
-                            min: min as i64,
-                            max: max as i64,
-                        }
-                    },
-                    {
-                        let hums = temp_data.iter().map(|t| t.humidity);
-                        let min = hums
-                            .clone()
-                            .min_by(|a, b| a.partial_cmp(b).unwrap())
-                            .unwrap_or_default();
-                        let max = hums
-                            .clone()
-                            .max_by(|a, b| a.partial_cmp(b).unwrap())
-                            .unwrap_or_default();
-                        let margin = ((max - min) * 0.1).max(1.0);
-                        let min = min - margin;
-                        let max = max + margin;
-                        ChartData {
-                            label: "Humidity".to_string(),
-                            data: format!(
-                                "[{}]",
-                                temp_data
-                                    .iter()
-                                    .map(|t| t.humidity.to_string())
-                                    .collect::<Vec<String>>()
-                                    .join(", ")
-                            ),
-                            color: "rgba(54, 162, 235, 0.9)".to_string(),
-                            grid_color: "rgba(54, 162, 235, 0.4)".to_string(),
-                            min: min as i64,
-                            max: max as i64,
-                        }
-                    },
-                ],
-            };
             Ok(Html(
-                AreaChartTemplate {
-                    area,
-                    feature: "temp".to_string(),
-                    chart,
-                    last,
-                    no_control: no_control.is_some(),
-                    sensor,
+                MotionChartTemplate {
+                    target: format!("area-chart-{}", area.id),
+                    control_url: match query.no_control {
+                        Some(_) => None,
+                        None => Some(format!("/areas/{}/chart?feature={}", area.id, info.key)),
+                    },
+                    last: query.last.unwrap_or(1),
+                    timeline: MotionTimeline::new(&sensors, &events, from, to),
                 }
                 .render()
                 .unwrap(),
             ))
         }
-        _ => api_err("Invalid feature", StatusCode::BAD_REQUEST, &req_data),
     }
 }
+
+/// Readings of the area, or of one of its sensors, aggregated into buckets sized to the width
+/// of the chart, with the readings compared against drawn over them.
+pub async fn area_chart_data(
+    Path(id): Path<i64>,
+    req_data: RequestData,
+    Query(query): Query<AreaChartQuery>,
+) -> Result<Json<ChartDataResponse>, ApiErrorResponse> {
+    let Some(info) = query
+        .feature
+        .as_deref()
+        .and_then(features::by_key)
+        .filter(|info| info.kind == FeatureKind::Measurement)
+    else {
+        return api_err("Invalid feature", StatusCode::BAD_REQUEST, &req_data);
+    };
+    let now = chrono::offset::Utc::now().timestamp();
+    let (from, to) = into_api_err(query.range(now), StatusCode::BAD_REQUEST, &req_data)?;
+    let comparison = into_api_err(query.comparison(), StatusCode::BAD_REQUEST, &req_data)?;
+    let derived = into_api_err(query.derived(info), StatusCode::BAD_REQUEST, &req_data)?;
+    let area = into_api_err(
+        req_data.conn.get_area(id).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let sensor = query.sensor.as_deref().map(|id| host_from_id(id.trim()));
+    let sensors = area
+        .sensors
+        .into_iter()
+        .filter(|s| s.features.contains(info.feature))
+        .filter(|s| sensor.as_ref().is_none_or(|host| s.host == *host))
+        .collect::<Vec<_>>();
+
+    // overlays are read at the same resolution, so the older previous period decides it
+    let span = (to - from) as u64;
+    let oldest = match comparison {
+        Some(ChartComparison::Previous) => from - span as i64,
+        _ => from,
+    };
+    let policy = into_api_err(
+        req_data.conn.get_retention_policy().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let resolution = policy.resolution_for_range(
+        span.div_ceil(DAY_SECS as u64) as u32,
+        ((now - oldest).max(0) as u64).div_ceil(DAY_SECS as u64) as u32,
+    );
+    let finest = resolution.seconds().or(info.interval).unwrap_or(60);
+    let bucket = chart_service::bucket_seconds(span, query.width.unwrap_or(CHART_WIDTH), finest);
+
+    let mut sources = vec![ChartSource {
+        name: None,
+        sensors,
+        breakdown: query.breakdown.is_some() && sensor.is_none(),
+        ..Default::default()
+    }];
+    let mut ranges = vec![(from, to)];
+    match comparison {
+        Some(ChartComparison::Previous) => {
+            sources[0].name = Some("Current".to_string());
+            sources.push(ChartSource {
+                name: Some("Previous".to_string()),
+                sensors: sources[0].sensors.clone(),
+                shift: span,
+                ..Default::default()
+            });
+            ranges.push((from - span as i64, from));
+        }
+        Some(ChartComparison::Area(other)) => {
+            let other = into_api_err(
+                req_data.conn.get_area(other).await,
+                StatusCode::BAD_REQUEST,
+                &req_data,
+            )?;
+            sources[0].name = Some(area.name.clone());
+            sources.push(ChartSource {
+                name: Some(other.name),
+                sensors: other
+                    .sensors
+                    .into_iter()
+                    .filter(|s| s.features.contains(info.feature))
+                    .collect(),
+                ..Default::default()
+            });
+            ranges.push((from, to));
+        }
+        None => (),
+    }
+    for (source, (from, to)) in sources.iter_mut().zip(ranges) {
+        if source.sensors.is_empty() {
+            continue;
+        }
+        source.buckets = into_api_err(
+            req_data
+                .conn
+                .get_chart_buckets(
+                    info,
+                    source.sensors.iter().map(|s| s.host.clone()).collect(),
+                    resolution,
+                    bucket,
+                    (from, to),
+                    query.flagged.is_some(),
+                )
+                .await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &req_data,
+        )?;
+    }
+    // a bucket of the readings on either side of it may stay empty without a reading missing
+    let gap_threshold = bucket + finest * 3 / 2;
+
+    Ok(Json(ChartDataResponse {
+        rollup: resolution.seconds().is_some(),
+        ..chart_service::build_chart(
+            info,
+            &derived,
+            req_data.units(),
+            &sources,
+            bucket,
+            (from as u64, to as u64),
+            gap_threshold,
+        )
+    }))
+}
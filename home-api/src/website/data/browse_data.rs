@@ -1,12 +1,23 @@
 use crate::{
     api_error::{into_api_err, ApiErrorResponse},
-    database::temp_data::TempDataDatabase,
-    models::{db::TempDataEntry, RequestData, User},
+    database::{
+        areas::AreaDatabase, measurements::MeasurementDatabase, motion_data::MotionDataDatabase,
+        sensors::SensorDatabase,
+    },
+    features::{self, FeatureInfo, FeatureKind, UnitPreferences},
+    models::{
+        db::{
+            AreaEntity, BrowseCursor, BrowseFilter, BrowsePage, BrowseSort, DataTimezone,
+            MeasurementRow, MotionDataEntry, ScheduleTarget, SensorEntity, SensorFeatures,
+        },
+        RequestData, User,
+    },
+    website::areas::MotionTimeline,
 };
 use askama::Template;
 use axum::{extract::Query, response::Html};
 use reqwest::StatusCode;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
 #[derive(Template)]
@@ -23,10 +34,166 @@ pub struct BrowseDataInnerTemplate {
     pub feature: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct BrowseDataQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
     feature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     page: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to: Option<String>,
+    /// Metric the `min` and `max` values apply to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metric: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    order: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<String>,
+    /// Lists the readings flagged on collection too.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flagged: Option<String>,
+    /// `next` or `prev`, the page after or before the cursor row.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
+    #[serde(rename = "cursor-time", skip_serializing_if = "Option::is_none")]
+    cursor_time: Option<String>,
+    #[serde(rename = "cursor-host", skip_serializing_if = "Option::is_none")]
+    cursor_host: Option<String>,
+    #[serde(rename = "cursor-value", skip_serializing_if = "Option::is_none")]
+    cursor_value: Option<String>,
+}
+
+/// Rows per page the browse page can show.
+pub const PAGE_SIZES: &[usize] = &[10, 25, 50, 100];
+
+impl BrowseDataQuery {
+    /// A field left empty in the filter form is not set.
+    fn field(value: &Option<String>) -> Option<&str> {
+        value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+    }
+
+    pub fn value(&self, name: &str) -> &str {
+        let value = match name {
+            "target" => &self.target,
+            "from" => &self.from,
+            "to" => &self.to,
+            "metric" => &self.metric,
+            "min" => &self.min,
+            "max" => &self.max,
+            "sort" => &self.sort,
+            "order" => &self.order,
+            _ => &None,
+        };
+        Self::field(value).unwrap_or_default()
+    }
+
+    pub fn size(&self) -> usize {
+        Self::field(&self.size)
+            .and_then(|s| s.parse::<usize>().ok())
+            .filter(|s| PAGE_SIZES.contains(s))
+            .unwrap_or(PAGE_SIZES[0])
+    }
+
+    /// The filter of the query, with the value range given in the units the user sees.
+    fn filter(
+        &self,
+        info: &'static FeatureInfo,
+        units: UnitPreferences,
+    ) -> Result<(BrowseFilter, BrowsePage), anyhow::Error> {
+        let metric = |name: &str| {
+            info.metrics
+                .iter()
+                .position(|m| m.name == name)
+                .ok_or(anyhow::anyhow!("Invalid metric `{}`", name))
+        };
+        let number = |value: Option<&str>| -> Result<Option<f64>, anyhow::Error> {
+            value
+                .map(|v| {
+                    v.parse::<f64>()
+                        .ok()
+                        .filter(|v| v.is_finite())
+                        .ok_or(anyhow::anyhow!("Invalid value `{}`", v))
+                })
+                .transpose()
+        };
+        let range = match Self::field(&self.metric) {
+            Some(name) => {
+                let index = metric(name)?;
+                let unit = units.unit(&info.metrics[index]);
+                Some((
+                    index,
+                    number(Self::field(&self.min))?.map(unit.to_base),
+                    number(Self::field(&self.max))?.map(unit.to_base),
+                ))
+            }
+            None => None,
+        };
+        let sort = match Self::field(&self.sort) {
+            None | Some("time") => BrowseSort::Time,
+            Some("sensor") => BrowseSort::Sensor,
+            Some(name) => BrowseSort::Metric(metric(name)?),
+        };
+        let filter = BrowseFilter {
+            target: Self::field(&self.target).unwrap_or("all").parse()?,
+            from: DataTimezone::Local.parse_time(self.value("from"))?,
+            to: DataTimezone::Local.parse_time(self.value("to"))?,
+            range,
+            sort,
+            descending: Self::field(&self.order) != Some("asc"),
+            flagged: self.flagged.is_some(),
+        };
+        let cursor = || -> Result<BrowseCursor, anyhow::Error> {
+            Ok(BrowseCursor {
+                timestamp: Self::field(&self.cursor_time)
+                    .ok_or(anyhow::anyhow!("Missing cursor time"))?
+                    .parse()?,
+                host: Self::field(&self.cursor_host)
+                    .ok_or(anyhow::anyhow!("Missing cursor host"))?
+                    .to_string(),
+                value: number(Self::field(&self.cursor_value))?,
+            })
+        };
+        let page = match Self::field(&self.cursor) {
+            None => BrowsePage::First,
+            Some("next") => BrowsePage::After(cursor()?),
+            Some("prev") => BrowsePage::Before(cursor()?),
+            Some(cursor) => anyhow::bail!("Invalid cursor `{}`", cursor),
+        };
+        Ok((filter, page))
+    }
+
+    /// The same query starting at `page`.
+    fn at(&self, page: BrowsePage) -> Self {
+        let (cursor, position) = match page {
+            BrowsePage::First => (None, None),
+            BrowsePage::After(position) => (Some("next"), Some(position)),
+            BrowsePage::Before(position) => (Some("prev"), Some(position)),
+        };
+        Self {
+            cursor: cursor.map(str::to_string),
+            cursor_time: position.as_ref().map(|p| p.timestamp.to_string()),
+            cursor_host: position.as_ref().map(|p| p.host.clone()),
+            cursor_value: position.and_then(|p| p.value).map(|v| v.to_string()),
+            ..self.clone()
+        }
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "/data/browse?{}",
+            serde_urlencoded::to_string(self).unwrap_or_default()
+        )
+    }
 }
 
 pub async fn browse_data(
@@ -34,12 +201,13 @@ pub async fn browse_data(
     Query(query): Query<BrowseDataQuery>,
 ) -> Result<Html<String>, ApiErrorResponse> {
     if let Some(feature) = query.feature.as_ref() {
-        let feature = match feature.as_str() {
-            "temp" => Some(handle_temp_data(query.page, &req_data).await),
-            _ => None,
-        };
-        if let Some(feature) = feature {
-            return feature;
+        if let Some(info) = features::by_key(feature) {
+            return match info.kind {
+                FeatureKind::Measurement => {
+                    handle_measurement_data(info, query.clone(), &req_data).await
+                }
+                FeatureKind::Events => handle_motion_data(query.page, &req_data).await,
+            };
         }
     }
 
@@ -64,14 +232,166 @@ pub async fn browse_data(
 }
 
 #[derive(Template)]
-#[template(path = "components/temp-browse.html")]
-pub struct TempBrowseTemplate {
-    pub items: Vec<TempDataEntry>,
+#[template(path = "components/measurement-browse.html")]
+pub struct MeasurementBrowseTemplate {
+    pub info: &'static FeatureInfo,
+    pub items: Vec<MeasurementRow>,
+    pub query: BrowseDataQuery,
+    pub filter: BrowseFilter,
+    pub has_prev: bool,
+    pub has_next: bool,
+    pub sensors: Vec<SensorEntity>,
+    pub areas: Vec<AreaEntity>,
+    pub units: UnitPreferences,
+}
+
+impl MeasurementBrowseTemplate {
+    pub fn sensor(&self, host: &str) -> Option<&SensorEntity> {
+        self.sensors.iter().find(|s| s.host == host)
+    }
+
+    pub fn is_area(&self, id: &i64) -> bool {
+        matches!(self.filter.target, ScheduleTarget::Area(area) if area == *id)
+    }
+
+    pub fn is_sensor(&self, host: &str) -> bool {
+        matches!(&self.filter.target, ScheduleTarget::Sensor(sensor) if sensor == host)
+    }
+
+    /// Link sorting by `key`, turning the order around when already sorted by it.
+    pub fn sort_link(&self, key: &str) -> String {
+        let order = match self.sort_key() == key && self.filter.descending {
+            true => "asc",
+            false => "desc",
+        };
+        BrowseDataQuery {
+            sort: Some(key.to_string()),
+            order: Some(order.to_string()),
+            ..self.query.at(BrowsePage::First)
+        }
+        .url()
+    }
+
+    /// Arrow shown next to the sorted column.
+    pub fn sort_arrow(&self, key: &str) -> &'static str {
+        match (self.sort_key() == key, self.filter.descending) {
+            (false, _) => "",
+            (true, true) => "▼",
+            (true, false) => "▲",
+        }
+    }
+
+    fn sort_key(&self) -> &str {
+        match self.filter.sort {
+            BrowseSort::Time => "time",
+            BrowseSort::Sensor => "sensor",
+            BrowseSort::Metric(i) => self.info.metrics[i].name,
+        }
+    }
+
+    pub fn first_link(&self) -> String {
+        self.query.at(BrowsePage::First).url()
+    }
+
+    pub fn prev_link(&self) -> String {
+        self.items
+            .first()
+            .map(|row| BrowsePage::Before(BrowseCursor::of(row, self.filter.sort)))
+            .map_or(self.first_link(), |page| self.query.at(page).url())
+    }
+
+    pub fn next_link(&self) -> String {
+        self.items
+            .last()
+            .map(|row| BrowsePage::After(BrowseCursor::of(row, self.filter.sort)))
+            .map_or(self.first_link(), |page| self.query.at(page).url())
+    }
+}
+
+async fn handle_measurement_data(
+    info: &'static FeatureInfo,
+    query: BrowseDataQuery,
+    req_data: &RequestData,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let units = req_data.units();
+    let (filter, page) =
+        into_api_err(query.filter(info, units), StatusCode::BAD_REQUEST, req_data)?;
+    let size = query.size();
+    // one row more than shown tells whether another page follows
+    let mut items = into_api_err(
+        req_data
+            .conn
+            .browse_measurements(info, &filter, &page, size + 1)
+            .await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        req_data,
+    )?;
+    let more = items.len() > size;
+    let (has_prev, has_next) = match page {
+        BrowsePage::First => (false, more),
+        BrowsePage::After(_) => (true, more),
+        BrowsePage::Before(_) => (more, true),
+    };
+    match page {
+        BrowsePage::Before(_) if more => {
+            items.remove(0);
+        }
+        _ => items.truncate(size),
+    }
+    let sensors = into_api_err(
+        req_data
+            .conn
+            .get_sensors()
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e)),
+        StatusCode::INTERNAL_SERVER_ERROR,
+        req_data,
+    )?;
+    let areas = into_api_err(
+        req_data.conn.get_area_entities().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        req_data,
+    )?;
+    let browse = MeasurementBrowseTemplate {
+        info,
+        items,
+        query,
+        filter,
+        has_prev,
+        has_next,
+        sensors,
+        areas,
+        units,
+    }
+    .render()
+    .unwrap();
+    if req_data.is_hx_request {
+        return Ok(Html(browse));
+    }
+
+    Ok(Html(
+        BrowseDataTemplate {
+            current_user: req_data.user.clone(),
+            feature: Some(browse),
+        }
+        .render()
+        .unwrap(),
+    ))
+}
+
+#[derive(Template)]
+#[template(path = "components/motion-browse.html")]
+pub struct MotionBrowseTemplate {
+    pub items: Vec<MotionDataEntry>,
     pub page: usize,
     pub last_page: bool,
+    pub target: String,
+    pub control_url: Option<String>,
+    pub last: usize,
+    pub timeline: MotionTimeline,
 }
 
-async fn handle_temp_data(
+async fn handle_motion_data(
     page: Option<usize>,
     req_data: &RequestData,
 ) -> Result<Html<String>, ApiErrorResponse> {
@@ -80,7 +400,7 @@ async fn handle_temp_data(
     let items = into_api_err(
         req_data
             .conn
-            .get_temp_data(
+            .get_motion_data(
                 Option::<Vec<&'static str>>::None,
                 Some(PAGE_SIZE + 1),
                 offset,
@@ -91,30 +411,50 @@ async fn handle_temp_data(
         req_data,
     )?;
     let last_page = items.get(PAGE_SIZE).is_none();
+    // timeline of the last day for all motion sensors
+    let sensors = into_api_err(
+        req_data
+            .conn
+            .get_sensors_by_features(SensorFeatures::MOTION)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e)),
+        StatusCode::INTERNAL_SERVER_ERROR,
+        req_data,
+    )?;
+    let now = chrono::Utc::now().timestamp();
+    let from = now - 24 * 60 * 60;
+    let events = into_api_err(
+        req_data
+            .conn
+            .get_motion_data(
+                Option::<Vec<&'static str>>::None,
+                None,
+                None,
+                Some((from, now)),
+            )
+            .await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        req_data,
+    )?;
+    let browse = MotionBrowseTemplate {
+        items: items.into_iter().take(PAGE_SIZE).collect(),
+        page: page.unwrap_or(1),
+        last_page,
+        target: "motion-browse-timeline".to_string(),
+        control_url: None,
+        last: 1,
+        timeline: MotionTimeline::new(&sensors, &events, from, now),
+    }
+    .render()
+    .unwrap();
     if req_data.is_hx_request {
-        return Ok(Html(
-            TempBrowseTemplate {
-                items: items.into_iter().take(PAGE_SIZE).collect(),
-                page: page.unwrap_or(1),
-                last_page,
-            }
-            .render()
-            .unwrap(),
-        ));
+        return Ok(Html(browse));
     }
 
     Ok(Html(
         BrowseDataTemplate {
             current_user: req_data.user.clone(),
-            feature: Some(
-                TempBrowseTemplate {
-                    items: items.into_iter().take(PAGE_SIZE).collect(),
-                    page: page.unwrap_or(1),
-                    last_page,
-                }
-                .render()
-                .unwrap(),
-            ),
+            feature: Some(browse),
         }
         .render()
         .unwrap(),
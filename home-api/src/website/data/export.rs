@@ -0,0 +1,222 @@
+use crate::{
+    api_error::{into_api_err, ApiErrorResponse},
+    database::{
+        areas::AreaDatabase, measurements::MeasurementDatabase, sensors::SensorDatabase, DbPool,
+    },
+    models::{
+        db::{AreaEntity, ExportFormat, ExportQuery, MeasurementRow, ScheduleTarget, SensorEntity},
+        json::ExportFormData,
+        RequestData, User,
+    },
+};
+use askama::Template;
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::header,
+    response::{Html, Response},
+};
+use futures_util::stream;
+use reqwest::StatusCode;
+use std::collections::HashMap;
+
+/// Rows read from the database at once while streaming an export.
+const CHUNK_SIZE: usize = 1000;
+
+#[derive(Template)]
+#[template(path = "pages/data-export.html")]
+pub struct DataExportTemplate {
+    pub current_user: Option<User>,
+    pub sensors: Vec<SensorEntity>,
+    pub areas: Vec<AreaEntity>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/data-export-inner.html")]
+pub struct DataExportInnerTemplate {
+    pub sensors: Vec<SensorEntity>,
+    pub areas: Vec<AreaEntity>,
+}
+
+pub async fn data_export(req_data: RequestData) -> Result<Html<String>, ApiErrorResponse> {
+    let sensors = into_api_err(
+        req_data
+            .conn
+            .get_sensors()
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e)),
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let areas = into_api_err(
+        req_data.conn.get_area_entities().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    if req_data.is_hx_request {
+        return Ok(Html(
+            DataExportInnerTemplate { sensors, areas }.render().unwrap(),
+        ));
+    }
+
+    Ok(Html(
+        DataExportTemplate {
+            current_user: req_data.user,
+            sensors,
+            areas,
+        }
+        .render()
+        .unwrap(),
+    ))
+}
+
+/// Streams the requested measurements page by page, so the export is never held in memory.
+pub async fn download_export(
+    State(pool): State<DbPool>,
+    req_data: RequestData,
+    Query(query): Query<ExportFormData>,
+) -> Result<Response, ApiErrorResponse> {
+    let query: ExportQuery = into_api_err(query.try_into(), StatusCode::BAD_REQUEST, &req_data)?;
+    let sensors = into_api_err(
+        req_data
+            .conn
+            .get_sensors()
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e)),
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    // readings of removed sensors are only part of an export of everything
+    let hosts = match &query.target {
+        ScheduleTarget::All => None,
+        target => Some(
+            sensors
+                .iter()
+                .filter(|s| target.matches(s))
+                .map(|s| s.host.clone())
+                .collect(),
+        ),
+    };
+    let names = sensors
+        .into_iter()
+        .map(|s| (s.host, s.name))
+        .collect::<HashMap<_, _>>();
+    let filename = format!(
+        "{}-{}.{}",
+        query.info.key,
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        query.format.extension()
+    );
+    let content_type = query.format.content_type();
+
+    let cursor = ExportCursor {
+        pool,
+        query,
+        hosts,
+        names,
+        after: None,
+        done: false,
+    };
+    let body = stream::try_unfold(cursor, |mut cursor| async move {
+        if cursor.done {
+            return Ok::<_, anyhow::Error>(None);
+        }
+        let mut chunk = match cursor.after {
+            None if cursor.query.format == ExportFormat::Csv => cursor.csv_header(),
+            _ => String::new(),
+        };
+        let conn = cursor.pool.get().await?;
+        let rows = conn
+            .get_measurement_page(
+                cursor.query.info,
+                cursor.hosts.clone(),
+                cursor.query.from,
+                cursor.query.to,
+                cursor.after.take(),
+                CHUNK_SIZE,
+            )
+            .await
+            .inspect_err(|e| tracing::error!("Failed to export measurements: {}", e))?;
+        cursor.done = rows.len() < CHUNK_SIZE;
+        for row in &rows {
+            chunk.push_str(&cursor.line(row));
+        }
+        cursor.after = rows.last().map(|r| (r.timestamp, r.host.clone()));
+
+        Ok(Some((chunk, cursor)))
+    });
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(Body::from_stream(body))
+        .unwrap())
+}
+
+struct ExportCursor {
+    pool: DbPool,
+    query: ExportQuery,
+    hosts: Option<Vec<String>>,
+    names: HashMap<String, String>,
+    /// Key of the last exported row.
+    after: Option<(u64, String)>,
+    done: bool,
+}
+
+impl ExportCursor {
+    fn csv_header(&self) -> String {
+        let mut header = String::from("timestamp,time,host,sensor");
+        for metric in self.query.info.metrics {
+            header.push(',');
+            header.push_str(metric.name);
+        }
+        header.push_str("\r\n");
+        header
+    }
+
+    fn line(&self, row: &MeasurementRow) -> String {
+        let time = self.query.timezone.format(row.timestamp);
+        let name = self.names.get(&row.host).unwrap_or(&row.host);
+        match self.query.format {
+            ExportFormat::Csv => {
+                let mut line = format!(
+                    "{},{},{},{}",
+                    row.timestamp,
+                    time,
+                    csv_field(&row.host),
+                    csv_field(name)
+                );
+                for value in &row.values {
+                    line.push(',');
+                    if let Some(value) = value {
+                        line.push_str(&value.to_string());
+                    }
+                }
+                line.push_str("\r\n");
+                line
+            }
+            ExportFormat::Ndjson => {
+                let mut object = serde_json::Map::new();
+                object.insert("timestamp".to_string(), row.timestamp.into());
+                object.insert("time".to_string(), time.into());
+                object.insert("host".to_string(), row.host.clone().into());
+                object.insert("sensor".to_string(), name.clone().into());
+                for (metric, value) in self.query.info.metrics.iter().zip(&row.values) {
+                    object.insert(metric.name.to_string(), (*value).into());
+                }
+                format!("{}\n", serde_json::Value::Object(object))
+            }
+        }
+    }
+}
+
+/// Quotes a CSV field when it contains a separator, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\r', '\n']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
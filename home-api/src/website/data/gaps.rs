@@ -0,0 +1,90 @@
+use crate::{
+    api_error::{into_api_err, ApiErrorResponse},
+    database::{measurements::MeasurementDatabase, sensors::SensorDatabase},
+    features::{self, FeatureInfo},
+    models::{db::MeasurementGap, RequestData, User},
+};
+use askama::Template;
+use axum::response::Html;
+use reqwest::StatusCode;
+
+#[derive(Template)]
+#[template(path = "pages/data-gaps.html")]
+pub struct DataGapsTemplate {
+    pub current_user: Option<User>,
+    pub reports: Vec<GapReport>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/data-gaps-inner.html")]
+pub struct DataGapsInnerTemplate {
+    pub reports: Vec<GapReport>,
+}
+
+/// Missing readings of a feature stored for a single sensor.
+pub struct GapReport {
+    pub info: &'static FeatureInfo,
+    pub host: String,
+    pub name: String,
+    pub gaps: Vec<MeasurementGap>,
+}
+
+impl GapReport {
+    pub fn missing(&self, gap: &MeasurementGap) -> u64 {
+        gap.missing(self.info.interval.unwrap_or(1))
+    }
+
+    pub fn total_missing(&self) -> u64 {
+        self.gaps.iter().map(|gap| self.missing(gap)).sum()
+    }
+
+    pub fn unrecoverable(&self) -> usize {
+        self.gaps.iter().filter(|gap| gap.unrecoverable).count()
+    }
+}
+
+pub async fn data_gaps(req_data: RequestData) -> Result<Html<String>, ApiErrorResponse> {
+    let sensors = into_api_err(
+        req_data.conn.get_sensors().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let mut reports = Vec::new();
+    for info in features::measurements() {
+        let gaps = into_api_err(
+            req_data.conn.get_measurement_gaps(info, None).await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &req_data,
+        )?;
+        for gap in gaps {
+            match reports
+                .iter_mut()
+                .find(|r: &&mut GapReport| r.info.key == info.key && r.host == gap.host)
+            {
+                Some(report) => report.gaps.push(gap),
+                None => reports.push(GapReport {
+                    info,
+                    name: sensors
+                        .iter()
+                        .find(|s| s.host == gap.host)
+                        .map_or(gap.host.clone(), |s| s.name.clone()),
+                    host: gap.host.clone(),
+                    gaps: vec![gap],
+                }),
+            }
+        }
+    }
+
+    if req_data.is_hx_request {
+        return Ok(Html(DataGapsInnerTemplate { reports }.render().unwrap()));
+    }
+
+    Ok(Html(
+        DataGapsTemplate {
+            current_user: req_data.user,
+            reports,
+        }
+        .render()
+        .unwrap(),
+    ))
+}
@@ -0,0 +1,264 @@
+use crate::{
+    api_error::{api_err, into_api_err, ApiErrorResponse},
+    database::{
+        measurement_rollups::MeasurementRollupDatabase, measurements::MeasurementDatabase,
+        sensors::SensorDatabase, DbPool,
+    },
+    features::{FeatureInfo, UnitPreferences},
+    models::{
+        db::{ImportOptions, MeasurementRow, RejectedRow, SensorEntity},
+        json::ImportFormData,
+        RequestData, User,
+    },
+    services::{import_service, validation_service},
+    website::components::alert::AlertTemplate,
+};
+use askama::Template;
+use axum::{
+    extract::{Multipart, State},
+    response::Html,
+};
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde::{de::value::MapDeserializer, Deserialize};
+use std::collections::HashMap;
+
+/// Largest file accepted for an import.
+pub const UPLOAD_LIMIT: usize = 64 * 1024 * 1024;
+/// Readings stored with a single query.
+const BATCH_SIZE: usize = 500;
+/// Number of rejected rows listed in the report.
+const REJECTED_SHOWN: usize = 50;
+
+#[derive(Template)]
+#[template(path = "pages/data-import.html")]
+pub struct DataImportTemplate {
+    pub current_user: Option<User>,
+    pub sensors: Vec<SensorEntity>,
+    pub units: UnitPreferences,
+}
+
+#[derive(Template)]
+#[template(path = "pages/data-import-inner.html")]
+pub struct DataImportInnerTemplate {
+    pub sensors: Vec<SensorEntity>,
+    pub units: UnitPreferences,
+}
+
+#[derive(Template)]
+#[template(path = "components/import-result.html")]
+pub struct ImportResultTemplate {
+    pub info: &'static FeatureInfo,
+    pub rows: usize,
+    pub preview: Vec<MeasurementRow>,
+    pub rejected: Vec<RejectedRow>,
+    pub rejected_count: usize,
+    /// Number of readings written, `None` for a preview.
+    pub stored: Option<usize>,
+    pub units: UnitPreferences,
+}
+
+pub async fn data_import(req_data: RequestData) -> Result<Html<String>, ApiErrorResponse> {
+    let sensors = into_api_err(
+        req_data
+            .conn
+            .get_sensors()
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e)),
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let units = req_data.units();
+    if req_data.is_hx_request {
+        return Ok(Html(
+            DataImportInnerTemplate { sensors, units }.render().unwrap(),
+        ));
+    }
+
+    Ok(Html(
+        DataImportTemplate {
+            current_user: req_data.user,
+            sensors,
+            units,
+        }
+        .render()
+        .unwrap(),
+    ))
+}
+
+pub async fn preview_import(
+    req_data: RequestData,
+    multipart: Multipart,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let (options, file) = read_upload(&req_data, multipart).await?;
+    let result = into_api_err(
+        import_service::parse_measurements(&options, &file),
+        StatusCode::BAD_REQUEST,
+        &req_data,
+    )?;
+
+    Ok(Html(
+        ImportResultTemplate {
+            info: options.info,
+            rows: result.rows,
+            preview: result.preview,
+            rejected_count: result.rejected.len(),
+            rejected: result.rejected.into_iter().take(REJECTED_SHOWN).collect(),
+            stored: None,
+            units: req_data.units(),
+        }
+        .render()
+        .unwrap(),
+    ))
+}
+
+/// Stores the valid rows of the file, replacing readings the sensor already has at the same time.
+/// The readings are checked against the validation rules like collected ones.
+pub async fn import_data(
+    State(pool): State<DbPool>,
+    req_data: RequestData,
+    multipart: Multipart,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let (options, file) = read_upload(&req_data, multipart).await?;
+    let result = into_api_err(
+        import_service::parse_measurements(&options, &file),
+        StatusCode::BAD_REQUEST,
+        &req_data,
+    )?;
+    let mut entries = result.entries;
+    let flagged = into_api_err(
+        validation_service::flag_entries(&pool, &mut entries).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let since = entries.iter().map(|e| e.timestamp).min();
+    // readings past the raw retention only live on in the rollups, compacting first leaves
+    // just the imported ones there to be merged into them
+    let policy = into_api_err(
+        req_data.conn.get_retention_policy().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let now = Utc::now().timestamp() as u64;
+    into_api_err(
+        req_data.conn.compact_measurements(policy, now).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let mut stored = 0;
+    for batch in entries.chunks(BATCH_SIZE) {
+        stored += into_api_err(
+            req_data.conn.create_measurement_batch(batch.to_vec()).await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &req_data,
+        )?;
+    }
+    // historical readings are older than anything the periodic compaction rolls up again
+    if let Some(since) = since {
+        into_api_err(
+            req_data.conn.import_rollups(since, policy, now).await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &req_data,
+        )?;
+    }
+    tracing::info!(
+        "Imported {} readings of {} from {} rows, rejected {}, flagged {}",
+        stored,
+        options.host,
+        result.rows,
+        result.rejected.len(),
+        flagged
+    );
+
+    let html = format!(
+        "{}\n{}",
+        ImportResultTemplate {
+            info: options.info,
+            rows: result.rows,
+            preview: result.preview,
+            rejected_count: result.rejected.len(),
+            rejected: result.rejected.into_iter().take(REJECTED_SHOWN).collect(),
+            stored: Some(stored),
+            units: req_data.units(),
+        }
+        .render()
+        .unwrap(),
+        AlertTemplate {
+            alert_message: Some(match flagged {
+                0 => format!("Imported {} readings!", stored),
+                _ => format!("Imported {} readings, {} of them flagged!", stored, flagged),
+            }),
+            alert_type: Some(StatusCode::OK.into()),
+            swap_oob: true,
+        }
+        .render()
+        .unwrap()
+    );
+
+    Ok(Html(html))
+}
+
+/// Reads the import options and the uploaded file from the form.
+async fn read_upload(
+    req_data: &RequestData,
+    mut multipart: Multipart,
+) -> Result<(ImportOptions, Vec<u8>), ApiErrorResponse> {
+    let mut fields = HashMap::new();
+    let mut file = None;
+    loop {
+        let field = into_api_err(
+            multipart.next_field().await,
+            StatusCode::BAD_REQUEST,
+            req_data,
+        )?;
+        let Some(field) = field else {
+            break;
+        };
+        let name = field.name().unwrap_or_default().to_string();
+        match name.as_str() {
+            "file" => {
+                file = Some(into_api_err(
+                    field.bytes().await,
+                    StatusCode::BAD_REQUEST,
+                    req_data,
+                )?)
+            }
+            _ => {
+                let value = into_api_err(field.text().await, StatusCode::BAD_REQUEST, req_data)?;
+                fields.insert(name, value);
+            }
+        }
+    }
+    let Some(file) = file.filter(|f| !f.is_empty()) else {
+        return api_err("Choose a file to import", StatusCode::BAD_REQUEST, req_data);
+    };
+    let form = into_api_err(
+        ImportFormData::deserialize(MapDeserializer::<_, serde::de::value::Error>::new(
+            fields.into_iter(),
+        )),
+        StatusCode::BAD_REQUEST,
+        req_data,
+    )?;
+    let options: ImportOptions = into_api_err(form.try_into(), StatusCode::BAD_REQUEST, req_data)?;
+    let sensor = into_api_err(
+        req_data
+            .conn
+            .get_sensor(&options.host)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e)),
+        StatusCode::INTERNAL_SERVER_ERROR,
+        req_data,
+    )?;
+    if !sensor.is_some_and(|s| s.features.contains(options.info.feature)) {
+        return api_err(
+            format!(
+                "Sensor {} doesn't measure {}",
+                options.host, options.info.label
+            ),
+            StatusCode::BAD_REQUEST,
+            req_data,
+        );
+    }
+
+    Ok((options, file.to_vec()))
+}
@@ -6,7 +6,12 @@ use askama::Template;
 use axum::response::Html;
 
 pub mod browse_data;
+pub mod export;
+pub mod gaps;
+pub mod import;
+pub mod retention;
 pub mod schedule;
+pub mod validation;
 
 #[derive(Template)]
 #[template(path = "pages/data.html")]
@@ -0,0 +1,107 @@
+use crate::{
+    api_error::{into_api_err, ApiErrorResponse},
+    database::measurement_rollups::MeasurementRollupDatabase,
+    models::{
+        db::{Resolution, RetentionPolicy},
+        json::RetentionFormData,
+        RequestData, User,
+    },
+    website::components::alert::AlertTemplate,
+};
+use askama::Template;
+use axum::{response::Html, Form};
+use reqwest::StatusCode;
+
+#[derive(Template)]
+#[template(path = "pages/data-retention.html")]
+pub struct DataRetentionTemplate {
+    pub current_user: Option<User>,
+    pub policy: RetentionPolicy,
+    pub counts: Vec<(Resolution, u64)>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/data-retention-inner.html")]
+pub struct DataRetentionInnerTemplate {
+    pub policy: RetentionPolicy,
+    pub counts: Vec<(Resolution, u64)>,
+}
+
+#[derive(Template)]
+#[template(path = "components/retention-policy.html")]
+pub struct RetentionPolicyTemplate {
+    pub policy: RetentionPolicy,
+    pub counts: Vec<(Resolution, u64)>,
+}
+
+async fn load(
+    req_data: &RequestData,
+) -> Result<(RetentionPolicy, Vec<(Resolution, u64)>), ApiErrorResponse> {
+    let policy = into_api_err(
+        req_data.conn.get_retention_policy().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        req_data,
+    )?;
+    let mut counts = Vec::new();
+    for resolution in [Resolution::Raw, Resolution::Hourly, Resolution::Daily] {
+        let count = into_api_err(
+            req_data.conn.count_measurements(resolution).await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            req_data,
+        )?;
+        counts.push((resolution, count));
+    }
+    Ok((policy, counts))
+}
+
+pub async fn data_retention(req_data: RequestData) -> Result<Html<String>, ApiErrorResponse> {
+    let (policy, counts) = load(&req_data).await?;
+
+    if req_data.is_hx_request {
+        return Ok(Html(
+            DataRetentionInnerTemplate { policy, counts }
+                .render()
+                .unwrap(),
+        ));
+    }
+
+    Ok(Html(
+        DataRetentionTemplate {
+            current_user: req_data.user,
+            policy,
+            counts,
+        }
+        .render()
+        .unwrap(),
+    ))
+}
+
+pub async fn update_retention(
+    req_data: RequestData,
+    Form(retention_form): Form<RetentionFormData>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let policy: RetentionPolicy = into_api_err(
+        retention_form.try_into(),
+        StatusCode::BAD_REQUEST,
+        &req_data,
+    )?;
+    into_api_err(
+        req_data.conn.set_retention_policy(policy).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let (policy, counts) = load(&req_data).await?;
+    let html = format!(
+        "{}\n{}",
+        RetentionPolicyTemplate { policy, counts }.render().unwrap(),
+        AlertTemplate {
+            alert_message: Some("Retention policy updated!".to_string()),
+            alert_type: Some(StatusCode::OK.into()),
+            swap_oob: true,
+        }
+        .render()
+        .unwrap()
+    );
+
+    Ok(Html(html))
+}
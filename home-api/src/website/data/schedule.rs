@@ -1,13 +1,20 @@
 use crate::{
     api_error::into_api_err,
     api_error::ApiErrorResponse,
-    database::data_schedule::DataScheduleDatabase,
+    database::{
+        areas::AreaDatabase, collection_runs::CollectionRunDatabase,
+        data_schedule::DataScheduleDatabase, sensors::SensorDatabase,
+    },
     models::{
-        db::{DataScheduleEntry, SensorFeatures},
-        json::ScheduleEntryFormData,
+        db::{
+            AreaEntity, CollectionRun, DataScheduleEntry, ScheduleTarget, ScheduleTrigger,
+            SensorEntity, SensorFeatures,
+        },
+        json::{CollectFormData, ScheduleEntryFormData},
         RequestData, User,
     },
     services::sensor_data_service::SensorDataService,
+    website::components::alert::{AlertTemplate, AlertType},
 };
 use askama::Template;
 use axum::{extract::Query, response::Html, Extension, Form};
@@ -19,40 +26,151 @@ use tokio::sync::Mutex;
 #[template(path = "pages/data-schedule.html")]
 pub struct DataScheduleTemplate {
     pub current_user: Option<User>,
-    pub schedule: Vec<DataScheduleEntry>,
+    pub page: SchedulePage,
 }
 
 #[derive(Template)]
 #[template(path = "pages/data-schedule-inner.html")]
 pub struct DataScheduleInnerTemplate {
-    pub schedule: Vec<DataScheduleEntry>,
+    pub page: SchedulePage,
+}
+
+/// A schedule entry with the sensors (and their features) it is the effective rule for.
+pub struct ScheduleRule {
+    pub entry: DataScheduleEntry,
+    pub applied: Vec<(SensorEntity, SensorFeatures)>,
+    pub upcoming: Vec<i64>,
+    pub last_run: Option<CollectionRun>,
+}
+
+/// Number of recent runs listed on the schedule page.
+const RECENT_RUNS: usize = 20;
+
+#[derive(Template)]
+#[template(path = "components/collection-runs.html")]
+pub struct CollectionRunsTemplate {
+    pub runs: Vec<CollectionRun>,
+    pub swap_oob: bool,
+}
+
+/// Number of next runs previewed for each rule.
+const PREVIEW_RUNS: usize = 5;
+
+impl ScheduleRule {
+    pub fn target_name(&self, page: &SchedulePage) -> String {
+        match &self.entry.target {
+            ScheduleTarget::All => "All sensors".to_string(),
+            ScheduleTarget::Area(id) => page
+                .areas
+                .iter()
+                .find(|a| a.id == *id)
+                .map_or(format!("Area {}", id), |a| format!("Area {}", a.name)),
+            ScheduleTarget::Sensor(host) => page
+                .sensors
+                .iter()
+                .find(|s| s.host == *host)
+                .map_or(format!("Sensor {}", host), |s| format!("Sensor {}", s.name)),
+        }
+    }
+}
+
+pub struct SchedulePage {
+    pub running: bool,
+    pub rules: Vec<ScheduleRule>,
+    pub runs: CollectionRunsTemplate,
+    pub sensors: Vec<SensorEntity>,
+    pub areas: Vec<AreaEntity>,
+}
+
+impl SchedulePage {
+    async fn load(
+        req_data: &RequestData,
+        data_service: &Mutex<SensorDataService>,
+    ) -> Result<Self, ApiErrorResponse> {
+        let schedule = into_api_err(
+            req_data.conn.get_schedule().await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            req_data,
+        )?;
+        let sensors = into_api_err(
+            req_data
+                .conn
+                .get_sensors()
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e)),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            req_data,
+        )?;
+        let areas = into_api_err(
+            req_data.conn.get_area_entities().await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            req_data,
+        )?;
+        let last_runs = into_api_err(
+            req_data.conn.get_last_scheduled_runs().await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            req_data,
+        )?;
+        let runs = into_api_err(
+            req_data.conn.get_collection_runs(RECENT_RUNS).await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            req_data,
+        )?;
+        let rules = schedule
+            .iter()
+            .map(|entry| {
+                let upcoming = entry.upcoming(PREVIEW_RUNS);
+                // the sensors the rule collects from on its next run
+                let applied = match upcoming.first() {
+                    Some(next) => sensors
+                        .iter()
+                        .map(|s| (s.clone(), entry.effective_features(&schedule, s, next)))
+                        .filter(|(_, features)| !features.is_empty())
+                        .collect(),
+                    None => vec![],
+                };
+                ScheduleRule {
+                    entry: entry.clone(),
+                    applied,
+                    upcoming: upcoming.iter().map(|t| t.timestamp()).collect(),
+                    last_run: last_runs
+                        .iter()
+                        .find(|r| r.schedule_id == Some(entry.id))
+                        .cloned(),
+                }
+            })
+            .collect();
+        Ok(Self {
+            running: data_service.lock().await.is_running(),
+            rules,
+            runs: CollectionRunsTemplate {
+                runs,
+                swap_oob: false,
+            },
+            sensors,
+            areas,
+        })
+    }
 }
 
 pub fn delete_query(entry: &DataScheduleEntry) -> String {
-    format!(
-        "features={}&interval_ms={}",
-        entry.features.bits(),
-        entry.interval_ms
-    )
+    format!("id={}", entry.id)
 }
 
-pub async fn data_schedule(req_data: RequestData) -> Result<Html<String>, ApiErrorResponse> {
-    let schedule = into_api_err(
-        req_data.conn.get_schedule().await,
-        StatusCode::INTERNAL_SERVER_ERROR,
-        &req_data,
-    )?;
+pub async fn data_schedule(
+    req_data: RequestData,
+    Extension(data_service): Extension<Arc<Mutex<SensorDataService>>>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let page = SchedulePage::load(&req_data, &data_service).await?;
 
     if req_data.is_hx_request {
-        return Ok(Html(
-            DataScheduleInnerTemplate { schedule }.render().unwrap(),
-        ));
+        return Ok(Html(DataScheduleInnerTemplate { page }.render().unwrap()));
     }
 
     Ok(Html(
         DataScheduleTemplate {
             current_user: req_data.user,
-            schedule,
+            page,
         }
         .render()
         .unwrap(),
@@ -77,15 +195,9 @@ pub async fn create_schedule_entry(
     if new_entry.is_some() {
         _ = data_service.lock().await.restart().await;
     }
-    let schedule = into_api_err(
-        req_data.conn.get_schedule().await,
-        StatusCode::INTERNAL_SERVER_ERROR,
-        &req_data,
-    )?;
+    let page = SchedulePage::load(&req_data, &data_service).await?;
 
-    Ok(Html(
-        DataScheduleInnerTemplate { schedule }.render().unwrap(),
-    ))
+    Ok(Html(DataScheduleInnerTemplate { page }.render().unwrap()))
 }
 
 pub async fn delete_schedule_entry(
@@ -93,27 +205,16 @@ pub async fn delete_schedule_entry(
     Query(query): Query<BTreeMap<String, String>>,
     Extension(data_service): Extension<Arc<Mutex<SensorDataService>>>,
 ) -> Result<Html<String>, ApiErrorResponse> {
-    let entry = into_api_err(
+    let id = into_api_err(
         query
-            .get("features")
-            .ok_or(anyhow::anyhow!("Missing `features` field"))
-            .and_then(|f| Ok(f.parse()?))
-            .map(SensorFeatures::from_bits_retain)
-            .and_then(|f| {
-                query
-                    .get("interval_ms")
-                    .ok_or(anyhow::anyhow!("Missing `interval_ms` field"))
-                    .and_then(|i| Ok((f, i.parse()?)))
-                    .map(|(f, i)| DataScheduleEntry {
-                        features: f,
-                        interval_ms: i,
-                    })
-            }),
+            .get("id")
+            .ok_or(anyhow::anyhow!("Missing `id` field"))
+            .and_then(|id| Ok(id.parse::<i64>()?)),
         StatusCode::BAD_REQUEST,
         &req_data,
     )?;
     let success = into_api_err(
-        req_data.conn.delete_entry(entry).await,
+        req_data.conn.delete_entry(id).await,
         StatusCode::INTERNAL_SERVER_ERROR,
         &req_data,
     )?;
@@ -122,13 +223,50 @@ pub async fn delete_schedule_entry(
         _ = data_service.lock().await.restart().await;
     }
 
-    let schedule = into_api_err(
-        req_data.conn.get_schedule().await,
+    let page = SchedulePage::load(&req_data, &data_service).await?;
+
+    Ok(Html(DataScheduleInnerTemplate { page }.render().unwrap()))
+}
+
+pub async fn collect_now(
+    req_data: RequestData,
+    Extension(data_service): Extension<Arc<Mutex<SensorDataService>>>,
+    Form(collect): Form<CollectFormData>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let (features, target) = into_api_err(collect.try_into(), StatusCode::BAD_REQUEST, &req_data)?;
+    let collect = data_service.lock().await.collect_now(features, target);
+    let run = into_api_err(collect.await, StatusCode::INTERNAL_SERVER_ERROR, &req_data)?;
+    let alert = AlertTemplate {
+        alert_message: Some(match run.errors.is_empty() {
+            true => format!("Collected {} rows from {} sensors.", run.rows, run.sensors),
+            false => format!(
+                "Collected {} rows from {} sensors, {} failed: {}",
+                run.rows,
+                run.sensors,
+                run.errors.len(),
+                run.errors.join(", ")
+            ),
+        }),
+        alert_type: Some(match run.errors.is_empty() {
+            true => StatusCode::OK.into(),
+            false => AlertType::Warning,
+        }),
+        swap_oob: true,
+    };
+    let runs = into_api_err(
+        req_data.conn.get_collection_runs(RECENT_RUNS).await,
         StatusCode::INTERNAL_SERVER_ERROR,
         &req_data,
     )?;
 
-    Ok(Html(
-        DataScheduleInnerTemplate { schedule }.render().unwrap(),
-    ))
+    Ok(Html(format!(
+        "{}\n{}",
+        alert.render().unwrap(),
+        CollectionRunsTemplate {
+            runs,
+            swap_oob: true,
+        }
+        .render()
+        .unwrap()
+    )))
 }
@@ -0,0 +1,147 @@
+use crate::{
+    api_error::{into_api_err, ApiErrorResponse},
+    database::measurement_validation::MeasurementValidationDatabase,
+    features::{self, FeatureInfo, Metric, Unit},
+    models::{json::ValidationFormData, RequestData, User},
+    website::components::alert::AlertTemplate,
+};
+use askama::Template;
+use axum::{response::Html, Form};
+use reqwest::StatusCode;
+
+#[derive(Template)]
+#[template(path = "pages/data-validation.html")]
+pub struct DataValidationTemplate {
+    pub current_user: Option<User>,
+    pub rules: Vec<ValidationRow>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/data-validation-inner.html")]
+pub struct DataValidationInnerTemplate {
+    pub rules: Vec<ValidationRow>,
+}
+
+#[derive(Template)]
+#[template(path = "components/validation-rules.html")]
+pub struct ValidationRulesTemplate {
+    pub rules: Vec<ValidationRow>,
+}
+
+/// Validation rule of a metric, in the units the user sees, empty limits are not checked.
+pub struct ValidationRow {
+    pub info: &'static FeatureInfo,
+    pub metric: &'static Metric,
+    pub unit: &'static Unit,
+    pub min: String,
+    pub max: String,
+    pub rate: String,
+    pub window: String,
+    pub deviation: String,
+    /// Stored readings flagged on collection.
+    pub flagged: u64,
+}
+
+impl ValidationRow {
+    /// Name of the form field of the `kind` of limit.
+    pub fn field(&self, kind: &str) -> String {
+        format!("{}-{}-{}", kind, self.info.key, self.metric.name)
+    }
+}
+
+/// Validation rule of every measured metric, unchecked metrics included.
+async fn load(req_data: &RequestData) -> Result<Vec<ValidationRow>, ApiErrorResponse> {
+    let rules = into_api_err(
+        req_data.conn.get_validation_rules().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        req_data,
+    )?;
+    let units = req_data.units();
+    let show = |value: Option<f64>| {
+        value.map_or(String::new(), |v| ((v * 100.0).round() / 100.0).to_string())
+    };
+    let mut rows = vec![];
+    for info in features::measurements() {
+        for metric in info.metrics {
+            let rule = rules
+                .iter()
+                .find(|r| r.feature == info.feature && r.metric == metric.name);
+            let unit = units.unit(metric);
+            let flagged = into_api_err(
+                req_data.conn.count_flagged(info.feature, metric.name).await,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                req_data,
+            )?;
+            rows.push(ValidationRow {
+                info,
+                metric,
+                unit,
+                min: show(rule.and_then(|r| r.min).map(unit.from_base)),
+                max: show(rule.and_then(|r| r.max).map(unit.from_base)),
+                rate: show(
+                    rule.and_then(|r| r.max_rate)
+                        .map(|v| unit.difference_from_base(v)),
+                ),
+                window: rule
+                    .map(|r| r.median_window)
+                    .filter(|w| *w > 0)
+                    .map_or(String::new(), |w| w.to_string()),
+                deviation: show(
+                    rule.and_then(|r| r.max_deviation)
+                        .map(|v| unit.difference_from_base(v)),
+                ),
+                flagged,
+            });
+        }
+    }
+    Ok(rows)
+}
+
+pub async fn data_validation(req_data: RequestData) -> Result<Html<String>, ApiErrorResponse> {
+    let rules = load(&req_data).await?;
+
+    if req_data.is_hx_request {
+        return Ok(Html(
+            DataValidationInnerTemplate { rules }.render().unwrap(),
+        ));
+    }
+
+    Ok(Html(
+        DataValidationTemplate {
+            current_user: req_data.user,
+            rules,
+        }
+        .render()
+        .unwrap(),
+    ))
+}
+
+pub async fn update_validation(
+    req_data: RequestData,
+    Form(validation_form): Form<ValidationFormData>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let rules = into_api_err(
+        validation_form.rules(req_data.units()),
+        StatusCode::BAD_REQUEST,
+        &req_data,
+    )?;
+    into_api_err(
+        req_data.conn.set_validation_rules(rules).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let rules = load(&req_data).await?;
+    let html = format!(
+        "{}\n{}",
+        ValidationRulesTemplate { rules }.render().unwrap(),
+        AlertTemplate {
+            alert_message: Some("Validation rules updated!".to_string()),
+            alert_type: Some(StatusCode::OK.into()),
+            swap_oob: true,
+        }
+        .render()
+        .unwrap()
+    );
+
+    Ok(Html(html))
+}
@@ -1,24 +1,39 @@
 use crate::{
     api_error::into_api_err,
     api_error::ApiErrorResponse,
-    database::{areas::AreaDatabase, temp_data::TempDataDatabase},
-    models::{Area, RequestData, User},
+    database::{
+        areas::AreaDatabase, measurements::MeasurementDatabase, motion_data::MotionDataDatabase,
+        scan_history::ScanHistoryDatabase, sensor_status::SensorStatusDatabase,
+        sensors::SensorDatabase,
+    },
+    features::{self, Metric, Unit},
+    models::{
+        db::{ScanChange, SensorEntity, SensorFeatures, SensorStatus},
+        Area, RequestData, User, OCCUPANCY_TIMEOUT_SECS,
+    },
 };
 use askama::Template;
 use axum::response::Html;
 use reqwest::StatusCode;
 
+/// Latest value of each metric measured in an area, in the unit the user sees it in.
+pub type Readings = Vec<(&'static Metric, &'static Unit, f64)>;
+
 #[derive(Template)]
 #[template(path = "pages/home.html")]
 pub struct HomeTemplate {
     pub current_user: Option<User>,
-    pub areas: Vec<(Area, f32, f32)>,
+    pub areas: Vec<(Area, Readings, Option<bool>)>,
+    pub scan_changes: Vec<ScanChange>,
+    pub sensors: Vec<(SensorEntity, Option<SensorStatus>)>,
 }
 
 #[derive(Template)]
 #[template(path = "pages/home-inner.html")]
 pub struct HomeInnerTemplate {
-    pub areas: Vec<(Area, f32, f32)>,
+    pub areas: Vec<(Area, Readings, Option<bool>)>,
+    pub scan_changes: Vec<ScanChange>,
+    pub sensors: Vec<(SensorEntity, Option<SensorStatus>)>,
 }
 
 pub async fn home(req_data: RequestData) -> Result<Html<String>, ApiErrorResponse> {
@@ -27,31 +42,94 @@ pub async fn home(req_data: RequestData) -> Result<Html<String>, ApiErrorRespons
         StatusCode::INTERNAL_SERVER_ERROR,
         &req_data,
     )?;
+    let now = chrono::Utc::now().timestamp();
+    let motion = into_api_err(
+        req_data
+            .conn
+            .get_recent_motion_data(now - OCCUPANCY_TIMEOUT_SECS)
+            .await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let units = req_data.units();
     let mut areas_full = vec![];
     for area in areas {
-        let (temp, hum) = into_api_err(
-            req_data
-                .conn
-                .get_temp_data(
-                    Some(area.sensors.iter().map(|s| s.host.clone()).collect()),
-                    Some(1),
-                    None,
-                    None,
-                )
-                .await,
-            StatusCode::INTERNAL_SERVER_ERROR,
-            &req_data,
-        )?
-        .first()
-        .map(|t| (t.temperature, t.humidity))
-        .unwrap_or_default();
+        let mut readings = vec![];
+        for info in features::measurements() {
+            let latest = into_api_err(
+                req_data
+                    .conn
+                    .get_measurements(
+                        info,
+                        Some(area.sensors.iter().map(|s| s.host.clone()).collect()),
+                        Some(1),
+                        None,
+                        None,
+                    )
+                    .await,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &req_data,
+            )?;
+            if let Some(row) = latest.first() {
+                let shown = |metric: &'static Metric, value: f64| {
+                    (metric, units.unit(metric), units.value(metric, value))
+                };
+                readings.extend(
+                    info.metrics
+                        .iter()
+                        .zip(row.values.iter())
+                        .filter_map(|(metric, value)| value.map(|v| shown(metric, v))),
+                );
+                readings.extend(
+                    info.derived
+                        .iter()
+                        .filter_map(|d| d.value(&row.values).map(|v| shown(&d.metric, v))),
+                );
+            }
+        }
+
+        // only areas with a motion sensor can tell their occupancy
+        let occupied = area
+            .sensors
+            .iter()
+            .any(|s| s.features.contains(SensorFeatures::MOTION))
+            .then(|| area.occupied(&motion, now));
 
-        areas_full.push((area, temp, hum));
+        areas_full.push((area, readings, occupied));
     }
 
+    let scan_changes = into_api_err(
+        req_data.conn.get_unseen_changes().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+
+    let statuses = into_api_err(
+        req_data.conn.get_sensor_statuses().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let sensors = into_api_err(
+        req_data.conn.get_sensors().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?
+    .into_iter()
+    .map(|sensor| {
+        let status = statuses.iter().find(|s| s.host == sensor.host).cloned();
+        (sensor, status)
+    })
+    .collect();
+
     if req_data.is_hx_request {
         return Ok(Html(
-            HomeInnerTemplate { areas: areas_full }.render().unwrap(),
+            HomeInnerTemplate {
+                areas: areas_full,
+                scan_changes,
+                sensors,
+            }
+            .render()
+            .unwrap(),
         ));
     }
 
@@ -59,6 +137,8 @@ pub async fn home(req_data: RequestData) -> Result<Html<String>, ApiErrorRespons
         HomeTemplate {
             current_user: req_data.user,
             areas: areas_full,
+            scan_changes,
+            sensors,
         }
         .render()
         .unwrap(),
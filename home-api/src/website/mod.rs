@@ -5,6 +5,7 @@ use crate::{
 use askama::Template;
 use axum::{http::StatusCode, response::Html};
 
+pub mod alerts;
 pub mod areas;
 pub mod components;
 pub mod data;
@@ -1,29 +1,37 @@
-use super::sensors::{SensorActions, SensorTemplate};
+use super::{
+    components::alert::AlertTemplate,
+    sensors::{SensorActions, SensorTemplate},
+};
 use crate::{
     api_error::into_api_err,
     api_error::ApiErrorResponse,
-    database::{sensors::SensorDatabase, DbPool},
+    database::{scan_history::ScanHistoryDatabase, sensors::SensorDatabase, DbPool},
     models::{
-        db::{SensorEntity, SensorFeatures},
+        db::{ScanChange, ScanHistoryEntry, SensorEntity},
+        host_from_id,
+        json::{ScanFormData, ScanScheduleFormData},
         RequestData, User,
     },
     services::{
-        scanner_service::{ScannerService, ScannerState},
-        sensor_service::SensorService,
+        scanner_service::{
+            interfaces, ScanEvent, ScanInterface, ScanOptions, ScanProgress, ScannerService,
+            ScannerState,
+        },
+        sensor_service::{sensor_client, SensorService},
     },
 };
 use askama::Template;
 use axum::{
     extract::{
-        ws::{Message, WebSocket},
+        ws::{close_code, CloseFrame, Message, WebSocket},
         ConnectInfo, Path, State, WebSocketUpgrade,
     },
     response::{Html, IntoResponse},
-    Extension,
+    Extension, Form,
 };
-use reqwest::{Client, StatusCode};
+use reqwest::StatusCode;
 use std::{net::SocketAddr, sync::Arc, time::Duration};
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast::error::RecvError, Mutex};
 
 #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
 #[derive(Template)]
@@ -33,6 +41,10 @@ pub struct ScannerTemplate {
     pub state: ScannerState<SensorEntity>,
     pub action_type: SensorActions,
     pub sensors: Vec<SensorEntity>,
+    pub options: ScanOptions,
+    pub interfaces: Vec<ScanInterface>,
+    pub history: Vec<ScanHistoryEntry>,
+    pub interval: u64,
 }
 
 #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
@@ -42,6 +54,44 @@ pub struct ScannerWsTemplate {
     pub state: ScannerState<SensorEntity>,
     pub action_type: SensorActions,
     pub sensors: Vec<SensorEntity>,
+    pub options: ScanOptions,
+    pub interfaces: Vec<ScanInterface>,
+    pub history: Vec<ScanHistoryEntry>,
+    pub interval: u64,
+}
+
+#[derive(Template)]
+#[template(path = "components/scanner-progress.html")]
+pub struct ScannerProgressTemplate {
+    pub progress: ScanProgress<SensorEntity>,
+}
+
+#[derive(Template)]
+#[template(path = "components/scanner-sensors.html")]
+pub struct ScannerSensorsTemplate {
+    pub sensors: Vec<SensorEntity>,
+    pub action_type: SensorActions,
+    pub append: bool,
+}
+
+#[derive(Template)]
+#[template(path = "components/scanner-history.html")]
+pub struct ScannerHistoryTemplate {
+    pub history: Vec<ScanHistoryEntry>,
+    pub interval: u64,
+}
+
+#[derive(Template)]
+#[template(path = "components/scan-changes.html")]
+pub struct ScanChangesTemplate {
+    pub scan_changes: Vec<ScanChange>,
+}
+
+const HISTORY_LIMIT: usize = 10;
+
+/// Background scan interval in minutes, 0 when disabled.
+fn interval_minutes(scanner: &ScannerService<SensorEntity>) -> u64 {
+    scanner.interval.map_or(0, |i| i.as_secs() / 60)
 }
 
 #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
@@ -51,20 +101,38 @@ pub struct ScannerContentTemplate {
     pub state: ScannerState<SensorEntity>,
     pub action_type: SensorActions,
     pub sensors: Vec<SensorEntity>,
+    pub options: ScanOptions,
+    pub interfaces: Vec<ScanInterface>,
 }
 
 pub async fn scanner(
     req_data: RequestData,
     Extension(scanner): Extension<Arc<Mutex<ScannerService<SensorEntity>>>>,
 ) -> Result<Html<String>, ApiErrorResponse> {
-    let state = scanner.lock().await.state().await;
+    let (state, options, interval) = {
+        let mut scanner = scanner.lock().await;
+        (
+            scanner.state().await,
+            scanner.options.clone(),
+            interval_minutes(&scanner),
+        )
+    };
     let sensors = state.scanned();
+    let history = into_api_err(
+        req_data.conn.get_scans(HISTORY_LIMIT).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
     if req_data.is_hx_request {
         return Ok(Html(
             ScannerWsTemplate {
                 state,
                 action_type: SensorActions::Scanner,
                 sensors,
+                options,
+                interfaces: interfaces(),
+                history,
+                interval,
             }
             .render()
             .unwrap(),
@@ -77,6 +145,10 @@ pub async fn scanner(
             current_user: req_data.user,
             action_type: SensorActions::Scanner,
             sensors,
+            options,
+            interfaces: interfaces(),
+            history,
+            interval,
         }
         .render()
         .unwrap(),
@@ -84,52 +156,141 @@ pub async fn scanner(
 }
 
 pub async fn scan(
+    req_data: RequestData,
     Extension(scanner): Extension<Arc<Mutex<ScannerService<SensorEntity>>>>,
     State(pool): State<DbPool>,
-) -> Html<String> {
-    let state = scanner.lock().await.init(pool).await;
-    Html(
+    Form(scan_form): Form<ScanFormData>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let options: ScanOptions =
+        into_api_err(scan_form.try_into(), StatusCode::BAD_REQUEST, &req_data)?;
+    let mut scanner = scanner.lock().await;
+    let state = scanner.init(pool, options).await;
+    Ok(Html(
         ScannerContentTemplate {
             sensors: state.scanned(),
             state,
             action_type: SensorActions::Scanner,
+            options: scanner.options.clone(),
+            interfaces: interfaces(),
         }
         .render()
         .unwrap(),
-    )
+    ))
 }
 
 pub async fn cancel(
     Extension(scanner): Extension<Arc<Mutex<ScannerService<SensorEntity>>>>,
 ) -> Html<String> {
-    scanner.lock().await.cancel().await;
-    let state = scanner.lock().await.state().await;
+    let mut scanner = scanner.lock().await;
+    scanner.cancel().await;
+    let state = scanner.state().await;
     Html(
         ScannerContentTemplate {
             sensors: state.scanned(),
             state,
             action_type: SensorActions::Scanner,
+            options: scanner.options.clone(),
+            interfaces: interfaces(),
         }
         .render()
         .unwrap(),
     )
 }
 
+pub async fn update_schedule(
+    req_data: RequestData,
+    Extension(scanner): Extension<Arc<Mutex<ScannerService<SensorEntity>>>>,
+    State(pool): State<DbPool>,
+    Form(schedule_form): Form<ScanScheduleFormData>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let interval: Option<Duration> =
+        into_api_err(schedule_form.try_into(), StatusCode::BAD_REQUEST, &req_data)?;
+    into_api_err(
+        req_data
+            .conn
+            .set_scanner_interval(interval.map(|i| i.as_millis() as u64))
+            .await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    ScannerService::schedule(scanner.clone(), pool, interval).await;
+    let history = into_api_err(
+        req_data.conn.get_scans(HISTORY_LIMIT).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let html = format!(
+        "{}\n{}",
+        ScannerHistoryTemplate {
+            history,
+            interval: interval_minutes(&*scanner.lock().await),
+        }
+        .render()
+        .unwrap(),
+        AlertTemplate {
+            alert_message: Some("Scan schedule updated!".to_string()),
+            alert_type: Some(StatusCode::OK.into()),
+            swap_oob: true,
+        }
+        .render()
+        .unwrap()
+    );
+
+    Ok(Html(html))
+}
+
+pub async fn mark_changes_seen(req_data: RequestData) -> Result<Html<String>, ApiErrorResponse> {
+    into_api_err(
+        req_data.conn.mark_changes_seen().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    Ok(Html(
+        ScanChangesTemplate {
+            scan_changes: vec![],
+        }
+        .render()
+        .unwrap(),
+    ))
+}
+
 pub async fn pair_sensor(
     req_data: RequestData,
     Path(host): Path<String>,
 ) -> Result<Html<String>, ApiErrorResponse> {
-    let host = host.replace('-', ".");
+    let host = host_from_id(&host);
     let sensor = into_api_err(
-        Client::new().pair(&host).await,
+        sensor_client(&host).pair(&host).await,
         StatusCode::UNAUTHORIZED,
         &req_data,
     )?;
-    let sensor = into_api_err(
-        req_data.conn.create_sensor(sensor).await,
+    let existing = into_api_err(
+        req_data.conn.get_sensor(&host).await,
         StatusCode::INTERNAL_SERVER_ERROR,
         &req_data,
     )?;
+    // a known sensor that lost its key store only needs the new key
+    let sensor = match existing {
+        Some(existing) => {
+            into_api_err(
+                req_data
+                    .conn
+                    .update_pair_id(&host, sensor.pair_id.as_deref().unwrap_or_default())
+                    .await,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &req_data,
+            )?;
+            SensorEntity {
+                pair_id: sensor.pair_id,
+                ..existing
+            }
+        }
+        None => into_api_err(
+            req_data.conn.create_sensor(sensor).await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &req_data,
+        )?,
+    };
     Ok(Html(
         SensorTemplate {
             sensor,
@@ -149,43 +310,103 @@ pub async fn status_ws(
     ws.on_upgrade(move |socket| handle_status_socket(socket, addr, scanner.clone()))
 }
 
+/// Renders the update for `state`, the whole scanner content once the scan is over
+/// (which also drops the socket element) or just the progress and found sensors otherwise.
+/// Returns `true` when nothing else is left to send.
+fn render_state(state: ScannerState<SensorEntity>, options: ScanOptions) -> (String, bool) {
+    match state {
+        ScannerState::Scanning(progress) => (
+            format!(
+                "{}\n{}",
+                ScannerSensorsTemplate {
+                    sensors: progress.scanned.clone(),
+                    action_type: SensorActions::Scanner,
+                    append: false,
+                }
+                .render()
+                .unwrap(),
+                ScannerProgressTemplate { progress }.render().unwrap()
+            ),
+            false,
+        ),
+        state => (
+            ScannerContentTemplate {
+                sensors: state.scanned(),
+                state,
+                action_type: SensorActions::Scanner,
+                options,
+                interfaces: interfaces(),
+            }
+            .render()
+            .unwrap(),
+            true,
+        ),
+    }
+}
+
 async fn handle_status_socket(
     mut socket: WebSocket,
     _addr: SocketAddr,
     scanner: Arc<Mutex<ScannerService<SensorEntity>>>,
 ) {
-    // send a ping (unsupported by some browsers) just to kick things off and get a response
-    if socket.send(Message::Ping(vec![1, 2, 3])).await.is_err() {
-        // no Error here since the only thing we can do is to close the connection.
-        // If we can not send messages, there is no way to salvage the statemachine anyway.
-        return;
-    }
+    let (mut events, state, options) = {
+        let mut scanner = scanner.lock().await;
+        (
+            scanner.subscribe(),
+            scanner.state().await,
+            scanner.options.clone(),
+        )
+    };
 
-    let send_task = {
-        let scanner = scanner.clone();
-        tokio::spawn(async move {
-            let mut last_msg = None;
-            loop {
-                let state = scanner.lock().await.state().await;
-                let msg = Message::Text(
-                    ScannerContentTemplate {
-                        sensors: state.scanned(),
-                        state,
+    // catch up with whatever happened since the page was rendered
+    let mut update = Some(render_state(state, options));
+    loop {
+        if let Some((msg, done)) = update.take() {
+            if socket.send(Message::Text(msg)).await.is_err() {
+                return;
+            }
+            if done {
+                break;
+            }
+        }
+
+        update = tokio::select! {
+            event = events.recv() => match event {
+                Ok(ScanEvent::Found(sensor)) => Some((
+                    ScannerSensorsTemplate {
+                        sensors: vec![sensor],
                         action_type: SensorActions::Scanner,
+                        append: true,
                     }
                     .render()
                     .unwrap(),
-                );
-                if last_msg.as_ref() != Some(&msg) {
-                    last_msg = Some(msg.clone());
-                    if socket.send(msg.clone()).await.is_err() {
-                        break;
-                    }
+                    false,
+                )),
+                Ok(ScanEvent::Progress(progress)) => {
+                    Some((ScannerProgressTemplate { progress }.render().unwrap(), false))
                 }
-                tokio::time::sleep(Duration::from_millis(650)).await;
-            }
-        })
-    };
+                Ok(ScanEvent::Finished(state)) => {
+                    let options = scanner.lock().await.options.clone();
+                    Some(render_state(state, options))
+                }
+                Err(RecvError::Lagged(_)) => {
+                    let mut scanner = scanner.lock().await;
+                    Some(render_state(scanner.state().await, scanner.options.clone()))
+                }
+                Err(RecvError::Closed) => break,
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                _ => None,
+            },
+        };
+    }
 
-    send_task.await.unwrap();
+    socket
+        .send(Message::Close(Some(CloseFrame {
+            code: close_code::NORMAL,
+            reason: "Scan finished".into(),
+        })))
+        .await
+        .ok();
 }
@@ -1,19 +1,26 @@
-use super::components::alert::AlertTemplate;
+use super::components::alert::{AlertTemplate, AlertType};
 use crate::{
     api_error::api_err,
     api_error::into_api_err,
     api_error::ApiErrorResponse,
-    database::{areas::AreaDatabase, sensors::SensorDatabase},
+    database::{
+        areas::AreaDatabase, sensor_calibration::SensorCalibrationDatabase,
+        sensor_health::SensorHealthDatabase, sensor_status::SensorStatusDatabase,
+        sensors::SensorDatabase,
+    },
+    features::{self, FeatureInfo, Metric, Unit},
     models::{
-        db::{AreaEntity, SensorEntity, SensorFeatures},
-        json::SensorFormData,
+        db::{AreaEntity, SensorEntity, SensorFeatures, SensorHealthEntry, SensorStatus},
+        host_from_id,
+        json::{CalibrationFormData, IdentifyFormData, SensorFormData},
         RequestData, User,
     },
-    services::sensor_service::SensorService,
+    services::sensor_service::{sensor_client, SensorService, UnpairedError, INFO_TIMEOUT},
 };
 use askama::Template;
 use axum::{extract::Path, http::HeaderMap, response::Html, Form};
-use reqwest::{Client, StatusCode};
+use reqwest::StatusCode;
+use std::time::Duration;
 
 #[derive(Template)]
 #[template(path = "pages/sensors.html")]
@@ -22,6 +29,7 @@ pub struct SensorsTemplate {
     pub sensors: Vec<SensorEntity>,
     pub action_type: SensorActions,
     pub areas: Vec<AreaEntity>,
+    pub warnings: Vec<String>,
 }
 
 #[derive(Template)]
@@ -30,8 +38,65 @@ pub struct SensorsInnerTemplate {
     pub sensors: Vec<SensorEntity>,
     pub action_type: SensorActions,
     pub areas: Vec<AreaEntity>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/sensor-details.html")]
+pub struct SensorDetailsTemplate {
+    pub current_user: Option<User>,
+    pub sensor: SensorEntity,
+    pub latest: Option<SensorHealthEntry>,
+    pub reboots: Vec<i64>,
+    pub memory_labels: String,
+    pub memory_data: String,
+    pub calibration: Vec<CalibrationRow>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/sensor-details-inner.html")]
+pub struct SensorDetailsInnerTemplate {
+    pub sensor: SensorEntity,
+    pub latest: Option<SensorHealthEntry>,
+    pub reboots: Vec<i64>,
+    pub memory_labels: String,
+    pub memory_data: String,
+    pub calibration: Vec<CalibrationRow>,
+}
+
+#[derive(Template)]
+#[template(path = "components/sensor-calibration.html")]
+pub struct SensorCalibrationTemplate {
+    pub sensor: SensorEntity,
+    pub calibration: Vec<CalibrationRow>,
 }
 
+/// Calibration of a metric of a sensor, in the units the user sees.
+pub struct CalibrationRow {
+    pub info: &'static FeatureInfo,
+    pub metric: &'static Metric,
+    pub unit: &'static Unit,
+    pub offset: f64,
+    pub scale: f64,
+}
+
+impl CalibrationRow {
+    /// Name of the form field of the `kind` of correction.
+    pub fn field(&self, kind: &str) -> String {
+        format!("{}-{}-{}", kind, self.info.key, self.metric.name)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "components/sensor-status.html")]
+pub struct SensorStatusTemplate {
+    pub host: String,
+    pub status: Option<SensorStatus>,
+}
+
+/// Days of health history shown on the details page.
+const HEALTH_DAYS: u64 = 7;
+
 #[derive(Template)]
 #[template(path = "components/sensor.html")]
 pub struct SensorTemplate {
@@ -57,6 +122,38 @@ pub fn areas_empty() -> Vec<(AreaEntity, bool)> {
     Vec::new()
 }
 
+/// Calibration of every metric the sensor measures, uncalibrated metrics included.
+async fn calibration_rows(
+    req_data: &RequestData,
+    sensor: &SensorEntity,
+) -> Result<Vec<CalibrationRow>, ApiErrorResponse> {
+    let calibrations = into_api_err(
+        req_data.conn.get_calibrations(&sensor.host).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        req_data,
+    )?;
+    let units = req_data.units();
+    let mut rows = vec![];
+    for info in features::measurements().filter(|f| sensor.features.contains(f.feature)) {
+        for metric in info.metrics {
+            let calibration = calibrations
+                .iter()
+                .find(|c| c.feature == info.feature && c.metric == metric.name);
+            let unit = units.unit(metric);
+            rows.push(CalibrationRow {
+                info,
+                metric,
+                unit,
+                offset: calibration.map_or(0.0, |c| {
+                    (unit.difference_from_base(c.offset) * 1000.0).round() / 1000.0
+                }),
+                scale: calibration.map_or(1.0, |c| c.scale),
+            });
+        }
+    }
+    Ok(rows)
+}
+
 pub fn sensor_style(sensor: &SensorEntity) -> &'static str {
     if sensor.features.is_empty() {
         return "bg-neutral border-neutral-content text-neutral-content opacity-70";
@@ -65,6 +162,25 @@ pub fn sensor_style(sensor: &SensorEntity) -> &'static str {
     "bg-base-300 border-base-content text-base-content"
 }
 
+/// Warnings about the last known health of the sensors.
+pub fn health_warnings(sensors: &[SensorEntity], health: &[SensorHealthEntry]) -> Vec<String> {
+    health
+        .iter()
+        .filter(|h| h.pair_store_nearly_full())
+        .map(|h| {
+            let name = sensors
+                .iter()
+                .find(|s| s.host == h.host)
+                .map_or(h.host.as_str(), |s| s.name.as_str());
+            format!(
+                "Pair store of sensor \"{}\" is {:.0}% full, revoke unused keys.",
+                name,
+                h.pair_usage() * 100.0
+            )
+        })
+        .collect()
+}
+
 pub enum SensorActions {
     Overview,
     Scanner,
@@ -95,12 +211,19 @@ pub async fn sensors(req_data: RequestData) -> Result<Html<String>, ApiErrorResp
         StatusCode::INTERNAL_SERVER_ERROR,
         &req_data,
     )?;
+    let health = into_api_err(
+        req_data.conn.get_latest_health_all().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let warnings = health_warnings(&sensors, &health);
     match req_data.is_hx_request {
         true => Ok(Html(
             SensorsInnerTemplate {
                 sensors,
                 action_type: SensorActions::Overview,
                 areas,
+                warnings,
             }
             .render()
             .unwrap(),
@@ -111,6 +234,7 @@ pub async fn sensors(req_data: RequestData) -> Result<Html<String>, ApiErrorResp
                 current_user: req_data.user,
                 action_type: SensorActions::Overview,
                 areas,
+                warnings,
             }
             .render()
             .unwrap(),
@@ -118,12 +242,147 @@ pub async fn sensors(req_data: RequestData) -> Result<Html<String>, ApiErrorResp
     }
 }
 
+pub async fn sensor_details(
+    req_data: RequestData,
+    Path(host): Path<String>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let host = host_from_id(&host);
+    let sensor = into_api_err(
+        req_data.conn.get_sensor(&host).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let Some(sensor) = sensor else {
+        return api_err("Sensor not found", StatusCode::NOT_FOUND, &req_data);
+    };
+    let after = chrono::Utc::now()
+        .checked_sub_days(chrono::Days::new(HEALTH_DAYS))
+        .unwrap()
+        .timestamp();
+    let health = into_api_err(
+        req_data.conn.get_health(&host, after).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let latest = health.last().cloned();
+    let reboots = health
+        .iter()
+        .filter(|h| h.rebooted)
+        .map(|h| h.timestamp)
+        .collect();
+    let memory_labels = format!(
+        "[{}]",
+        health
+            .iter()
+            .map(|h| format!("new Date({} * 1000).toLocaleString()", h.timestamp))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    let memory_data = format!(
+        "[{}]",
+        health
+            .iter()
+            .map(|h| (h.free_mem / 1024).to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    let calibration = calibration_rows(&req_data, &sensor).await?;
+    if req_data.is_hx_request {
+        return Ok(Html(
+            SensorDetailsInnerTemplate {
+                sensor,
+                latest,
+                reboots,
+                memory_labels,
+                memory_data,
+                calibration,
+            }
+            .render()
+            .unwrap(),
+        ));
+    }
+
+    Ok(Html(
+        SensorDetailsTemplate {
+            current_user: req_data.user,
+            sensor,
+            latest,
+            reboots,
+            memory_labels,
+            memory_data,
+            calibration,
+        }
+        .render()
+        .unwrap(),
+    ))
+}
+
+/// Stores the calibration of the sensor, its readings themselves are kept as they were sent.
+pub async fn update_calibration(
+    req_data: RequestData,
+    Path(host): Path<String>,
+    Form(form): Form<CalibrationFormData>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let host = host_from_id(&host);
+    let sensor = into_api_err(
+        req_data.conn.get_sensor(&host).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let Some(sensor) = sensor else {
+        return api_err("Sensor not found", StatusCode::NOT_FOUND, &req_data);
+    };
+    let calibrations = into_api_err(
+        form.calibrations(&sensor, req_data.units()),
+        StatusCode::BAD_REQUEST,
+        &req_data,
+    )?;
+    into_api_err(
+        req_data.conn.set_calibrations(&host, calibrations).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let calibration = calibration_rows(&req_data, &sensor).await?;
+    let html = format!(
+        "{}\n{}",
+        SensorCalibrationTemplate {
+            sensor,
+            calibration
+        }
+        .render()
+        .unwrap(),
+        AlertTemplate {
+            alert_message: Some("Calibration updated!".to_string()),
+            alert_type: Some(StatusCode::OK.into()),
+            swap_oob: true,
+        }
+        .render()
+        .unwrap()
+    );
+
+    Ok(Html(html))
+}
+
+pub async fn sensor_status(
+    req_data: RequestData,
+    Path(host): Path<String>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let status = into_api_err(
+        req_data.conn.get_sensor_status(&host_from_id(&host)).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    Ok(Html(
+        SensorStatusTemplate { host, status }.render().unwrap(),
+    ))
+}
+
 pub async fn update_sensor(
     req_data: RequestData,
     Path(host): Path<String>,
     Form(sensor): Form<SensorFormData>,
 ) -> Result<Html<String>, ApiErrorResponse> {
-    let host = host.replace('-', ".");
+    let host = host_from_id(&host);
     let sensor_entity = into_api_err(
         req_data.conn.get_sensor(&host).await,
         StatusCode::INTERNAL_SERVER_ERROR,
@@ -136,7 +395,7 @@ pub async fn update_sensor(
         return api_err("Sensor not found", StatusCode::NOT_FOUND, &req_data);
     };
     let sensor_response = into_api_err(
-        Client::new()
+        sensor_client(&host)
             .update_sensor(&host, pair_id, sensor.clone())
             .await,
         StatusCode::INTERNAL_SERVER_ERROR,
@@ -195,15 +454,29 @@ pub async fn delete_sensor(
     req_data: RequestData,
     Path(host): Path<String>,
 ) -> Result<Html<String>, ApiErrorResponse> {
-    let host = host.replace('-', ".");
-    let affected = into_api_err(
-        req_data.conn.delete_sensor(&host).await,
+    let host = host_from_id(&host);
+    let sensor = into_api_err(
+        req_data.conn.get_sensor(&host).await,
         StatusCode::INTERNAL_SERVER_ERROR,
         &req_data,
     )?;
-    if affected == 0 {
+    let Some(sensor) = sensor else {
         return api_err("Sensor not found", StatusCode::NOT_FOUND, &req_data);
-    }
+    };
+    // an unreachable sensor should neither prevent nor hold up removing it
+    let unpair_result = match &sensor.pair_id {
+        Some(pair_id) => {
+            sensor_client(&host)
+                .unpair(&host, pair_id, INFO_TIMEOUT)
+                .await
+        }
+        None => Ok(()),
+    };
+    into_api_err(
+        req_data.conn.delete_sensor(&host).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
 
     let areas = into_api_err(
         req_data.conn.get_area_entities().await,
@@ -211,29 +484,130 @@ pub async fn delete_sensor(
         &req_data,
     )?;
 
-    Ok(Html(
+    let alert = match unpair_result {
+        Ok(_) => AlertTemplate {
+            alert_message: Some("Sensor deleted and unpaired!".to_string()),
+            alert_type: Some(StatusCode::OK.into()),
+            swap_oob: true,
+        },
+        Err(e) if e.is::<UnpairedError>() => AlertTemplate {
+            alert_message: Some("Sensor deleted, it was already unpaired.".to_string()),
+            alert_type: Some(StatusCode::OK.into()),
+            swap_oob: true,
+        },
+        Err(e) => AlertTemplate {
+            alert_message: Some(format!(
+                "Sensor deleted, but its pair key could not be revoked: {}",
+                e
+            )),
+            alert_type: Some(AlertType::Warning),
+            swap_oob: true,
+        },
+    };
+
+    let sensors = into_api_err(
+        req_data.conn.get_sensors().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let health = into_api_err(
+        req_data.conn.get_latest_health_all().await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let html = format!(
+        "{}\n{}",
         SensorsInnerTemplate {
-            sensors: into_api_err(
-                req_data.conn.get_sensors().await,
-                StatusCode::INTERNAL_SERVER_ERROR,
+            warnings: health_warnings(&sensors, &health),
+            sensors,
+            action_type: SensorActions::Overview,
+            areas,
+        }
+        .render()
+        .unwrap(),
+        alert.render().unwrap()
+    );
+
+    Ok(Html(html))
+}
+
+/// Rotates the pair key of a sensor, falling back to pairing from scratch
+/// (which needs the pairing button pressed) when the sensor lost the current key.
+pub async fn repair_sensor(
+    req_data: RequestData,
+    Path(host): Path<String>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let host = host_from_id(&host);
+    let sensor = into_api_err(
+        req_data.conn.get_sensor(&host).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let Some(mut sensor) = sensor else {
+        return api_err("Sensor not found", StatusCode::NOT_FOUND, &req_data);
+    };
+    let client = sensor_client(&host);
+    let rotated = match &sensor.pair_id {
+        Some(pair_id) => client.rotate_pair(&host, pair_id).await,
+        None => Err(UnpairedError.into()),
+    };
+    let pair_id = match rotated {
+        Ok(pair_id) => pair_id,
+        Err(e) if e.is::<UnpairedError>() => {
+            let paired = into_api_err(
+                client.pair(&host).await,
+                StatusCode::UNAUTHORIZED,
                 &req_data,
-            )?,
+            )?;
+            paired.pair_id.unwrap_or_default()
+        }
+        Err(e) => return api_err(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR, &req_data),
+    };
+    into_api_err(
+        req_data.conn.update_pair_id(&host, &pair_id).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    sensor.pair_id = Some(pair_id);
+    let areas = into_api_err(
+        req_data
+            .conn
+            .get_area_entities()
+            .await
+            .map(|a| areas(a.iter(), &sensor)),
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+
+    let html = format!(
+        "{}\n{}",
+        SensorTemplate {
+            sensor,
             action_type: SensorActions::Overview,
             areas,
         }
         .render()
         .unwrap(),
-    ))
+        AlertTemplate {
+            alert_message: Some("Sensor re-paired successfully!".to_string()),
+            alert_type: Some(StatusCode::OK.into()),
+            swap_oob: true,
+        }
+        .render()
+        .unwrap()
+    );
+
+    Ok(Html(html))
 }
 
 pub async fn sync_sensor(
     req_data: RequestData,
     Path(host): Path<String>,
 ) -> Result<Html<String>, ApiErrorResponse> {
-    let host = host.replace('-', ".");
+    let host = host_from_id(&host);
     let sensor = into_api_err(
-        Client::new()
-            .get_sensor(&host)
+        sensor_client(&host)
+            .get_sensor(&host, INFO_TIMEOUT)
             .await
             .and_then(|s| s.map_err(|e| anyhow::anyhow!("{}", e).into())),
         StatusCode::INTERNAL_SERVER_ERROR,
@@ -283,3 +657,40 @@ pub async fn sync_sensor(
 
     Ok(Html(html))
 }
+
+pub async fn identify_sensor(
+    req_data: RequestData,
+    Path(host): Path<String>,
+    Form(identify): Form<IdentifyFormData>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let host = host_from_id(&host);
+    let duration: Duration = into_api_err(identify.try_into(), StatusCode::BAD_REQUEST, &req_data)?;
+    let sensor = into_api_err(
+        req_data.conn.get_sensor(&host).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let Some(pair_id) = sensor.and_then(|s| s.pair_id) else {
+        return api_err("Sensor not found", StatusCode::NOT_FOUND, &req_data);
+    };
+    into_api_err(
+        sensor_client(&host)
+            .identify(&host, &pair_id, duration)
+            .await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+
+    Ok(Html(
+        AlertTemplate {
+            alert_message: Some(format!(
+                "Sensor LED is blinking for {} seconds.",
+                duration.as_secs()
+            )),
+            alert_type: Some(StatusCode::OK.into()),
+            swap_oob: true,
+        }
+        .render()
+        .unwrap(),
+    ))
+}
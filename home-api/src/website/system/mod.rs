@@ -1,30 +1,42 @@
 use crate::{
     api_error::ApiErrorResponse,
     models::{RequestData, User},
+    services::sensor_data_service::{CollectorHealth, SensorDataService},
 };
 use askama::Template;
-use axum::response::Html;
+use axum::{response::Html, Extension};
+use std::sync::Arc;
+use tokio::sync::Mutex;
 
+pub mod notifications;
 pub mod users;
 
 #[derive(Template)]
 #[template(path = "pages/system.html")]
 pub struct SystemTemplate {
     pub current_user: Option<User>,
+    pub collector: CollectorHealth,
 }
 
 #[derive(Template)]
 #[template(path = "pages/system-inner.html")]
-pub struct SystemInnerTemplate;
+pub struct SystemInnerTemplate {
+    pub collector: CollectorHealth,
+}
 
-pub async fn system(req_data: RequestData) -> Result<Html<String>, ApiErrorResponse> {
+pub async fn system(
+    req_data: RequestData,
+    Extension(data_service): Extension<Arc<Mutex<SensorDataService>>>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let collector = data_service.lock().await.health();
     if req_data.is_hx_request {
-        return Ok(Html(SystemInnerTemplate.render().unwrap()));
+        return Ok(Html(SystemInnerTemplate { collector }.render().unwrap()));
     }
 
     Ok(Html(
         SystemTemplate {
             current_user: req_data.user,
+            collector,
         }
         .render()
         .unwrap(),
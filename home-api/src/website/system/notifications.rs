@@ -0,0 +1,281 @@
+use crate::{
+    api_error::{into_api_err, ApiErrorResponse},
+    database::notifications::NotificationDatabase,
+    models::{
+        db::{
+            ChannelKind, Notification, NotificationChannel, NotificationDelivery,
+            NotificationSubscription, NotificationTopics,
+        },
+        json::{NotificationChannelFormData, NotificationSubscriptionFormData},
+        RequestData, User,
+    },
+    services::notification_service,
+    website::components::alert::{AlertTemplate, AlertType},
+};
+use askama::Template;
+use axum::{
+    extract::{Path, Query},
+    response::Html,
+    Form,
+};
+use reqwest::StatusCode;
+use std::collections::BTreeMap;
+
+/// Number of deliveries listed in the delivery log.
+const LOG_LIMIT: usize = 50;
+
+#[derive(Template)]
+#[template(path = "pages/notifications.html")]
+pub struct NotificationsTemplate {
+    pub current_user: Option<User>,
+    pub page: NotificationsPage,
+}
+
+#[derive(Template)]
+#[template(path = "pages/notifications-inner.html")]
+pub struct NotificationsInnerTemplate {
+    pub page: NotificationsPage,
+}
+
+pub struct NotificationsPage {
+    pub channels: Vec<NotificationChannel>,
+    /// Subscriptions of the current user.
+    pub subscriptions: Vec<NotificationSubscription>,
+    pub deliveries: Vec<NotificationDelivery>,
+}
+
+impl NotificationsPage {
+    async fn load(req_data: &RequestData) -> Result<Self, ApiErrorResponse> {
+        let channels = into_api_err(
+            req_data.conn.get_channels().await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            req_data,
+        )?;
+        let subscriptions = match &req_data.user {
+            Some(user) => into_api_err(
+                req_data.conn.get_subscriptions(Some(user.id)).await,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                req_data,
+            )?,
+            None => vec![],
+        };
+        let deliveries = into_api_err(
+            req_data.conn.get_deliveries(LOG_LIMIT).await,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            req_data,
+        )?;
+        Ok(Self {
+            channels,
+            subscriptions,
+            deliveries,
+        })
+    }
+
+    fn subscription(&self, channel: &NotificationChannel) -> Option<&NotificationSubscription> {
+        self.subscriptions
+            .iter()
+            .find(|s| s.channel_id == channel.id)
+    }
+
+    pub fn is_subscribed(&self, channel: &NotificationChannel, topic: &NotificationTopics) -> bool {
+        self.subscription(channel)
+            .is_some_and(|s| s.topics.contains(*topic))
+    }
+
+    pub fn address(&self, channel: &NotificationChannel) -> String {
+        self.subscription(channel)
+            .and_then(|s| s.address.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn channel_name(&self, delivery: &NotificationDelivery) -> String {
+        self.channels
+            .iter()
+            .find(|c| c.id == delivery.channel_id)
+            .map_or(format!("Channel #{}", delivery.channel_id), |c| {
+                c.name.clone()
+            })
+    }
+}
+
+async fn render_inner(req_data: &RequestData) -> Result<String, ApiErrorResponse> {
+    let page = NotificationsPage::load(req_data).await?;
+
+    Ok(NotificationsInnerTemplate { page }.render().unwrap())
+}
+
+async fn load_channel(
+    req_data: &RequestData,
+    id: i64,
+) -> Result<NotificationChannel, ApiErrorResponse> {
+    into_api_err(
+        req_data
+            .conn
+            .get_channel(id)
+            .await
+            .and_then(|c| c.ok_or(anyhow::anyhow!("Channel {} not found", id))),
+        StatusCode::NOT_FOUND,
+        req_data,
+    )
+}
+
+pub async fn notifications(req_data: RequestData) -> Result<Html<String>, ApiErrorResponse> {
+    let page = NotificationsPage::load(&req_data).await?;
+
+    if req_data.is_hx_request {
+        return Ok(Html(NotificationsInnerTemplate { page }.render().unwrap()));
+    }
+
+    Ok(Html(
+        NotificationsTemplate {
+            current_user: req_data.user,
+            page,
+        }
+        .render()
+        .unwrap(),
+    ))
+}
+
+pub async fn create_channel(
+    req_data: RequestData,
+    Form(channel): Form<NotificationChannelFormData>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let channel = into_api_err(channel.try_into(), StatusCode::BAD_REQUEST, &req_data)?;
+    into_api_err(
+        req_data.conn.create_channel(channel).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+
+    Ok(Html(render_inner(&req_data).await?))
+}
+
+pub async fn delete_channel(
+    req_data: RequestData,
+    Query(query): Query<BTreeMap<String, String>>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let id = into_api_err(
+        query
+            .get("id")
+            .ok_or(anyhow::anyhow!("Missing `id` field"))
+            .and_then(|id| Ok(id.parse::<i64>()?)),
+        StatusCode::BAD_REQUEST,
+        &req_data,
+    )?;
+    into_api_err(
+        req_data.conn.delete_channel(id).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+
+    Ok(Html(render_inner(&req_data).await?))
+}
+
+pub async fn update_subscription(
+    req_data: RequestData,
+    Path(id): Path<i64>,
+    Form(subscription): Form<NotificationSubscriptionFormData>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let channel = load_channel(&req_data, id).await?;
+    let user_id = into_api_err(
+        req_data
+            .user
+            .as_ref()
+            .map(|u| u.id)
+            .ok_or(anyhow::anyhow!("Not logged in")),
+        StatusCode::UNAUTHORIZED,
+        &req_data,
+    )?;
+    let subscription = into_api_err(
+        subscription.into_subscription(user_id, &channel),
+        StatusCode::BAD_REQUEST,
+        &req_data,
+    )?;
+    into_api_err(
+        req_data.conn.set_subscription(subscription).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+
+    let html = format!(
+        "{}\n{}",
+        render_inner(&req_data).await?,
+        AlertTemplate {
+            alert_message: Some(format!("Subscriptions of {} updated!", channel.name)),
+            alert_type: Some(StatusCode::OK.into()),
+            swap_oob: true,
+        }
+        .render()
+        .unwrap()
+    );
+
+    Ok(Html(html))
+}
+
+/// Sends a test notification through the channel right away, to the current user's address for mails.
+pub async fn test_channel(
+    req_data: RequestData,
+    Path(id): Path<i64>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let channel = load_channel(&req_data, id).await?;
+    let page = NotificationsPage::load(&req_data).await?;
+    let recipient = match channel.kind {
+        ChannelKind::Smtp => Some(into_api_err(
+            Some(page.address(&channel))
+                .filter(|a| !a.is_empty())
+                .ok_or(anyhow::anyhow!(
+                    "Set your mail address for this channel first"
+                )),
+            StatusCode::BAD_REQUEST,
+            &req_data,
+        )?),
+        _ => None,
+    };
+    let notification = Notification::new(
+        NotificationTopics::empty(),
+        "Test notification",
+        format!("Channel {} works", channel.name),
+    );
+    let delivery = into_api_err(
+        req_data
+            .conn
+            .create_delivery(NotificationDelivery {
+                channel_id: channel.id,
+                recipient,
+                topic: notification.topic,
+                title: notification.title.clone(),
+                body: channel.render(&notification),
+                created: notification.time,
+                ..Default::default()
+            })
+            .await
+            .and_then(|d| d.ok_or(anyhow::anyhow!("Failed to queue the test notification"))),
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let delivery = into_api_err(
+        notification_service::attempt(&req_data.conn, delivery).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+    let alert = match &delivery.error {
+        None => AlertTemplate {
+            alert_message: Some(format!("Test notification sent through {}!", channel.name)),
+            alert_type: Some(StatusCode::OK.into()),
+            swap_oob: true,
+        },
+        Some(error) => AlertTemplate {
+            alert_message: Some(format!("Test notification failed, will retry: {}", error)),
+            alert_type: Some(AlertType::Error),
+            swap_oob: true,
+        },
+    };
+
+    let html = format!(
+        "{}\n{}",
+        render_inner(&req_data).await?,
+        alert.render().unwrap()
+    );
+
+    Ok(Html(html))
+}
@@ -3,7 +3,9 @@ use crate::{
     api_error::into_api_err,
     api_error::ApiErrorResponse,
     database::users::UserDatabase,
+    features::UnitPreferences,
     models::{RequestData, User},
+    website::components::alert::AlertTemplate,
 };
 use askama::Template;
 use axum::{extract::Path, response::Html, Form};
@@ -31,6 +33,12 @@ pub struct UserRowTemplate {
     pub user: User,
 }
 
+#[derive(Template)]
+#[template(path = "components/unit-preferences.html")]
+pub struct UnitPreferencesTemplate {
+    pub current_user: Option<User>,
+}
+
 pub async fn users(req_data: RequestData) -> Result<Html<String>, ApiErrorResponse> {
     let users = into_api_err(
         req_data.conn.get_users().await,
@@ -211,3 +219,48 @@ pub async fn delete_user(
         .unwrap(),
     ))
 }
+
+#[derive(Deserialize)]
+pub struct UnitPreferencesForm {
+    temperature: String,
+}
+
+/// Stores the units the current user sees the readings in.
+pub async fn update_unit_preferences(
+    req_data: RequestData,
+    Form(form): Form<UnitPreferencesForm>,
+) -> Result<Html<String>, ApiErrorResponse> {
+    let Some(units) = UnitPreferences::new(&form.temperature) else {
+        return api_err(
+            format!("Unknown temperature unit `{}`", form.temperature),
+            StatusCode::BAD_REQUEST,
+            &req_data,
+        );
+    };
+    let Some(user) = req_data.user.clone() else {
+        return api_err("Not logged in", StatusCode::UNAUTHORIZED, &req_data);
+    };
+    into_api_err(
+        req_data.conn.set_unit_preferences(&user.name, units).await,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &req_data,
+    )?;
+
+    let html = format!(
+        "{}\n{}",
+        UnitPreferencesTemplate {
+            current_user: Some(User { units, ..user }),
+        }
+        .render()
+        .unwrap(),
+        AlertTemplate {
+            alert_message: Some("Display units updated!".to_string()),
+            alert_type: Some(StatusCode::OK.into()),
+            swap_oob: true,
+        }
+        .render()
+        .unwrap()
+    );
+
+    Ok(Html(html))
+}
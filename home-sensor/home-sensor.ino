@@ -9,14 +9,18 @@ WifiService *wifi_service = new WifiService(ssid, pass);
 PairingService *pairing_service = new PairingService(&prefs);
 SensorService *sensor_service = new SensorService(&prefs);
 DhtService *dht_service = new DhtService(&prefs);
+MotionService *motion_service = new MotionService();
 
 std::vector<Route *> all_routes = {
     new GetSensorRoute(sensor_service, pairing_service),
     new PostSensorRoute(sensor_service, pairing_service),
     new PairRoute(pairing_service),
     new PairConfirmRoute(pairing_service),
+    new PairRevokeRoute(pairing_service),
+    new PairRotateRoute(pairing_service),
     new DhtRoute(dht_service, pairing_service),
     new LedRoute(led_service, pairing_service),
+    new MotionRoute(motion_service, pairing_service),
 };
 
 ServerService *server_service = new ServerService(all_routes);
@@ -28,10 +32,16 @@ void setup()
   led_service->set(HIGH);
   wifi_service->init();
   dht_service->init();
+#if MOTION_ENABLED
+  motion_service->init();
+#endif
   configTime(0, 0, "pool.ntp.org", "time.nist.gov");
   server_service->init();
   pairing_service->init();
   sensor_service->init();
+#if MOTION_ENABLED
+  *sensor_service->store->features |= MOTION_FEATURE;
+#endif
   led_service->set(LOW);
 }
 
@@ -39,10 +49,15 @@ void loop()
 {
   ulong led_elapsed = led_service->handle();
   ulong dht_elapsed = dht_service->handle();
+#if MOTION_ENABLED
+  ulong motion_elapsed = motion_service->handle();
+#else
+  ulong motion_elapsed = 0;
+#endif
   ulong pairing_elapsed = pairing_service->handle();
   ulong wifi_elapsed = wifi_service->handle();
   ulong server_elapsed = server_service->handle();
-  printElapsed(led_elapsed, dht_elapsed, pairing_elapsed, wifi_elapsed, server_elapsed);
+  printElapsed(led_elapsed, dht_elapsed, motion_elapsed, pairing_elapsed, wifi_elapsed, server_elapsed);
   if (esp_get_free_heap_size() < 10000)
   {
     dht_service->save();
@@ -52,9 +67,9 @@ void loop()
   }
 }
 
-void printElapsed(ulong led_elapsed, ulong dht_elapsed, ulong pairing_elapsed, ulong wifi_elapsed, ulong server_elapsed)
+void printElapsed(ulong led_elapsed, ulong dht_elapsed, ulong motion_elapsed, ulong pairing_elapsed, ulong wifi_elapsed, ulong server_elapsed)
 {
-  ulong elapsed = led_elapsed + dht_elapsed + pairing_elapsed + wifi_elapsed + server_elapsed;
+  ulong elapsed = led_elapsed + dht_elapsed + motion_elapsed + pairing_elapsed + wifi_elapsed + server_elapsed;
   if (elapsed <= 10)
   {
     return;
@@ -64,6 +79,8 @@ void printElapsed(ulong led_elapsed, ulong dht_elapsed, ulong pairing_elapsed, u
   Serial.println(led_elapsed);
   Serial.print("DHT: ");
   Serial.println(dht_elapsed);
+  Serial.print("Motion: ");
+  Serial.println(motion_elapsed);
   Serial.print("Pairing: ");
   Serial.println(pairing_elapsed);
   Serial.print("WiFi: ");
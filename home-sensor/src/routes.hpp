@@ -5,4 +5,5 @@
 #include "routes/sensor.hpp"
 #include "routes/pair.hpp"
 #include "routes/dht.hpp"
-#include "routes/led.hpp"
\ No newline at end of file
+#include "routes/led.hpp"
+#include "routes/motion.hpp"
\ No newline at end of file
@@ -0,0 +1,73 @@
+#pragma once
+
+#include <ArduinoJson.h>
+
+#include "route.hpp"
+#include "../services.hpp"
+
+class MotionRoute : public Route
+{
+private:
+    MotionService *motion_service;
+    PairingService *pairing_service;
+    JsonDocument event_as_json(MotionEvent *e)
+    {
+        JsonDocument json;
+        json["start"] = e->start;
+        if (e->end != 0)
+        {
+            json["end"] = e->end;
+        }
+        return json;
+    }
+
+public:
+    MotionRoute(MotionService *m, PairingService *p) : Route("/motion", "GET")
+    {
+        motion_service = m;
+        pairing_service = p;
+    }
+
+    void write_response(NetworkClient *client, Request *req) override
+    {
+        JsonDocument json;
+        JsonDocument arr;
+        if (!pairing_service->is_paired(req))
+        {
+            json["error"] = PairingService::ERROR_MESSAGE;
+            write_json(client, json, sUNAUTHORIZED);
+            return;
+        }
+        // events that started after `after`, newest first
+        time_t after = 0;
+        size_t count = MOTION_STORAGE_ENTRIES;
+        JsonDocument req_json;
+        DeserializationError err = deserializeJson(req_json, req->body, 1024);
+        if (!err)
+        {
+            if (req_json.containsKey("after"))
+            {
+                after = req_json["after"];
+            }
+            if (req_json.containsKey("count"))
+            {
+                count = req_json["count"];
+            }
+        }
+        size_t added = 0;
+        size_t offset = motion_service->last_event_idx + MOTION_STORAGE_ENTRIES;
+        for (size_t i = 0; i < MOTION_STORAGE_ENTRIES && added < count; i++)
+        {
+            MotionEvent *e = &motion_service->events[(offset - i) % MOTION_STORAGE_ENTRIES];
+            if (e->start == 0 || e->start <= after)
+            {
+                break;
+            }
+            arr.add(event_as_json(e));
+            added++;
+        }
+
+        json["events"] = arr;
+        write_json(client, json);
+    }
+};
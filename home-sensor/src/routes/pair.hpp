@@ -61,4 +61,57 @@ public:
 
         write_json(client, json, status);
     }
+};
+
+class PairRevokeRoute : public Route
+{
+private:
+    PairingService *pairing_service;
+
+public:
+    PairRevokeRoute(PairingService *p) : Route("/pair/revoke", "POST")
+    {
+        pairing_service = p;
+    }
+
+    void write_response(NetworkClient *client, Request *req) override
+    {
+        JsonDocument json;
+        if (!pairing_service->unpair(req))
+        {
+            json["error"] = PairingService::ERROR_MESSAGE;
+            write_json(client, json, sUNAUTHORIZED);
+            return;
+        }
+
+        json["result"] = "success";
+        write_json(client, json);
+    }
+};
+
+class PairRotateRoute : public Route
+{
+private:
+    PairingService *pairing_service;
+
+public:
+    PairRotateRoute(PairingService *p) : Route("/pair/rotate", "POST")
+    {
+        pairing_service = p;
+    }
+
+    void write_response(NetworkClient *client, Request *req) override
+    {
+        JsonDocument json;
+        const char *id = pairing_service->rotate(req);
+        if (id == NULL)
+        {
+            json["error"] = PairingService::ERROR_MESSAGE;
+            write_json(client, json, sUNAUTHORIZED);
+            return;
+        }
+
+        json["id"] = id;
+        write_json(client, json);
+    }
 };
\ No newline at end of file
@@ -2,6 +2,7 @@
 
 #include "services/dht.hpp"
 #include "services/led.hpp"
+#include "services/motion.hpp"
 #include "services/pairing.hpp"
 #include "services/sensor.hpp"
 #include "services/server.hpp"
@@ -0,0 +1,66 @@
+#pragma once
+
+#include "service.hpp"
+
+#define MOTION_PIN 4
+#define MOTION_FEATURE 0b10
+#define MOTION_STORAGE_ENTRIES 100
+#define MOTION_HOLD_MS 30000 // keep an event open this long after the last detection
+
+// build with -DMOTION_ENABLED=1 for sensors with a PIR wired to MOTION_PIN
+#ifndef MOTION_ENABLED
+#define MOTION_ENABLED 0
+#endif
+
+struct MotionEvent
+{
+    time_t start;
+    time_t end; // 0 while the motion is ongoing
+};
+
+class MotionService : public ServiceBase
+{
+private:
+    ulong last_detection_ms = 0;
+
+protected:
+    void handle_inner(ulong *start_ms) override
+    {
+        bool detected = digitalRead(MOTION_PIN) == HIGH;
+        time_t now;
+        time(&now);
+        if (detected)
+        {
+            last_detection_ms = *start_ms;
+            if (!active)
+            {
+                active = true;
+                last_event_idx++;
+                if (last_event_idx >= MOTION_STORAGE_ENTRIES)
+                {
+                    last_event_idx = 0;
+                }
+                events[last_event_idx] = {now, 0};
+                Serial.println("Motion started");
+            }
+            return;
+        }
+
+        if (active && *start_ms - last_detection_ms > MOTION_HOLD_MS)
+        {
+            active = false;
+            events[last_event_idx].end = now;
+            Serial.println("Motion ended");
+        }
+    }
+
+public:
+    bool active = false;
+    size_t last_event_idx = 0;
+    MotionEvent events[MOTION_STORAGE_ENTRIES] = {};
+    MotionService() {}
+    void init() override
+    {
+        pinMode(MOTION_PIN, INPUT);
+    }
+};
@@ -20,6 +20,16 @@ private:
     PairStore *temp_pair_store = new PairStore();
     UUID next_id;
 
+    void add_key(const char *key)
+    {
+        if (store->keys[store->count] == NULL)
+        {
+            store->keys[store->count] = new char[64];
+        }
+        strcpy(store->keys[store->count], key);
+        store->count++;
+    }
+
 protected:
     void handle_inner(ulong* start_ms) override
     {
@@ -79,17 +89,44 @@ public:
             return false;
         }
 
-        if (store->keys[store->count] == NULL)
+        add_key(key);
+        store->as_json();
+        store->save(prefs);
+        return true;
+    }
+
+    bool unpair(Request *req)
+    {
+        char key[64] = {0};
+        get_header_value(req, "X-Pair-Id", key);
+        if (!store->remove_key(key))
         {
-            store->keys[store->count] = new char[64];
+            return false;
         }
-        strcpy(store->keys[store->count], key);
-        store->count++;
+
         store->as_json();
         store->save(prefs);
         return true;
     }
 
+    // Replaces the key of a paired client with a freshly generated one
+    const char *rotate(Request *req)
+    {
+        char key[64] = {0};
+        get_header_value(req, "X-Pair-Id", key);
+        if (!store->remove_key(key))
+        {
+            return NULL;
+        }
+
+        next_id.generate();
+        const char *id = next_id.toCharArray();
+        add_key(id);
+        store->as_json();
+        store->save(prefs);
+        return id;
+    }
+
     void init() override
     {
         pinMode(PAIR_BUTTON_PIN, INPUT);
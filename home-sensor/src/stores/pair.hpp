@@ -25,6 +25,25 @@ public:
         return false;
     }
 
+    bool remove_key(char *key)
+    {
+        for (size_t i = 0; i < count; i++)
+        {
+            if (keys[i] != NULL && strcmp(keys[i], key) == 0)
+            {
+                // move the last key into the freed slot
+                count--;
+                if (i != count)
+                {
+                    strcpy(keys[i], keys[count]);
+                }
+                keys[count][0] = '\0';
+                return true;
+            }
+        }
+        return false;
+    }
+
     void init_json() override
     {
         JsonArray json_keys = doc["keys"];
@@ -50,11 +69,11 @@ public:
     {
         JsonDocument new_doc;
         JsonDocument arr_doc;
-        for (char *key : keys)
+        for (size_t i = 0; i < count; i++)
         {
-            if (key != NULL)
+            if (keys[i] != NULL)
             {
-                arr_doc.add(key);
+                arr_doc.add(keys[i]);
             }
         }
         new_doc["keys"] = arr_doc;